@@ -7,17 +7,112 @@ const v = require("node-bindgen-loader")({
   dir: __dirname,
 });
 
-const stringify = (msg) => JSON.stringify(msg, null, 2);
+// `null` and `undefined` are passed through, so that they are reported by index
+// when given in place of a message
+const stringify = (msg) => (msg == null ? msg : JSON.stringify(msg, null, 2));
 
-const verifySignatures = (hmacKey, msgs, cb) => {
+// messages may also be given as buffers (eg. a `Buffer` or `Uint8Array` read
+// from a log file) holding the JSON encoding of the message value, which are
+// passed on as-is to avoid a round-trip through a string
+const encode = (msg) => {
+  if (ArrayBuffer.isView(msg)) return msg;
+  if (msg instanceof ArrayBuffer) return Buffer.from(msg);
+  return stringify(msg);
+};
+
+// convert `null` and `undefined` (or any other falsy key) to a string ("none")
+// for easier matching in rustland. a key handle (see `registerHmacKey`) of 0 is
+// passed on as is
+const nativeHmacKey = (hmacKey) =>
+  hmacKey || hmacKey === 0 ? hmacKey : "none";
+
+// the limit set with `setMaxBatchSize`, also checked here so that oversized
+// batches are rejected before their messages are encoded
+let batchSizeLimit = Infinity;
+
+// convert an options object for rustland: `revokedKeys` may be given as an
+// object mapping feed ids to revocation sequence numbers, which is passed on as
+// an array of `[feedId, sequence]` pairs, and `knownMessages` as an object
+// mapping keys to `{ sequence, previous }`, which is passed on as an array of
+// `[key, sequence, previous]` triples (with `previous` "none" if `null`)
+const nativeOpts = (opts) => {
+  if (!opts) return {};
+  const converted = {};
+  if (opts.revokedKeys && !Array.isArray(opts.revokedKeys)) {
+    converted.revokedKeys = Object.entries(opts.revokedKeys);
+  }
+  if (opts.knownMessages && !Array.isArray(opts.knownMessages)) {
+    converted.knownMessages = Object.entries(opts.knownMessages).map(
+      ([key, { sequence, previous }]) => [key, sequence, previous || "none"]
+    );
+  }
+  return Object.assign({}, opts, converted);
+};
+
+// whether `previous` is given as `{ key, sequence }` rather than as a message
+const isLink = (previous) =>
+  !!previous &&
+  typeof previous.key === "string" &&
+  typeof previous.sequence === "number" &&
+  previous.signature === undefined;
+
+// the stable number of each error code (eg. `INVALID_SIGNATURE` is 100)
+const errorCodes = Object.freeze(Object.fromEntries(v.errorCodes()));
+
+// wrap an error message from rustland in an `Error`, with `err.code` holding a
+// stable, machine-readable code for the cause of the error (eg.
+// `"INVALID_SIGNATURE"` or `"FORKED_FEED"`), `err.errorCode` the number of
+// that code and `err.offendingMessage` the message at fault (as given), if any
+const nativeError = (err, code, offendingMessage) => {
+  const error = new Error(err);
+  if (code) error.code = code;
+  if (code in errorCodes) error.errorCode = errorCodes[code];
+  if (offendingMessage !== undefined) {
+    error.offendingMessage = offendingMessage;
+  }
+  return error;
+};
+
+// `nativeError` for the `{ code, index }` failure details of a batch, which
+// also sets `err.index` if a single message is at fault
+const batchError = (err, details, msgs) => {
+  const { code, index } = details || {};
+  if (index == null) return nativeError(err, code);
+  const error = nativeError(err, code, msgs[index]);
+  error.index = index;
+  return error;
+};
+
+// `batchError` for the additional information returned by the native
+// `validateBatch` on error
+const batchInfoError = (err, info, msgs) => {
+  const { errorCode, invalidIndex, missingRange } = info || {};
+  // zero-based index of the offending message, if a single message is at fault
+  const error = batchError(err, { code: errorCode, index: invalidIndex }, msgs);
+  // `[first, last]` sequence numbers missing from the batch, if that was the cause
+  if (missingRange) error.missingRange = missingRange;
+  return error;
+};
+
+const verifySignatures = (hmacKey, msgs, opts, cb) => {
+  // `opts` is optional
+  if (typeof opts === "function") {
+    cb = opts;
+    opts = {};
+  }
   if (!Array.isArray(msgs)) {
     cb(new Error("input must be an array of message objects"));
     return;
   }
-  const jsonMsgs = msgs.map(stringify);
+  const jsonMsgs = msgs.map(encode);
   // convert `null` and `undefined` to a string ("none") for easier matching in rustland
-  if (!hmacKey) hmacKey = "none";
-  const [err, result] = v.verifySignatures(hmacKey, jsonMsgs);
+  hmacKey = nativeHmacKey(hmacKey);
+  // with `opts.countInvalid`, every signature is verified and the result is of
+  // shape `{ total, invalid }` rather than the keys of the messages
+  const [err, result] =
+    opts && opts.countInvalid
+      ? v.countInvalidSignatures(hmacKey, jsonMsgs)
+      : v.verifySignatures(hmacKey, jsonMsgs, opts || {});
   if (err) {
     cb(new Error(err));
     return;
@@ -25,57 +120,646 @@ const verifySignatures = (hmacKey, msgs, cb) => {
   cb(err, result);
 };
 
-const validateSingle = (hmacKey, msg, previous, cb) => {
+// Verify the signature of every message in `msgs`, without stopping at the
+// first invalid one. The result holds one `{ index, valid, key, code, error }`
+// object per message, in order, with the `key` of each verified message
+const verifySignaturesPartial = (hmacKey, msgs, cb) => {
+  if (!Array.isArray(msgs)) {
+    cb(new Error("input must be an array of message objects"));
+    return;
+  }
+  const jsonMsgs = msgs.map(encode);
+  hmacKey = nativeHmacKey(hmacKey);
+  const [err, result] = v.verifySignaturesPartial(hmacKey, jsonMsgs);
+  if (err) {
+    cb(nativeError(err, "INVALID_HMAC_KEY"));
+    return;
+  }
+  cb(err, result);
+};
+
+// Verify and validate each of `entries`, an array of `{ msg, hmacKey }`
+// objects, with its own hmac key (`null` for none). Each message is validated
+// standalone. The result holds one `{ index, valid, key, code, error }` object
+// per entry, in order
+const validateBatchMixedHmac = (entries, opts, cb) => {
+  // `opts` is optional
+  if (typeof opts === "function") {
+    cb = opts;
+    opts = {};
+  }
+  if (!Array.isArray(entries)) {
+    cb(new Error("input must be an array of { msg, hmacKey } objects"));
+    return;
+  }
+  const jsonMsgs = entries.map((entry) => encode(entry.msg));
+  const hmacKeys = entries.map((entry) => entry.hmacKey);
+  const [err, result] = v.validateBatchMixedHmac(
+    jsonMsgs,
+    hmacKeys,
+    opts || {}
+  );
+  if (err) {
+    cb(new Error(err));
+    return;
+  }
+  cb(err, result);
+};
+
+// Compute the keys of `msgs` without verifying or validating them
+const getMessageKeys = (msgs, opts, cb) => {
+  // `opts` is optional
+  if (typeof opts === "function") {
+    cb = opts;
+    opts = {};
+  }
+  if (!Array.isArray(msgs)) {
+    cb(new Error("input must be an array of message objects"));
+    return;
+  }
+  const jsonMsgs = msgs.map(encode);
+  const [err, result] = v.getMessageKeys(jsonMsgs, opts || {});
+  if (err) {
+    cb(new Error(err));
+    return;
+  }
+  cb(err, result);
+};
+
+// Compute the key of a single trusted message without verifying or validating
+// it. The message may also be given as its JSON encoding
+const computeMessageKey = (msg) => {
+  const jsonMsg = typeof msg === "string" ? msg : stringify(msg);
+  const [err, key] = v.computeMessageKey(jsonMsg);
+  if (err) throw new Error(err);
+  return key;
+};
+
+// `computeMessageKey` for an array of messages, returning the keys in order.
+// The keys are computed in parallel by the native `getMessageKeys`; an element
+// which is not a message is reported by its index
+const computeMessageKeys = (msgs) => {
+  if (!Array.isArray(msgs)) {
+    throw new Error("input must be an array of message objects");
+  }
+  const jsonMsgs = msgs.map((msg) =>
+    typeof msg === "string" ? msg : encode(msg)
+  );
+  const [err, keys] = v.getMessageKeys(jsonMsgs, {});
+  if (err) throw new Error(err);
+  return keys;
+};
+
+// Return the author (feed id) of a message without verifying or validating it.
+// The message may also be given as its JSON encoding
+const extractAuthor = (msg) => {
+  const jsonMsg = typeof msg === "string" ? msg : stringify(msg);
+  const [err, author] = v.extractAuthor(jsonMsg);
+  if (err) throw new Error(err);
+  return author;
+};
+
+// Return the canonical encoding of a message as `{ value, signing }`: the
+// encoding of the whole message and the encoding without the `signature` field,
+// which is the data signed by the author. A message given as a string is parsed
+// as is, so that its own encoding can be compared with these. The key of the
+// message is the sha256 hash of `Buffer.from(value, "binary")`, which differs
+// from the hash of the utf8 encoding for non-ascii content
+const canonicalizeMessage = (msg) => {
+  const jsonMsg = typeof msg === "string" ? msg : stringify(msg);
+  const [err, value, signing] = v.canonicalizeMessage(jsonMsg);
+  if (err) throw new Error(err);
+  return { value, signing };
+};
+
+// Return the feed format of a message (`classic`, `bendybutt-v1` or
+// `gabbygrove-v1`), as given by its author, without verifying or validating it.
+// The message may also be given as its JSON encoding
+const detectFeedFormat = (msg) => {
+  const jsonMsg = typeof msg === "string" ? msg : stringify(msg);
+  const [err, format] = v.detectFeedFormat(jsonMsg);
+  if (err) throw new Error(err);
+  return format;
+};
+
+// Register an HMAC key, returning a handle which may be passed in place of the
+// key to any function taking an `hmacKey` (and to `verifySignatureBuffer`)
+const registerHmacKey = (hmacKey) => {
+  hmacKey = nativeHmacKey(hmacKey);
+  const [err, handle] = v.registerHmacKey(hmacKey);
+  if (err) throw new Error(err);
+  return handle;
+};
+
+// Register the HMAC key held (base64 or hex encoded) in the environment
+// variable `varName` or in the file at `path`, returning a handle as
+// `registerHmacKey` does. An empty value or `none` is an error rather than the
+// absence of a key. The key itself is read natively and never passes through js
+const loadHmacKeyFromEnv = (varName) => {
+  const [err, handle] = v.loadHmacKeyFromEnv(varName);
+  if (err) throw new Error(err);
+  return handle;
+};
+
+const loadHmacKeyFromFile = (path) => {
+  const [err, handle] = v.loadHmacKeyFromFile(path);
+  if (err) throw new Error(err);
+  return handle;
+};
+
+// Verify the signature of a single message value held in a buffer (encoded as
+// `JSON.stringify(value, null, 2)`), using a handle from `registerHmacKey`.
+// Intended for hot paths where messages are already available as buffers
+const verifySignatureBuffer = (hmacHandle, buf, cb) => {
+  if (!Buffer.isBuffer(buf)) {
+    cb(new Error("input must be a buffer"));
+    return;
+  }
+  const err = v.verifySignatureBuffer(hmacHandle, buf);
+  if (err) {
+    cb(new Error(err));
+    return;
+  }
+  cb(null);
+};
+
+// Verify a detached ed25519 `signature` of `payload` (a buffer or a string,
+// which is encoded as utf8) by `publicKey`. The key and signature are base64
+// encoded, optionally as a feed id and with the `.sig.ed25519` suffix, or
+// buffers. Returns whether the signature is valid
+const verifyDetached = (publicKey, signature, payload) => {
+  const base64 = (bytes) =>
+    ArrayBuffer.isView(bytes)
+      ? Buffer.from(bytes.buffer, bytes.byteOffset, bytes.byteLength).toString(
+          "base64"
+        )
+      : bytes;
+  const buf = typeof payload === "string" ? Buffer.from(payload) : payload;
+  if (!Buffer.isBuffer(buf)) {
+    throw new Error("payload must be a buffer or string");
+  }
+  const [err, valid] = v.verifyDetached(
+    base64(publicKey),
+    base64(signature),
+    buf
+  );
+  if (err) throw new Error(err);
+  return valid;
+};
+
+// Verify only the signature of a single message, calling back with its key.
+// Unlike `validateSingle`, the message is not validated against its previous
+// message, so this suits messages whose hash chain is already known to be valid
+const verifySingleSignature = (hmacKey, msg, cb) => {
+  hmacKey = nativeHmacKey(hmacKey);
+  const [err, key, code] = v.verifySingleSignature(hmacKey, stringify(msg));
+  if (err) {
+    const offending = code === "INVALID_HMAC_KEY" ? undefined : msg;
+    cb(nativeError(err, code, offending));
+    return;
+  }
+  cb(null, key);
+};
+
+// Verify the signature of `msg` under each of `hmacKeys` in turn (`null` for
+// keyless verification). The result is the index of the first key under which
+// the signature is valid
+const verifySingleMultiKey = (msg, hmacKeys, cb) => {
+  if (!Array.isArray(hmacKeys)) {
+    cb(new Error("hmac keys must be an array"));
+    return;
+  }
+  const [err, index] = v.verifySingleMultiKey(stringify(msg), hmacKeys);
+  if (err) {
+    cb(new Error(err));
+    return;
+  }
+  cb(null, index);
+};
+
+// Check only that `msg` links to `previous` (by sequence number and key),
+// without verifying the signature of either, calling back with the key of `msg`
+const validateLink = (previous, msg, cb) => {
+  const [err, key, code] = v.validateLink(stringify(previous), stringify(msg));
+  if (err) {
+    cb(nativeError(err, code, msg));
+    return;
+  }
+  cb(null, key);
+};
+
+// Check that `msg` holds the required message fields with the expected types,
+// without any cryptographic work. `msg` may also be a raw JSON string. On
+// failure, `err.field` holds the name of the offending field (if any)
+const validateStructure = (msg, cb) => {
+  const jsonMsg = typeof msg === "string" ? msg : stringify(msg);
+  const [err, field] = v.validateStructure(jsonMsg);
+  if (err) {
+    const error = new Error(err);
+    if (field) error.field = field;
+    cb(error);
+    return;
+  }
+  cb(null);
+};
+
+// Verify and validate a single message. `opts.keyFormat` selects the format of
+// the key (`legacy` or `uri`)
+const validateSingle = (hmacKey, msg, previous, opts, cb) => {
+  // `opts` is optional
+  if (typeof opts === "function") {
+    cb = opts;
+    opts = {};
+  }
   const jsonMsg = stringify(msg);
   // convert `null` and `undefined` to a string ("none") for easier matching in rustland
-  if (!hmacKey) hmacKey = "none";
+  hmacKey = nativeHmacKey(hmacKey);
   let err;
   let result;
+  let code;
   if (previous) {
     const jsonPrevious = stringify(previous);
     // `result` is a string of the hash (`key`) for the given `jsonMsg` value
-    [err, result] = v.validateSingle(hmacKey, jsonMsg, jsonPrevious);
+    [err, result, code] = v.validateSingle(
+      hmacKey,
+      jsonMsg,
+      opts || {},
+      jsonPrevious
+    );
   } else {
-    [err, result] = v.validateSingle(hmacKey, jsonMsg);
+    [err, result, code] = v.validateSingle(hmacKey, jsonMsg, opts || {});
   }
   if (err) {
-    cb(new Error(err));
+    const offending = code === "INVALID_HMAC_KEY" ? undefined : msg;
+    cb(nativeError(err, code, offending));
     return;
   }
   cb(err, result);
 };
 
-const validateBatch = (hmacKey, msgs, previous, cb) => {
+// `validateSingle` calling back with `{ key, author, sequence, previous }`
+// rather than just the key
+const validateSingleWithMeta = (hmacKey, msg, previous, cb) => {
+  hmacKey = nativeHmacKey(hmacKey);
+  const args = [hmacKey, stringify(msg)];
+  if (previous) args.push(stringify(previous));
+  const [err, result, code] = v.validateSingleWithMeta(...args);
+  if (err) {
+    const offending = code === "INVALID_HMAC_KEY" ? undefined : msg;
+    cb(nativeError(err, code, offending));
+    return;
+  }
+  cb(null, result);
+};
+
+// `validateSingle` returning a promise. The message is verified and validated
+// on a background thread, so the event loop is not blocked
+const validateSingleAsync = (hmacKey, msg, previous, opts = {}) =>
+  new Promise((resolve, reject) => {
+    v.validateSingleBackground(
+      nativeHmacKey(hmacKey),
+      stringify(msg),
+      opts,
+      previous ? stringify(previous) : "none",
+      (err, result, code) => {
+        if (err) {
+          const offending = code === "INVALID_HMAC_KEY" ? undefined : msg;
+          reject(nativeError(err, code, offending));
+          return;
+        }
+        resolve(result);
+      }
+    );
+  });
+
+// Verify and validate an ordered array of messages by a single author. The
+// phases to run are selected via `opts.verify`, `opts.chain` and `opts.hash`
+// (all `true` by default); see the native `validateBatch` for all options.
+// With `opts.trustedPrefixLen` (N), the first N messages are known to be valid:
+// they are skipped and message N-1 is used as the previous message of the
+// rest. The keys (and any indices in `info`) then refer to the remaining
+// messages only. Instead of the full previous message, `previous` may be given
+// as `{ key, sequence }` of that message; the last returned key and the
+// sequence of the last message then serve as `previous` for the next batch
+const validate = (hmacKey, msgs, previous, opts, cb) => {
+  // `opts` is optional
+  if (typeof opts === "function") {
+    cb = opts;
+    opts = {};
+  }
+  opts = nativeOpts(opts);
   if (!Array.isArray(msgs)) {
     cb(new Error("input must be an array of message objects"));
     return;
   }
-  const jsonMsgs = msgs.map(stringify);
-  if (!hmacKey) hmacKey = "none";
+  if (msgs.length > batchSizeLimit) {
+    cb(new Error(`batch too large: ${msgs.length} > ${batchSizeLimit}`));
+    return;
+  }
+  const trusted = (opts && opts.trustedPrefixLen) || 0;
+  if (trusted > msgs.length) {
+    cb(new Error("trusted prefix is longer than the batch"));
+    return;
+  }
+  if (trusted > 0) {
+    previous = msgs[trusted - 1];
+    msgs = msgs.slice(trusted);
+  }
+  if (isLink(previous)) {
+    const { key, sequence } = previous;
+    opts = Object.assign({}, opts, {
+      previousKey: key,
+      previousSequence: sequence,
+    });
+    previous = null;
+  }
+  // with `opts.hardened`, panics on malformed input are returned as errors and
+  // messages may also be given as raw JSON strings (as received from a peer)
+  const hardened = opts && opts.hardened;
+  const toJson = (msg) =>
+    hardened && typeof msg === "string" ? msg : encode(msg);
+  let jsonMsgs;
+  let jsonPrevious;
+  try {
+    jsonMsgs = msgs.map(toJson);
+    jsonPrevious = toJson(previous);
+  } catch (err) {
+    // eg. a stack overflow on pathologically nested objects
+    cb(err);
+    return;
+  }
+  hmacKey = nativeHmacKey(hmacKey);
+  if (opts && opts.envelope) {
+    const args = [hmacKey, jsonMsgs, opts];
+    if (previous) args.push(jsonPrevious);
+    cb(null, v.validateBatchEnvelope(...args));
+    return;
+  }
+  const validateNative = hardened ? v.validateBatchHardened : v.validateBatch;
   let err;
   let result;
+  let info;
   if (previous) {
-    const jsonPrevious = stringify(previous);
     // `result` is an array of strings (each string a `key`) for the given `jsonMsgs`
-    [err, result] = v.validateBatch(hmacKey, jsonMsgs, jsonPrevious);
+    [err, result, info] = validateNative(
+      hmacKey,
+      jsonMsgs,
+      opts || {},
+      jsonPrevious
+    );
+  } else {
+    [err, result, info] = validateNative(hmacKey, jsonMsgs, opts || {});
+  }
+  if (err) {
+    cb(batchInfoError(err, info, msgs));
+    return;
+  }
+  // `result` is `null` if the `hash` phase was skipped and `info` is only set
+  // when additional checks are requested via `opts`
+  cb(err, result, info);
+};
+
+// `validate` with all phases enabled (except signature verification, with
+// `opts.skipVerify`)
+const validateBatch = (hmacKey, msgs, previous, opts, cb) => {
+  // `opts` is optional
+  if (typeof opts === "function") {
+    cb = opts;
+    opts = {};
+  }
+  if (opts && opts.timeoutMs) {
+    validateBatchWithTimeout(hmacKey, msgs, previous, opts, cb);
+    return;
+  }
+  const allPhases = { verify: true, chain: true, hash: true };
+  validate(hmacKey, msgs, previous, Object.assign({}, opts, allPhases), cb);
+};
+
+// whether the messages validated with `opts` may become a tip, which requires
+// their signatures to be verified and their hash chain to be checked
+const recordsTip = (opts) =>
+  opts.verify !== false && opts.chain !== false && !opts.skipVerify;
+
+// whether `opts` holds a previous link, which takes the place of a cached tip
+const isLinked = (opts) =>
+  opts.previousKey != null || opts.previousSequence != null;
+
+// the validated message with the highest sequence number, which becomes the
+// tip of its author: with `opts.sortBySequence` this need not be the last
+// message and with `opts.reportGaps` only the messages preceding the first gap
+// (in sequence order) are validated
+const validatedTip = (msgs, opts, info) => {
+  let validated = msgs;
+  if (opts && opts.sortBySequence) {
+    validated = msgs.slice().sort((a, b) => a.sequence - b.sequence);
+  }
+  const gaps = info && info.gaps;
+  if (gaps && gaps.length) validated = validated.slice(0, gaps[0]);
+  return validated.reduce(
+    (tip, msg) => (tip && tip.sequence >= msg.sequence ? tip : msg),
+    null
+  );
+};
+
+// A long-lived validator for the live-replication loop, which caches the last
+// validated message (tip) of each author. `validateBatch` chains a batch onto
+// the cached tip of its author, so `previous` is only needed for the first
+// batch of a feed; an explicit `previous` (including `null`) overrides the
+// cache. `hmacKey` and `opts` apply to every batch. `pushBatch` also keeps the
+// tips in rustland, so that they are not passed through js again for each batch
+class Validator {
+  constructor(hmacKey, opts) {
+    this.hmacKey = hmacKey;
+    this.opts = opts || {};
+    this.tips = new Map();
+    // the native validator, created on the first `pushBatch`
+    this.native = null;
+    // authors whose cached tip is also held by the native validator
+    this.synced = new Set();
+  }
+
+  validateBatch(msgs, previous, cb) {
+    // `previous` is optional
+    if (typeof previous === "function") {
+      cb = previous;
+      previous = undefined;
+    }
+    const last = Array.isArray(msgs) ? msgs[msgs.length - 1] : undefined;
+    const author = last && last.author;
+    if (previous === undefined && !isLinked(this.opts)) {
+      previous = this.tips.get(author) || null;
+    }
+    validateBatch(this.hmacKey, msgs, previous, this.opts, (err, ...res) => {
+      const tracked = !err && recordsTip(this.opts);
+      const tip = tracked ? validatedTip(msgs, this.opts, res[1]) : null;
+      if (author && tip) {
+        this.tips.set(author, tip);
+        this.synced.delete(author);
+      }
+      cb(err, ...res);
+    });
+  }
+
+  // `validateBatch` chained onto the tip held by the native validator, which
+  // supports the options of the native `validateBatch`. `opts` is optional and
+  // applies to this batch only, on top of the options of the validator (eg. a
+  // previous link, via `previousKey` and `previousSequence`, which takes the
+  // place of the tip)
+  pushBatch(msgs, opts, cb) {
+    if (typeof opts === "function") {
+      cb = opts;
+      opts = {};
+    }
+    if (!Array.isArray(msgs)) {
+      cb(new Error("input must be an array of message objects"));
+      return;
+    }
+    if (this.native === null) this.native = new v.Validator();
+    const last = msgs[msgs.length - 1];
+    const author = last && last.author;
+    opts = nativeOpts(Object.assign({}, this.opts, opts));
+    const args = [this.hmacKey, msgs.map(encode), opts];
+    // a tip cached by `validateBatch` is passed on once
+    const previous = this.tips.get(author);
+    if (previous && !isLinked(opts) && !this.synced.has(author)) {
+      args.push(stringify(previous));
+    }
+    const [err, keys, info] = this.native.pushBatch(...args);
+    if (err) {
+      cb(batchInfoError(err, info, msgs));
+      return;
+    }
+    const tip = recordsTip(opts) ? validatedTip(msgs, opts, info) : null;
+    if (author && tip) {
+      this.tips.set(author, tip);
+      this.synced.add(author);
+    }
+    cb(err, keys, info);
+  }
+
+  // the cached tip (message value) of `author`, if any
+  tip(author) {
+    return this.tips.get(author) || null;
+  }
+
+  // forget the cached tip of `author`, or of all authors
+  reset(author) {
+    if (author === undefined) {
+      this.tips.clear();
+      this.synced.clear();
+    } else {
+      this.tips.delete(author);
+      this.synced.delete(author);
+    }
+    if (this.native === null) return;
+    if (author === undefined) this.native.reset();
+    else this.native.reset(author);
+  }
+
+  // release the native validator; the cached tips are kept
+  close() {
+    this.native = null;
+    this.synced.clear();
+  }
+}
+
+// shared implementation of `validateBatchSettled` and `validateBatchVerbose`
+const validateAll = (validateNative, hmacKey, msgs, previous, opts, cb) => {
+  // `opts` is optional
+  if (typeof opts === "function") {
+    cb = opts;
+    opts = {};
+  }
+  if (!Array.isArray(msgs)) {
+    cb(new Error("input must be an array of message objects"));
+    return;
+  }
+  const jsonMsgs = msgs.map(encode);
+  hmacKey = nativeHmacKey(hmacKey);
+  let err;
+  let result;
+  if (previous) {
+    const jsonPrevious = stringify(previous);
+    [err, result] = validateNative(
+      hmacKey,
+      jsonMsgs,
+      opts || {},
+      jsonPrevious
+    );
   } else {
-    [err, result] = v.validateBatch(hmacKey, jsonMsgs);
+    [err, result] = validateNative(hmacKey, jsonMsgs, opts || {});
   }
   if (err) {
     cb(new Error(err));
     return;
   }
+  if (result.mask) result.mask = new Uint8Array(result.mask);
   cb(err, result);
 };
 
-const validateOOOBatch = (hmacKey, msgs, cb) => {
+// Validate every message in `msgs`, even if some are invalid. The result is of
+// shape `{ keys, errors }`, where `keys` are the keys of all valid messages (in
+// order) and `errors` is an array of `{ index, code, reason }` objects. With
+// `opts.mask`, the result also holds a `mask` (a `Uint8Array` holding 1 for
+// each valid message and 0 for each invalid message)
+const validateBatchSettled = (hmacKey, msgs, previous, opts, cb) =>
+  validateAll(v.validateBatchSettled, hmacKey, msgs, previous, opts, cb);
+
+// Like `validateBatchSettled`, but each message is checked against the message
+// preceding it in `msgs` (valid or not), so that every corrupt message is
+// reported on its own. The keys of the valid messages are returned as well
+const validateBatchVerbose = (hmacKey, msgs, previous, opts, cb) =>
+  validateAll(v.validateBatchVerbose, hmacKey, msgs, previous, opts, cb);
+
+// Validate every message in `msgs` on a background thread, calling
+// `onResult(err, key, index)` as soon as each message has been validated.
+// `cb` is called once all messages have been processed, with an error only if
+// the batch could not be processed at all (eg. an invalid hmac key)
+const validateEach = (hmacKey, msgs, previous, opts, onResult, cb) => {
+  // `opts` is optional
+  if (typeof opts === "function") {
+    cb = onResult;
+    onResult = opts;
+    opts = {};
+  }
   if (!Array.isArray(msgs)) {
     cb(new Error("input must be an array of message objects"));
     return;
   }
-  const jsonMsgs = msgs.map(stringify);
-  if (!hmacKey) hmacKey = "none";
-  const [err, result] = v.validateOOOBatch(hmacKey, jsonMsgs);
+  const jsonMsgs = msgs.map(encode);
+  hmacKey = nativeHmacKey(hmacKey);
+  const jsonPrevious = previous ? stringify(previous) : "none";
+  v.validateEach(
+    hmacKey,
+    jsonMsgs,
+    jsonPrevious,
+    opts || {},
+    (index, err, key) => {
+      if (index < 0) {
+        cb(err ? new Error(err) : null);
+        return;
+      }
+      onResult(err ? new Error(err) : null, key, index);
+    }
+  );
+};
+
+// Find the indices of the messages in `msgs` which fail verification or
+// validation, each message being checked against its predecessor in `msgs`
+const findInvalid = (hmacKey, msgs, previous, cb) => {
+  if (!Array.isArray(msgs)) {
+    cb(new Error("input must be an array of message objects"));
+    return;
+  }
+  const jsonMsgs = msgs.map(encode);
+  hmacKey = nativeHmacKey(hmacKey);
+  let err;
+  let result;
+  if (previous) {
+    [err, result] = v.findInvalid(hmacKey, jsonMsgs, stringify(previous));
+  } else {
+    [err, result] = v.findInvalid(hmacKey, jsonMsgs);
+  }
   if (err) {
     cb(new Error(err));
     return;
@@ -83,14 +767,23 @@ const validateOOOBatch = (hmacKey, msgs, cb) => {
   cb(err, result);
 };
 
-const validateMultiAuthorBatch = (hmacKey, msgs, cb) => {
+// Classify `msgs` as a contiguous, hash-chained segment of a single feed (or
+// not) as `{ isContiguousFeed, author, startSeq, endSeq }`, without full
+// validation. Signatures are only verified with `opts.verify`
+const classifyBatch = (hmacKey, msgs, opts, cb) => {
+  // `opts` is optional
+  if (typeof opts === "function") {
+    cb = opts;
+    opts = {};
+  }
   if (!Array.isArray(msgs)) {
     cb(new Error("input must be an array of message objects"));
     return;
   }
-  const jsonMsgs = msgs.map(stringify);
-  if (!hmacKey) hmacKey = "none";
-  const [err, result] = v.validateMultiAuthorBatch(hmacKey, jsonMsgs);
+  const jsonMsgs = msgs.map(encode);
+  hmacKey = nativeHmacKey(hmacKey);
+  const verify = Boolean(opts && opts.verify);
+  const [err, result] = v.classifyBatch(hmacKey, jsonMsgs, verify);
   if (err) {
     cb(new Error(err));
     return;
@@ -98,6 +791,421 @@ const validateMultiAuthorBatch = (hmacKey, msgs, cb) => {
   cb(err, result);
 };
 
+// Default number of messages validated per chunk by `validateBatchAsync`.
+// Control is returned to the event loop between chunks, which is when a
+// cancellation request (via an `AbortSignal`) is observed.
+const ASYNC_CHUNK_SIZE = 250;
+
+// options which apply to a batch as a whole, rather than to each of its chunks
+const UNCHUNKABLE_OPTS = [
+  "sortBySequence",
+  "reportGaps",
+  "trustedPrefixLen",
+  "envelope",
+];
+
+// the options for a chunk of a batch validated in chunks, which are those of
+// the batch without the ones controlling the chunking. the previous link only
+// applies to the first chunk, which is the one at `index` 0
+const chunkOptions = (opts, index) => {
+  const chunkOpts = Object.assign({}, opts);
+  delete chunkOpts.timeoutMs;
+  delete chunkOpts.chunkSize;
+  delete chunkOpts.signal;
+  if (index > 0) {
+    delete chunkOpts.previousKey;
+    delete chunkOpts.previousSequence;
+  }
+  return chunkOpts;
+};
+
+// an error if any of the options cannot be applied to a batch validated in chunks
+const unchunkableError = (opts) => {
+  const name = UNCHUNKABLE_OPTS.find((name) => opts[name]);
+  if (name) return new Error(`${name} cannot be combined with chunking`);
+};
+
+// `validateBatch` with a time limit of `opts.timeoutMs`, which is checked
+// between chunks of messages. If the limit is reached, the keys of the messages
+// validated so far are returned along with `{ timeout: true, index }`, where
+// `index` is the index of the first message which was not validated. All other
+// options apply to each chunk, except those in `UNCHUNKABLE_OPTS`
+const validateBatchWithTimeout = (hmacKey, msgs, previous, opts, cb) => {
+  if (!Array.isArray(msgs)) {
+    cb(new Error("input must be an array of message objects"));
+    return;
+  }
+  const optsErr = unchunkableError(opts);
+  if (optsErr) {
+    cb(optsErr);
+    return;
+  }
+  const deadline = Date.now() + opts.timeoutMs;
+  const chunkSize = opts.chunkSize || ASYNC_CHUNK_SIZE;
+  const keys = [];
+  let index = 0;
+  while (index < msgs.length) {
+    if (Date.now() >= deadline) {
+      cb(null, keys, { timeout: true, index });
+      return;
+    }
+    const chunk = msgs.slice(index, index + chunkSize);
+    // the last message of the previous chunk becomes `previous` for this one
+    const chunkPrevious = index === 0 ? previous : msgs[index - 1];
+    const chunkOpts = chunkOptions(opts, index);
+    let chunkErr;
+    validateBatch(hmacKey, chunk, chunkPrevious, chunkOpts, (err, res) => {
+      if (err) {
+        chunkErr = err;
+        return;
+      }
+      keys.push(...res);
+    });
+    if (chunkErr) {
+      // the index of the offending message within the whole batch
+      if (chunkErr.index != null) chunkErr.index += index;
+      chunkErr.keys = keys;
+      cb(chunkErr);
+      return;
+    }
+    index += chunk.length;
+  }
+  cb(null, keys);
+};
+
+// `validateBatch` returning a promise. Each chunk of messages is verified and
+// validated on a background thread, so the event loop is not blocked even for
+// large batches. All options apply to each chunk, except those in
+// `UNCHUNKABLE_OPTS`
+const validateBatchAsync = (hmacKey, msgs, previous, opts = {}) =>
+  new Promise((resolve, reject) => {
+    if (!Array.isArray(msgs)) {
+      reject(new Error("input must be an array of message objects"));
+      return;
+    }
+    const optsErr = unchunkableError(opts);
+    if (optsErr) {
+      reject(optsErr);
+      return;
+    }
+    opts = nativeOpts(opts);
+    if (isLink(previous)) {
+      const { key, sequence } = previous;
+      opts = Object.assign({}, opts, {
+        previousKey: key,
+        previousSequence: sequence,
+      });
+      previous = null;
+    }
+    const signal = opts.signal;
+    const chunkSize = opts.chunkSize || ASYNC_CHUNK_SIZE;
+    // keys of the messages validated so far; attached to the rejection error so
+    // that the caller can resume from `keys.length`
+    const keys = [];
+    let index = 0;
+    const next = () => {
+      if (signal && signal.aborted) {
+        const err = new Error("cancelled");
+        err.keys = keys;
+        reject(err);
+        return;
+      }
+      if (index >= msgs.length) {
+        resolve(keys);
+        return;
+      }
+      const chunk = msgs.slice(index, index + chunkSize);
+      // the last message of the previous chunk becomes `previous` for this one
+      const chunkPrevious = index === 0 ? previous : msgs[index - 1];
+      const chunkOpts = chunkOptions(opts, index);
+      v.validateBatchBackground(
+        nativeHmacKey(hmacKey),
+        chunk.map(encode),
+        chunkOpts,
+        chunkPrevious ? stringify(chunkPrevious) : "none",
+        (err, res, info) => {
+          if (err) {
+            const error = batchInfoError(err, info, chunk);
+            // the index of the offending message within the whole batch
+            if (error.index != null) error.index += index;
+            error.keys = keys;
+            reject(error);
+            return;
+          }
+          keys.push(...res);
+          index += chunk.length;
+          next();
+        }
+      );
+    };
+    next();
+  });
+
+const validateWithContent = (hmacKey, msg, content, previous, cb) => {
+  const jsonMsg = stringify(msg);
+  hmacKey = nativeHmacKey(hmacKey);
+  // off-chain content may be supplied as a string or a buffer
+  if (typeof content === "string") content = Buffer.from(content);
+  if (!Buffer.isBuffer(content)) {
+    cb(new Error("content must be a string or buffer"));
+    return;
+  }
+  let err;
+  let result;
+  if (previous) {
+    const jsonPrevious = stringify(previous);
+    [err, result] = v.validateWithContent(
+      hmacKey,
+      jsonMsg,
+      content,
+      jsonPrevious
+    );
+  } else {
+    [err, result] = v.validateWithContent(hmacKey, jsonMsg, content);
+  }
+  if (err) {
+    cb(new Error(err));
+    return;
+  }
+  cb(err, result);
+};
+
+// Validate each message in `msgs` against the corresponding entry in
+// `previous`. Messages with a `null` previous are validated standalone
+const validateWithPrevious = (hmacKey, msgs, previous, opts, cb) => {
+  // `opts` is optional
+  if (typeof opts === "function") {
+    cb = opts;
+    opts = {};
+  }
+  if (!Array.isArray(msgs) || !Array.isArray(previous)) {
+    cb(new Error("input must be an array of message objects"));
+    return;
+  }
+  const jsonMsgs = msgs.map(encode);
+  // convert `null` and `undefined` to a string ("none") for easier matching in rustland
+  const jsonPrevious = previous.map((msg) => (msg ? stringify(msg) : "none"));
+  hmacKey = nativeHmacKey(hmacKey);
+  const [err, result] = v.validateWithPrevious(
+    hmacKey,
+    jsonMsgs,
+    jsonPrevious,
+    opts || {}
+  );
+  if (err) {
+    cb(new Error(err));
+    return;
+  }
+  cb(err, result);
+};
+
+const validateOOOBatch = (hmacKey, msgs, opts, cb) => {
+  // `opts` is optional
+  if (typeof opts === "function") {
+    cb = opts;
+    opts = {};
+  }
+  opts = nativeOpts(opts);
+  if (!Array.isArray(msgs)) {
+    cb(new Error("input must be an array of message objects"));
+    return;
+  }
+  const jsonMsgs = msgs.map(encode);
+  hmacKey = nativeHmacKey(hmacKey);
+  if (opts && opts.envelope) {
+    cb(null, v.validateOOOBatchEnvelope(hmacKey, jsonMsgs, opts));
+    return;
+  }
+  const [err, result, details, unlinked] = v.validateOOOBatch(
+    hmacKey,
+    jsonMsgs,
+    opts || {}
+  );
+  if (err) {
+    cb(batchError(err, details, msgs));
+    return;
+  }
+  // with `opts.knownMessages`, `unlinked` holds the indices of the messages
+  // whose previous message is neither in the batch nor known
+  if (unlinked) {
+    cb(err, result, unlinked);
+    return;
+  }
+  cb(err, result);
+};
+
+// Validate every message in `msgs` (out-of-order) and summarise the result as
+// `{ valid, invalid, reasons }`, where `reasons` maps each error code to
+// `{ count, examples }` (see the native `validateOOOBatchSummary`)
+const validateOOOBatchSummary = (hmacKey, msgs, opts, cb) => {
+  // `opts` is optional
+  if (typeof opts === "function") {
+    cb = opts;
+    opts = {};
+  }
+  if (!Array.isArray(msgs)) {
+    cb(new Error("input must be an array of message objects"));
+    return;
+  }
+  const jsonMsgs = msgs.map(encode);
+  hmacKey = nativeHmacKey(hmacKey);
+  const [err, result] = v.validateOOOBatchSummary(
+    hmacKey,
+    jsonMsgs,
+    opts || {}
+  );
+  if (err) {
+    cb(new Error(err));
+    return;
+  }
+  cb(err, result);
+};
+
+// Validate a single out-of-order message which is expected to follow the
+// message with key `previousKey` (`null` for the first message of a feed) at
+// sequence number `sequence`
+const validateOOOSingle = (hmacKey, msg, previousKey, sequence, cb) => {
+  const jsonMsg = stringify(msg);
+  hmacKey = nativeHmacKey(hmacKey);
+  const [err, result] = v.validateOOOSingle(
+    hmacKey,
+    jsonMsg,
+    previousKey || "none",
+    sequence
+  );
+  if (err) {
+    cb(new Error(err));
+    return;
+  }
+  cb(err, result);
+};
+
+// Validate a single message and check that it fits between its claimed
+// predecessor `prevValue` and successor `nextValue` (either may be `null`). If
+// a link is broken, `err.link` is `"previous"` or `"next"`
+const validateInPlace = (value, prevValue, nextValue, hmacKey, cb) => {
+  hmacKey = nativeHmacKey(hmacKey);
+  const [err, link, result] = v.validateInPlace(
+    hmacKey,
+    stringify(value),
+    prevValue ? stringify(prevValue) : "none",
+    nextValue ? stringify(nextValue) : "none"
+  );
+  if (err) {
+    const error = new Error(err);
+    if (link) error.link = link;
+    cb(error);
+    return;
+  }
+  cb(null, result);
+};
+
+const validateMultiAuthorBatch = (hmacKey, msgs, opts, cb) => {
+  // `opts` is optional
+  if (typeof opts === "function") {
+    cb = opts;
+    opts = {};
+  }
+  opts = nativeOpts(opts);
+  if (!Array.isArray(msgs)) {
+    cb(new Error("input must be an array of message objects"));
+    return;
+  }
+  const jsonMsgs = msgs.map(encode);
+  hmacKey = nativeHmacKey(hmacKey);
+  if (opts && opts.envelope) {
+    cb(null, v.validateMultiAuthorBatchEnvelope(hmacKey, jsonMsgs, opts));
+    return;
+  }
+  const [err, result, author, tips, details] = v.validateMultiAuthorBatch(
+    hmacKey,
+    jsonMsgs,
+    opts || {}
+  );
+  if (err) {
+    const error = batchError(err, details, msgs);
+    // feed id of the author of the offending message
+    if (author) error.author = author;
+    cb(error);
+    return;
+  }
+  // with `opts.feedTips`, `tips` maps each author to `{ key, sequence }` of the
+  // latest message of their feed in the batch
+  if (tips) {
+    cb(err, result, tips);
+    return;
+  }
+  cb(err, result);
+};
+
+// Decode the `ArrayBuffer` passed to the callback of `validateBatch`,
+// `validateOOOBatch` and `validateMultiAuthorBatch` with `opts.envelope` (see
+// the native `validateBatchEnvelope` for the layout). Returns the keys as an
+// array of 32-byte sha256 digests, or throws the validation error
+const decodeEnvelope = (envelope) => {
+  const buf = Buffer.from(envelope);
+  const count = buf.readUInt32LE(1);
+  if (buf[0] !== 0) throw new Error(buf.toString("utf8", 5, 5 + count));
+  const keys = new Array(count);
+  for (let i = 0; i < count; i++) {
+    keys[i] = buf.subarray(5 + i * 32, 5 + (i + 1) * 32);
+  }
+  return keys;
+};
+
+// Cap the number of threads used for parallel validation at `maxThreads`. Must
+// be called (at most once) before any messages are validated
+const setMaxThreads = (maxThreads) => {
+  const err = v.setMaxThreads(maxThreads);
+  if (err) throw new Error(err);
+};
+
+// Reject batches of more than `maxBatchSize` messages with a `batch too large`
+// error (`Infinity`, the default, removes the limit)
+const setMaxBatchSize = (maxBatchSize) => {
+  const err = v.setMaxBatchSize(maxBatchSize);
+  if (err) throw new Error(err);
+  batchSizeLimit = maxBatchSize;
+};
+
+// Keep up to `poolSize` message buffers for reuse by the next `validateBatch`
+// (`0` disables reuse), which reduces allocator churn when validating many
+// small batches
+const setBufferPoolSize = (poolSize) => {
+  const err = v.setBufferPoolSize(poolSize);
+  if (err) throw new Error(err);
+};
+
+// Recommend a number of messages per batch, based on the size of the thread pool
+// used for validation. An optional sample message (or its size in bytes) refines
+// the recommendation for feeds with unusually small or large messages
+const recommendedBatchSize = (sample) => {
+  if (sample === undefined || sample === null) return v.recommendedBatchSize();
+  const size =
+    typeof sample === "number" ? sample : Buffer.byteLength(stringify(sample));
+  return v.recommendedBatchSize(size);
+};
+
+// Return a short string identifying the validator configuration (including the
+// given `hmacKey` and `opts`). Cached validation results should be discarded
+// when the fingerprint changes
+const configFingerprint = (hmacKey, opts) => {
+  hmacKey = nativeHmacKey(hmacKey);
+  const [err, result] = v.configFingerprint(hmacKey, nativeOpts(opts));
+  if (err) throw new Error(err);
+  return result;
+};
+
+// Return `{ version, cryptoBackend, simdEnabled, simdSupported }` for the
+// native module, eg. to check whether a prebuilt binary uses SIMD verification
+const versionInfo = () => v.versionInfo();
+
+// Run a known-good validation vector through the native module and return
+// `{ passed, reason, versionInfo }`, where `versionInfo` includes the crypto
+// backend which was exercised. This is a post-install sanity check which tells
+// a module that loads but is broken apart from one that works
+const selfTest = () => v.selfTest();
+
 // Mirrors the `ready` function for the `web` version of `ssb-validate2-rsjs`.
 // The function initializes WASM and WebWorkers in `web`. We define it here with
 // a callback so that both libraries can be safely called with the same code.
@@ -106,8 +1214,49 @@ const ready = (cb) => {
 };
 
 module.exports.ready = ready;
+module.exports.errorCodes = errorCodes;
+module.exports.setMaxThreads = setMaxThreads;
+module.exports.setMaxBatchSize = setMaxBatchSize;
+module.exports.setBufferPoolSize = setBufferPoolSize;
+module.exports.recommendedBatchSize = recommendedBatchSize;
+module.exports.decodeEnvelope = decodeEnvelope;
+module.exports.configFingerprint = configFingerprint;
+module.exports.versionInfo = versionInfo;
+module.exports.selfTest = selfTest;
 module.exports.verifySignatures = verifySignatures;
+module.exports.verifySignaturesPartial = verifySignaturesPartial;
+module.exports.verifyDetached = verifyDetached;
+module.exports.validateBatchMixedHmac = validateBatchMixedHmac;
+module.exports.getMessageKeys = getMessageKeys;
+module.exports.computeMessageKey = computeMessageKey;
+module.exports.computeMessageKeys = computeMessageKeys;
+module.exports.extractAuthor = extractAuthor;
+module.exports.detectFeedFormat = detectFeedFormat;
+module.exports.canonicalizeMessage = canonicalizeMessage;
+module.exports.registerHmacKey = registerHmacKey;
+module.exports.loadHmacKeyFromEnv = loadHmacKeyFromEnv;
+module.exports.loadHmacKeyFromFile = loadHmacKeyFromFile;
+module.exports.verifySignatureBuffer = verifySignatureBuffer;
+module.exports.verifySingleSignature = verifySingleSignature;
+module.exports.verifySingleMultiKey = verifySingleMultiKey;
+module.exports.validateLink = validateLink;
+module.exports.validateStructure = validateStructure;
 module.exports.validateSingle = validateSingle;
+module.exports.validateSingleWithMeta = validateSingleWithMeta;
+module.exports.validateSingleAsync = validateSingleAsync;
+module.exports.validate = validate;
 module.exports.validateBatch = validateBatch;
+module.exports.Validator = Validator;
+module.exports.validateBatchSettled = validateBatchSettled;
+module.exports.validateBatchVerbose = validateBatchVerbose;
+module.exports.validateBatchAsync = validateBatchAsync;
+module.exports.validateEach = validateEach;
+module.exports.findInvalid = findInvalid;
+module.exports.classifyBatch = classifyBatch;
+module.exports.validateWithContent = validateWithContent;
+module.exports.validateWithPrevious = validateWithPrevious;
 module.exports.validateOOOBatch = validateOOOBatch;
+module.exports.validateOOOBatchSummary = validateOOOBatchSummary;
+module.exports.validateOOOSingle = validateOOOSingle;
+module.exports.validateInPlace = validateInPlace;
 module.exports.validateMultiAuthorBatch = validateMultiAuthorBatch;
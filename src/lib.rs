@@ -2,390 +2,5172 @@
 //
 // SPDX-License-Identifier: LGPL-3.0-only
 
-use node_bindgen::core::{buffer::JSArrayBuffer, val::JsEnv, JSValue, NjError};
+// the thread-safe callback code generated by `node_bindgen` for `mt` functions compares a raw
+// pointer with null
+#![allow(clippy::cmp_null)]
+
+use node_bindgen::core::{
+    buffer::{ArrayBuffer, JSArrayBuffer},
+    val::JsEnv,
+    val::JsObject,
+    JSValue, NjError, TryIntoJs,
+};
 use node_bindgen::derive::node_bindgen;
-use node_bindgen::sys::napi_value;
-use ssb_crypto::{AsBytes, NetworkKey as MsgHmacKey};
+use node_bindgen::sys::{napi_get_value_string_utf8, napi_status_napi_ok};
+use node_bindgen::sys::{
+    napi_value, napi_valuetype_napi_null, napi_valuetype_napi_number, napi_valuetype_napi_undefined,
+};
+use rayon::prelude::*;
+use ssb_crypto::{AsBytes, NetworkKey as MsgHmacKey, PublicKey, Signature};
+use ssb_legacy_msg_data::{
+    json::{self, from_slice},
+    value::Value,
+};
+use ssb_multiformats::{multihash::Multihash, multikey::Multikey};
 use ssb_validate::{
+    error::Error as ValidationError,
     message_value::{
         par_validate_message_value, par_validate_message_value_hash_chain_of_feed,
         par_validate_ooo_message_value_hash_chain_of_feed, validate_message_value,
-        validate_message_value_hash_chain, validate_ooo_message_value_hash_chain,
+        validate_message_value_hash_chain, validate_ooo_message_value_hash_chain, SsbMessageValue,
     },
     utils,
 };
-use ssb_verify_signatures::{par_verify_message_values, verify_message_value};
+use ssb_verify_signatures::{
+    par_verify_message_values, verify_message_value, Error as VerificationError,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::ops::{Deref, DerefMut};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::Instant;
+
+// number of messages per worker thread recommended by `recommendedBatchSize` when no sample
+// message size is given, and verified per worker thread in each chunk of `validateEach`
+const MESSAGES_PER_THREAD: usize = 250;
+// amount of message data per worker thread targeted by `recommendedBatchSize` when a sample
+// message size is given
+const BYTES_PER_THREAD: usize = 256 * 1024;
+// bounds on the number of messages per worker thread recommended by `recommendedBatchSize`
+const MIN_MESSAGES_PER_THREAD: usize = 16;
+const MAX_MESSAGES_PER_THREAD: usize = 2048;
+
+// number of example keys reported per failure reason by `validateOOOBatchSummary`
+const MAX_EXAMPLE_KEYS: usize = 5;
+
+// maximum nesting depth of message content applied by `validateBatchHardened` when the `maxDepth`
+// option is not set. the json parser is recursive, so deeply nested input could otherwise
+// overflow the stack, which aborts the process rather than panicking
+const HARDENED_MAX_DEPTH: usize = 128;
+
+// maximum length of a message value in UTF-16 code units, as enforced by `ssb-validate`
+const MAX_MESSAGE_LENGTH: usize = 8192;
+
+// curve25519-dalek backend compiled in for ed25519 signature verification. `ssb-crypto` and
+// `ssb-verify-signatures` depend on `ed25519-dalek` 1.0 with its default features, which select
+// the portable `u64_backend` of curve25519-dalek 3; neither exposes the `simd_backend` feature
+const CRYPTO_BACKEND: &str = "u64";
+
+// known-good validation vector used by `selfTest`: the first message of a feed (generated with
+// `ssb-keys` from a fixed seed) and its key
+const SELF_TEST_MESSAGE: &str = r#"{
+  "previous": null,
+  "author": "@GQISmTM73gFVUmBvUDQpIuw7F8Mo4/MaIwyXv29Lw14=.ed25519",
+  "sequence": 1,
+  "timestamp": 1600000000000,
+  "hash": "sha256",
+  "content": {
+    "type": "self-test"
+  },
+  "signature": "Rv6E8OeECdaPV+fA6d8E931zQCWbGUJ7+RFI5iHxS6Xn6AIy/p8MmNP968HbJzo/c2czlwUYhQRLuLhi6p11BA==.sig.ed25519"
+}"#;
+const SELF_TEST_KEY: &str = "%Ezm/IbRWejmahZwp21gj0H13ILT3FThqlgFfyvwTWQU=.sha256";
+
+// network key (aka caps key) of the main Scuttlebutt network. it is used for the
+// secret-handshake between peers and is not a message-signing hmac key
+const MAIN_NETWORK_CAPS_KEY: &str = "1KHLiKZvAvjbY1ziZEHMXawbCEIM6qwjCDm3VYRan/s=";
+
+// hmac keys registered with `registerHmacKey`, indexed by handle. `None` represents the absence
+// of a key (ie. `null`)
+static HMAC_KEYS: RwLock<Vec<Option<Vec<u8>>>> = RwLock::new(Vec::new());
+
+// the thread pool used for parallel verification and validation, if its size was set with
+// `setMaxThreads`. the global rayon pool is used otherwise
+static THREAD_POOL: OnceLock<rayon::ThreadPool> = OnceLock::new();
+
+// the maximum number of messages accepted by the batch functions, as set with `setMaxBatchSize`.
+// unlimited by default
+static MAX_BATCH_SIZE: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+// message buffers kept for reuse by the next batch, which spares the allocator a pair of
+// allocations per message when many small batches are validated (eg. during live replication)
+static BUFFER_POOL: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+// the maximum number of buffers kept in `BUFFER_POOL`, as set with `setBufferPoolSize`
+static MAX_POOLED_BUFFERS: AtomicUsize = AtomicUsize::new(4096);
+
+// buffers with a larger capacity are not kept, since they only hold oversized messages
+const MAX_POOLED_BUFFER_CAPACITY: usize = 4 * MAX_MESSAGE_LENGTH;
+
+// custom `enum` to allow type conversion of the message-signing hmac from js. buffers are
+// copied on conversion: the reference to a js buffer must not be released from another thread,
+// which would happen when a key is moved to a background thread
+enum HmacKey {
+    Buf(Vec<u8>),
+    Str(String),
+    // a handle returned by `registerHmacKey`, `loadHmacKeyFromEnv` or `loadHmacKeyFromFile`
+    Handle(f64),
+    // `null` or `undefined`
+    None,
+}
+
+// implement type conversion for our custom `HmacKey` enum
+// we're primarily interested in strings and array buffers
+impl JSValue<'_> for HmacKey {
+    fn convert_to_rust(env: &JsEnv, n_value: napi_value) -> Result<Self, NjError> {
+        let value_type = env.value_type(n_value)?;
+        if value_type == napi_valuetype_napi_undefined || value_type == napi_valuetype_napi_null {
+            Ok(Self::None)
+        } else if value_type == napi_valuetype_napi_number {
+            Ok(Self::Handle(env.convert_to_rust::<f64>(n_value)?))
+        } else if let Ok(string_value) = env.convert_to_rust::<String>(n_value) {
+            Ok(Self::Str(string_value))
+        } else if let Ok(buffer_value) = env.convert_to_rust::<JSArrayBuffer>(n_value) {
+            Ok(Self::Buf(buffer_value.to_vec()))
+        } else {
+            Err(NjError::Other(
+                "hmacKey must be of type string, array buffer, key handle, null or undefined"
+                    .to_owned(),
+            ))
+        }
+    }
+}
+
+// a single (json encoded) message from js, given either as a string or as a buffer (any typed
+// array, eg. a `Buffer` or `Uint8Array`) holding the utf8 encoding of the message. buffers are
+// copied as-is, which avoids decoding the message into a js string and re-encoding it.
+//
+// messages are always valid utf8, which `utils::multihash_from_bytes` relies on (it panics
+// otherwise), so buffers are checked on conversion
+struct Message(Vec<u8>);
+
+impl Message {
+    fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Message {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+// take up to `count` empty buffers from `BUFFER_POOL`
+fn take_buffers(count: usize) -> Vec<Vec<u8>> {
+    match BUFFER_POOL.lock() {
+        Ok(mut pool) => {
+            let start = pool.len().saturating_sub(count);
+            pool.split_off(start)
+        }
+        Err(_) => Vec::new(),
+    }
+}
+
+// return buffers to `BUFFER_POOL` (emptied), up to the size of the pool
+fn recycle_buffers(buffers: impl Iterator<Item = Vec<u8>>) {
+    let max_pooled = MAX_POOLED_BUFFERS.load(Ordering::Relaxed);
+    if let Ok(mut pool) = BUFFER_POOL.lock() {
+        let room = max_pooled.saturating_sub(pool.len());
+        pool.extend(
+            buffers
+                .filter(|buffer| buffer.capacity() <= MAX_POOLED_BUFFER_CAPACITY)
+                .take(room)
+                .map(|mut buffer| {
+                    buffer.clear();
+                    buffer
+                }),
+        );
+    }
+}
+
+// message buffers which are returned to `BUFFER_POOL` when dropped
+struct PooledBuffers(Vec<Vec<u8>>);
+
+impl Deref for PooledBuffers {
+    type Target = Vec<Vec<u8>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for PooledBuffers {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Drop for PooledBuffers {
+    fn drop(&mut self) {
+        recycle_buffers(self.0.drain(..));
+    }
+}
+
+// read a js string into `buffer` as utf8. unlike the `String` conversion of `node_bindgen`, which
+// allocates twice per string, the (reused) buffer is only grown if it is too small
+fn read_string(env: &JsEnv, js_value: napi_value, buffer: &mut Vec<u8>) -> Result<(), NjError> {
+    env.assert_type(js_value, node_bindgen::sys::napi_valuetype_napi_string)?;
+    let mut length = 0;
+    // SAFETY: a null buffer only queries the length (in bytes, excluding the null terminator)
+    let status = unsafe {
+        napi_get_value_string_utf8(env.inner(), js_value, std::ptr::null_mut(), 0, &mut length)
+    };
+    if status != napi_status_napi_ok {
+        return Err(NjError::Other("failed to read string".to_owned()));
+    }
+    buffer.clear();
+    buffer.resize(length + 1, 0);
+    let mut read = 0;
+    // SAFETY: the buffer holds `length + 1` bytes, the size passed to napi
+    let status = unsafe {
+        napi_get_value_string_utf8(
+            env.inner(),
+            js_value,
+            buffer.as_mut_ptr() as *mut std::os::raw::c_char,
+            length + 1,
+            &mut read,
+        )
+    };
+    if status != napi_status_napi_ok {
+        return Err(NjError::Other("failed to read string".to_owned()));
+    }
+    buffer.truncate(read);
+    Ok(())
+}
+
+// the message as a string slice, for inclusion in error messages. every message is checked to be
+// valid utf8 when it is received from js (see `Messages`), so the fallback is never expected
+fn message_str(msg: &[u8]) -> &str {
+    std::str::from_utf8(msg).unwrap_or("message is not valid UTF-8")
+}
+
+// custom `struct` to allow type conversion of an array of messages from js. the index of the
+// first element which is neither a string nor a buffer (or not valid utf8) is reported, rather
+// than a generic conversion error
+struct Messages(Vec<Message>);
+
+impl JSValue<'_> for Messages {
+    fn convert_to_rust(env: &JsEnv, n_value: napi_value) -> Result<Self, NjError> {
+        if !env.is_array(n_value)? {
+            return Err(NjError::Other("messages must be of type array".to_owned()));
+        }
+        let obj = env.convert_to_rust::<JsObject>(n_value)?;
+        let length = get_property::<i64>(&obj, "length")?.unwrap_or(0);
+        // checked before anything is allocated for the messages
+        let max_batch_size = MAX_BATCH_SIZE.load(Ordering::Relaxed);
+        if length as usize > max_batch_size {
+            return Err(NjError::Other(format!(
+                "batch too large: {} > {}",
+                length, max_batch_size
+            )));
+        }
+        // the buffers taken from the pool (including those already filled) are returned to it
+        // if an element cannot be converted
+        let mut buffers = PooledBuffers(take_buffers(length as usize));
+        let mut filled = PooledBuffers(Vec::with_capacity(length as usize));
+        for index in 0..length {
+            let element = env.get_element(n_value, index as u32)?;
+            if env.is_buffer(element)? {
+                let bytes = env.get_buffer_info(element)?;
+                if std::str::from_utf8(bytes).is_err() {
+                    return Err(NjError::Other(format!(
+                        "message at index {} is not valid UTF-8",
+                        index
+                    )));
+                }
+                let mut buffer = buffers.pop().unwrap_or_default();
+                buffer.extend_from_slice(bytes);
+                filled.push(buffer);
+                continue;
+            }
+            let mut buffer = buffers.pop().unwrap_or_default();
+            let read = read_string(env, element, &mut buffer);
+            filled.push(buffer);
+            if read.is_err() {
+                return Err(NjError::Other(format!(
+                    "message at index {} is not a string or buffer",
+                    index
+                )));
+            }
+        }
+        let msgs = filled.drain(..).map(Message).collect();
+        Ok(Self(msgs))
+    }
+}
+
+impl std::ops::Deref for Messages {
+    type Target = Vec<Message>;
+
+    fn deref(&self) -> &Vec<Message> {
+        &self.0
+    }
+}
+
+impl IntoIterator for Messages {
+    type Item = Message;
+    type IntoIter = std::vec::IntoIter<Message>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+// read an optional property from a js object. `undefined` and `null` values are treated as if
+// the property had not been set
+fn get_property<'a, T: JSValue<'a>>(obj: &'a JsObject, key: &str) -> Result<Option<T>, NjError> {
+    match obj.get_property(key)? {
+        Some(property) => {
+            let value_type = obj.env().value_type(property.napi_value())?;
+            if value_type == napi_valuetype_napi_undefined || value_type == napi_valuetype_napi_null
+            {
+                Ok(None)
+            } else {
+                obj.env()
+                    .convert_to_rust::<T>(property.napi_value())
+                    .map(Some)
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+// custom `struct` to allow type conversion of the batch options object from js.
+// each field corresponds to an optional property of the object. every field except the per-call
+// previous link and known messages is part of the `configFingerprint` (see `fingerprint_config`)
+#[derive(Default)]
+struct BatchOptions {
+    // report the indices of messages with a timestamp earlier than that of their predecessor
+    check_timestamps: bool,
+    // report statistics on the differences between consecutive timestamps
+    timestamp_stats: bool,
+    // report the timestamps of the first and last message
+    timestamp_range: bool,
+    // format of the returned message keys (`legacy` if not set)
+    key_format: Option<String>,
+    // sort messages by sequence number before validation
+    sort_by_sequence: bool,
+    // report metafeed announcements (migration markers) found in the batch
+    detect_migrations: bool,
+    // group the keys of the messages by content type
+    classify_types: bool,
+    // compute a digest over the keys of the batch
+    digest: bool,
+    // validation phases to skip (all phases run by default)
+    skip_verify: bool,
+    skip_chain: bool,
+    skip_hash: bool,
+    // the expected key of the first message of the feed
+    expected_genesis_key: Option<String>,
+    // reject messages by any other author
+    expected_author: Option<String>,
+    // the key and sequence number of the previous message, when the message itself is not given
+    previous_key: Option<String>,
+    previous_sequence: Option<i64>,
+    // report the keys of messages with any of these (deprecated) content types
+    deprecated_types: Option<Vec<String>>,
+    // reject (or, with `warn_disallowed_types`, report) messages with any other content type
+    allowed_types: Option<Vec<String>>,
+    warn_disallowed_types: bool,
+    // reject messages by any of these authors at or beyond the paired (revocation) sequence
+    revoked_keys: Option<Vec<(String, i64)>>,
+    // report the signature algorithm of each message
+    signature_algorithms: bool,
+    // report whether the content of each message is encrypted
+    detect_encrypted: bool,
+    // report the hash of the content of each message
+    content_hashes: bool,
+    // report the key of `previous`
+    return_previous_key: bool,
+    // report the time spent in each phase
+    stats: bool,
+    // collect the feed ids referenced in message content
+    collect_feed_ids: bool,
+    // report the blob ids referenced in the content of each message
+    collect_blob_ids: bool,
+    // return a mask of the valid messages from `validateBatchSettled`
+    mask: bool,
+    // return the latest message of each feed from `validateMultiAuthorBatch`
+    feed_tips: bool,
+    // validate the hash chain of each feed in a `validateMultiAuthorBatch`
+    chain_per_author: bool,
+    // the key, sequence number and previous key (`none` for the first message of a feed) of
+    // messages known to the caller, against which the links of a `validateOOOBatch` are checked
+    known_messages: Option<Vec<(String, i64, String)>>,
+    // report messages with non-canonical base64 in the `author` or `signature` field
+    check_base64: bool,
+    // reject messages with non-canonical base64 in the `author` or `signature` field
+    strict_base64: bool,
+    // reject messages whose content is nested deeper than this
+    max_depth: Option<usize>,
+    // reject messages whose (compact) content encoding is larger than this
+    max_content_bytes: Option<usize>,
+    // validate the batch up to the first sequence gap and report the gaps instead of failing
+    report_gaps: bool,
+}
+
+impl BatchOptions {
+    // whether any additional information was requested
+    fn wants_info(&self) -> bool {
+        self.check_timestamps
+            || self.timestamp_stats
+            || self.timestamp_range
+            || self.detect_migrations
+            || self.classify_types
+            || self.digest
+            || self.check_base64
+            || self.deprecated_types.is_some()
+            || (self.allowed_types.is_some() && self.warn_disallowed_types)
+            || self.collect_feed_ids
+            || self.collect_blob_ids
+            || self.signature_algorithms
+            || self.detect_encrypted
+            || self.content_hashes
+            || self.return_previous_key
+            || self.stats
+            || self.report_gaps
+    }
+
+    fn key_format(&self) -> Result<KeyFormat, String> {
+        match self.key_format.as_deref() {
+            None | Some("legacy") => Ok(KeyFormat::Legacy),
+            Some("uri") => Ok(KeyFormat::Uri),
+            Some(format) => Err(format!(
+                "key format invalid: expected `legacy` or `uri` but got `{}`",
+                format
+            )),
+        }
+    }
+
+    // whether keys are to be returned in both the legacy and the uri format (`keyFormat` of
+    // `both`). only supported by `validateBatch` and `getMessageKeys`
+    fn both_key_formats(&self) -> bool {
+        self.key_format.as_deref() == Some("both")
+    }
+
+    // whether keys are to be returned as raw 32 byte digests (`keyFormat` of `raw`). only
+    // supported by `validateBatch` and `getMessageKeys`
+    fn raw_keys(&self) -> bool {
+        self.key_format.as_deref() == Some("raw")
+    }
+}
+
+// format in which message keys are returned
+#[derive(Clone, Copy)]
+enum KeyFormat {
+    // sigil-based legacy format: `%<base64>.sha256`
+    Legacy,
+    // ssb uri format: `ssb:message/classic/<url-safe base64>`
+    Uri,
+}
+
+impl JSValue<'_> for BatchOptions {
+    fn convert_to_rust(env: &JsEnv, n_value: napi_value) -> Result<Self, NjError> {
+        let obj = env.convert_to_rust::<JsObject>(n_value)?;
+        let mut options = Self::default();
+        if let Some(check_timestamps) = get_property::<bool>(&obj, "checkTimestamps")? {
+            options.check_timestamps = check_timestamps;
+        }
+        if let Some(timestamp_stats) = get_property::<bool>(&obj, "timestampStats")? {
+            options.timestamp_stats = timestamp_stats;
+        }
+        if let Some(timestamp_range) = get_property::<bool>(&obj, "timestampRange")? {
+            options.timestamp_range = timestamp_range;
+        }
+        options.key_format = get_property::<String>(&obj, "keyFormat")?;
+        if let Some(sort_by_sequence) = get_property::<bool>(&obj, "sortBySequence")? {
+            options.sort_by_sequence = sort_by_sequence;
+        }
+        if let Some(detect_migrations) = get_property::<bool>(&obj, "detectMigrations")? {
+            options.detect_migrations = detect_migrations;
+        }
+        if let Some(classify_types) = get_property::<bool>(&obj, "classifyTypes")? {
+            options.classify_types = classify_types;
+        }
+        if let Some(digest) = get_property::<bool>(&obj, "digest")? {
+            options.digest = digest;
+        }
+        options.skip_verify = !get_property::<bool>(&obj, "verify")?.unwrap_or(true)
+            || get_property::<bool>(&obj, "skipVerify")?.unwrap_or(false);
+        options.skip_chain = !get_property::<bool>(&obj, "chain")?.unwrap_or(true);
+        options.skip_hash = !get_property::<bool>(&obj, "hash")?.unwrap_or(true);
+        options.expected_genesis_key = get_property::<String>(&obj, "expectedGenesisKey")?;
+        options.expected_author = get_property::<String>(&obj, "expectedAuthor")?;
+        options.previous_key = get_property::<String>(&obj, "previousKey")?;
+        options.previous_sequence = get_property::<i64>(&obj, "previousSequence")?;
+        options.deprecated_types = get_property::<Vec<String>>(&obj, "deprecatedTypes")?;
+        options.allowed_types = get_property::<Vec<String>>(&obj, "allowedTypes")?;
+        options.revoked_keys = get_property::<Vec<(String, i64)>>(&obj, "revokedKeys")?;
+        options.known_messages = get_property::<Vec<(String, i64, String)>>(&obj, "knownMessages")?;
+        if let Some(mode) = get_property::<String>(&obj, "disallowedTypes")? {
+            options.warn_disallowed_types = match mode.as_str() {
+                "reject" => false,
+                "warn" => true,
+                _ => {
+                    return Err(NjError::Other(format!(
+                        "invalid disallowedTypes option: {}; expected `reject` or `warn`",
+                        mode
+                    )))
+                }
+            };
+        }
+        if let Some(signature_algorithms) = get_property::<bool>(&obj, "signatureAlgorithms")? {
+            options.signature_algorithms = signature_algorithms;
+        }
+        if let Some(detect_encrypted) = get_property::<bool>(&obj, "detectEncrypted")? {
+            options.detect_encrypted = detect_encrypted;
+        }
+        if let Some(content_hashes) = get_property::<bool>(&obj, "contentHashes")? {
+            options.content_hashes = content_hashes;
+        }
+        if let Some(return_previous_key) = get_property::<bool>(&obj, "returnPreviousKey")? {
+            options.return_previous_key = return_previous_key;
+        }
+        if let Some(stats) = get_property::<bool>(&obj, "stats")? {
+            options.stats = stats;
+        }
+        if let Some(report_gaps) = get_property::<bool>(&obj, "reportGaps")? {
+            options.report_gaps = report_gaps;
+        }
+        if let Some(collect_feed_ids) = get_property::<bool>(&obj, "collectFeedIds")? {
+            options.collect_feed_ids = collect_feed_ids;
+        }
+        if let Some(collect_blob_ids) = get_property::<bool>(&obj, "collectBlobIds")? {
+            options.collect_blob_ids = collect_blob_ids;
+        }
+        if let Some(mask) = get_property::<bool>(&obj, "mask")? {
+            options.mask = mask;
+        }
+        if let Some(feed_tips) = get_property::<bool>(&obj, "feedTips")? {
+            options.feed_tips = feed_tips;
+        }
+        if let Some(chain_per_author) = get_property::<bool>(&obj, "chainPerAuthor")? {
+            options.chain_per_author = chain_per_author;
+        }
+        if let Some(check_base64) = get_property::<bool>(&obj, "checkBase64")? {
+            options.check_base64 = check_base64;
+        }
+        if let Some(strict_base64) = get_property::<bool>(&obj, "strictBase64")? {
+            options.strict_base64 = strict_base64;
+        }
+        options.max_depth = get_limit(&obj, "maxDepth")?;
+        options.max_content_bytes = get_limit(&obj, "maxContentBytes")?;
+        Ok(options)
+    }
+}
+
+// read an optional non-negative integer limit from an options object
+fn get_limit(obj: &JsObject, key: &str) -> Result<Option<usize>, NjError> {
+    match get_property::<i64>(obj, key)? {
+        Some(limit) => usize::try_from(limit).map(Some).map_err(|_| {
+            NjError::Other(format!(
+                "{} invalid: expected a non-negative integer but got {}",
+                key, limit
+            ))
+        }),
+        None => Ok(None),
+    }
+}
+
+// the keys of a batch, either in a single format, as `[legacy, uri]` pairs or as raw digests
+#[derive(Debug)]
+enum BatchKeys {
+    Single(Vec<String>),
+    Both(Vec<(String, String)>),
+    Raw(Vec<[u8; 32]>),
+}
+
+impl TryIntoJs for BatchKeys {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        match self {
+            BatchKeys::Single(keys) => keys.try_to_js(js_env),
+            BatchKeys::Both(pairs) => pairs
+                .into_iter()
+                .map(|(legacy, uri)| vec![legacy, uri])
+                .collect::<Vec<Vec<String>>>()
+                .try_to_js(js_env),
+            BatchKeys::Raw(digests) => digests
+                .into_iter()
+                .map(|digest| ArrayBuffer::new(digest.to_vec()))
+                .collect::<Vec<ArrayBuffer>>()
+                .try_to_js(js_env),
+        }
+    }
+}
+
+// additional information gathered during batch validation, as requested by `BatchOptions`.
+// fields which were not requested are omitted from the js object
+#[derive(Debug, Default)]
+struct BatchInfo {
+    timestamp_regressions: Option<Vec<i64>>,
+    // `None` within the outer option if there are fewer than two timestamps
+    timestamp_deltas: Option<Option<TimestampDeltas>>,
+    // `None` within the outer option if the batch is empty
+    timestamp_range: Option<Option<(f64, f64)>>,
+    migration_markers: Option<Vec<MigrationMarker>>,
+    // inclusive range of sequence numbers missing from the batch; only set on error
+    missing_range: Option<(u64, u64)>,
+    // index of the offending message; only set on error
+    invalid_index: Option<usize>,
+    // stable, machine-readable code of the cause of the error; only set on error
+    error_code: Option<&'static str>,
+    content_types: Option<BTreeMap<String, Vec<String>>>,
+    digest: Option<String>,
+    non_canonical_base64: Option<Vec<NonCanonicalField>>,
+    deprecated: Option<Vec<DeprecatedMessage>>,
+    disallowed_types: Option<Vec<DisallowedMessage>>,
+    feed_ids: Option<BTreeSet<String>>,
+    blob_ids: Option<Vec<Vec<String>>>,
+    signature_algorithms: Option<Vec<Option<String>>>,
+    encrypted: Option<Vec<bool>>,
+    content_hashes: Option<Vec<Option<String>>>,
+    // `None` within the outer option if no previous message was given
+    previous_key: Option<Option<String>>,
+    stats: Option<BatchStats>,
+    // indices of the messages following a sequence gap
+    gaps: Option<Vec<i64>>,
+}
+
+impl TryIntoJs for BatchInfo {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        if let Some(regressions) = self.timestamp_regressions {
+            obj.set_property("timestampRegressions", regressions.try_to_js(js_env)?)?;
+        }
+        if let Some(deltas) = self.timestamp_deltas {
+            obj.set_property("timestampDeltas", deltas.try_to_js(js_env)?)?;
+        }
+        if let Some(range) = self.timestamp_range {
+            let range = range.map(|(first, last)| vec![first, last]);
+            obj.set_property("timestampRange", range.try_to_js(js_env)?)?;
+        }
+        if let Some(markers) = self.migration_markers {
+            obj.set_property("migrationMarkers", markers.try_to_js(js_env)?)?;
+        }
+        if let Some((first, last)) = self.missing_range {
+            let range = vec![first as i64, last as i64];
+            obj.set_property("missingRange", range.try_to_js(js_env)?)?;
+        }
+        if let Some(index) = self.invalid_index {
+            obj.set_property("invalidIndex", (index as i64).try_to_js(js_env)?)?;
+        }
+        if let Some(code) = self.error_code {
+            obj.set_property("errorCode", code.to_owned().try_to_js(js_env)?)?;
+        }
+        if let Some(content_types) = self.content_types {
+            let mut types_obj = JsObject::create(js_env)?;
+            for (msg_type, keys) in content_types {
+                types_obj.set_property(&msg_type, keys.try_to_js(js_env)?)?;
+            }
+            obj.set_property("contentTypes", types_obj.try_to_js(js_env)?)?;
+        }
+        if let Some(digest) = self.digest {
+            obj.set_property("digest", digest.try_to_js(js_env)?)?;
+        }
+        if let Some(fields) = self.non_canonical_base64 {
+            obj.set_property("nonCanonicalBase64", fields.try_to_js(js_env)?)?;
+        }
+        if let Some(deprecated) = self.deprecated {
+            obj.set_property("deprecated", deprecated.try_to_js(js_env)?)?;
+        }
+        if let Some(disallowed) = self.disallowed_types {
+            obj.set_property("disallowedTypes", disallowed.try_to_js(js_env)?)?;
+        }
+        if let Some(feed_ids) = self.feed_ids {
+            let feed_ids: Vec<String> = feed_ids.into_iter().collect();
+            obj.set_property("feedIds", feed_ids.try_to_js(js_env)?)?;
+        }
+        if let Some(blob_ids) = self.blob_ids {
+            obj.set_property("blobIds", blob_ids.try_to_js(js_env)?)?;
+        }
+        if let Some(algorithms) = self.signature_algorithms {
+            obj.set_property("signatureAlgorithms", algorithms.try_to_js(js_env)?)?;
+        }
+        if let Some(encrypted) = self.encrypted {
+            obj.set_property("encrypted", encrypted.try_to_js(js_env)?)?;
+        }
+        if let Some(content_hashes) = self.content_hashes {
+            obj.set_property("contentHashes", content_hashes.try_to_js(js_env)?)?;
+        }
+        if let Some(previous_key) = self.previous_key {
+            obj.set_property("previousKey", previous_key.try_to_js(js_env)?)?;
+        }
+        if let Some(stats) = self.stats {
+            obj.set_property("stats", stats.try_to_js(js_env)?)?;
+        }
+        if let Some(gaps) = self.gaps {
+            obj.set_property("gaps", gaps.try_to_js(js_env)?)?;
+        }
+        obj.try_to_js(js_env)
+    }
+}
+
+// statistics on the differences between the timestamps of consecutive messages, in milliseconds
+#[derive(Debug)]
+struct TimestampDeltas {
+    min: f64,
+    max: f64,
+    mean: f64,
+}
+
+impl TryIntoJs for TimestampDeltas {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property("min", self.min.try_to_js(js_env)?)?;
+        obj.set_property("max", self.max.try_to_js(js_env)?)?;
+        obj.set_property("mean", self.mean.try_to_js(js_env)?)?;
+        obj.try_to_js(js_env)
+    }
+}
+
+// the time spent verifying signatures and validating the hash chain of a batch, in milliseconds
+#[derive(Debug)]
+struct BatchStats {
+    verify_ms: f64,
+    validate_ms: f64,
+    count: usize,
+}
+
+impl TryIntoJs for BatchStats {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property("verifyMs", self.verify_ms.try_to_js(js_env)?)?;
+        obj.set_property("validateMs", self.validate_ms.try_to_js(js_env)?)?;
+        obj.set_property("count", (self.count as i64).try_to_js(js_env)?)?;
+        obj.try_to_js(js_env)
+    }
+}
+
+// a `metafeed/announce` message found in a classic feed, announcing the metafeed the author
+// has migrated to
+#[derive(Debug)]
+struct MigrationMarker {
+    index: usize,
+    metafeed: Option<String>,
+}
+
+impl TryIntoJs for MigrationMarker {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property("index", (self.index as i64).try_to_js(js_env)?)?;
+        obj.set_property("metafeed", self.metafeed.try_to_js(js_env)?)?;
+        obj.try_to_js(js_env)
+    }
+}
+
+// a message with a deprecated content type
+#[derive(Debug)]
+struct DeprecatedMessage {
+    key: String,
+    msg_type: String,
+}
+
+impl TryIntoJs for DeprecatedMessage {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property("key", self.key.try_to_js(js_env)?)?;
+        obj.set_property("type", self.msg_type.try_to_js(js_env)?)?;
+        obj.try_to_js(js_env)
+    }
+}
+
+// a message with a content type which is not in the `allowedTypes` list
+#[derive(Debug)]
+struct DisallowedMessage {
+    index: usize,
+    msg_type: String,
+}
+
+impl TryIntoJs for DisallowedMessage {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property("index", (self.index as i64).try_to_js(js_env)?)?;
+        obj.set_property("type", self.msg_type.try_to_js(js_env)?)?;
+        obj.try_to_js(js_env)
+    }
+}
+
+// a message field holding base64 which is not canonically encoded (eg. incorrect padding or
+// non-zero trailing bits)
+#[derive(Debug)]
+struct NonCanonicalField {
+    index: usize,
+    field: &'static str,
+}
+
+impl TryIntoJs for NonCanonicalField {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property("index", (self.index as i64).try_to_js(js_env)?)?;
+        obj.set_property("field", self.field.to_string().try_to_js(js_env)?)?;
+        obj.try_to_js(js_env)
+    }
+}
+
+// the number of invalid signatures in a batch, as returned by `countInvalidSignatures`
+struct SignatureCount {
+    total: usize,
+    invalid: usize,
+}
+
+impl TryIntoJs for SignatureCount {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property("total", (self.total as i64).try_to_js(js_env)?)?;
+        obj.set_property("invalid", (self.invalid as i64).try_to_js(js_env)?)?;
+        obj.try_to_js(js_env)
+    }
+}
+
+// the result of verifying the signature of a single message, as returned by
+// `verifySignaturesPartial` and `validateBatchMixedHmac`. `outcome` holds the key of the message
+// if the message is valid, or the error code and reason otherwise
+struct SignatureResult {
+    index: usize,
+    outcome: Result<String, (&'static str, String)>,
+}
+
+impl TryIntoJs for SignatureResult {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property("index", (self.index as i64).try_to_js(js_env)?)?;
+        obj.set_property("valid", self.outcome.is_ok().try_to_js(js_env)?)?;
+        match self.outcome {
+            Ok(key) => obj.set_property("key", key.try_to_js(js_env)?)?,
+            Err((code, reason)) => {
+                obj.set_property("code", code.to_owned().try_to_js(js_env)?)?;
+                obj.set_property("error", reason.try_to_js(js_env)?)?;
+            }
+        }
+        obj.try_to_js(js_env)
+    }
+}
+
+// build and runtime information returned by `versionInfo`
+struct VersionInfo {
+    version: &'static str,
+    crypto_backend: &'static str,
+    simd_enabled: bool,
+    simd_supported: bool,
+}
+
+impl TryIntoJs for VersionInfo {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property("version", self.version.to_owned().try_to_js(js_env)?)?;
+        obj.set_property(
+            "cryptoBackend",
+            self.crypto_backend.to_owned().try_to_js(js_env)?,
+        )?;
+        obj.set_property("simdEnabled", self.simd_enabled.try_to_js(js_env)?)?;
+        obj.set_property("simdSupported", self.simd_supported.try_to_js(js_env)?)?;
+        obj.try_to_js(js_env)
+    }
+}
+
+// the outcome of `selfTest`, along with the build information of the native module
+struct SelfTestResult {
+    passed: bool,
+    // the cause of the failure, if the self-test did not pass
+    reason: Option<String>,
+    info: VersionInfo,
+}
+
+impl TryIntoJs for SelfTestResult {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property("passed", self.passed.try_to_js(js_env)?)?;
+        obj.set_property("reason", self.reason.try_to_js(js_env)?)?;
+        obj.set_property("versionInfo", self.info.try_to_js(js_env)?)?;
+        obj.try_to_js(js_env)
+    }
+}
+
+// the key of a valid message along with the fields most often needed for indexing it
+struct MessageMeta {
+    key: String,
+    author: String,
+    sequence: u64,
+    previous: Option<String>,
+}
+
+impl TryIntoJs for MessageMeta {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property("key", self.key.try_to_js(js_env)?)?;
+        obj.set_property("author", self.author.try_to_js(js_env)?)?;
+        obj.set_property("sequence", (self.sequence as i64).try_to_js(js_env)?)?;
+        obj.set_property("previous", self.previous.try_to_js(js_env)?)?;
+        obj.try_to_js(js_env)
+    }
+}
+
+// a single failed message in a batch, identified by its index in the input array
+struct MessageFailure {
+    index: usize,
+    code: &'static str,
+    reason: String,
+}
+
+impl TryIntoJs for MessageFailure {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property("index", (self.index as i64).try_to_js(js_env)?)?;
+        obj.set_property("code", self.code.to_owned().try_to_js(js_env)?)?;
+        obj.set_property("reason", self.reason.try_to_js(js_env)?)?;
+        obj.try_to_js(js_env)
+    }
+}
+
+// the cause of a failed validation: a stable, machine-readable error code and the index of the
+// offending message (if a single message is at fault)
+struct FailureDetails {
+    code: &'static str,
+    index: Option<usize>,
+}
+
+impl FailureDetails {
+    fn new(code: &'static str, index: Option<usize>) -> Option<Self> {
+        Some(FailureDetails { code, index })
+    }
+}
+
+impl TryIntoJs for FailureDetails {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property("code", self.code.to_owned().try_to_js(js_env)?)?;
+        if let Some(index) = self.index {
+            obj.set_property("index", (index as i64).try_to_js(js_env)?)?;
+        }
+        obj.try_to_js(js_env)
+    }
+}
+
+// the latest message (tip) of a feed within a batch
+struct FeedTip {
+    key: String,
+    sequence: u64,
+}
+
+// the tip of each feed within a batch, by author
+struct FeedTips(BTreeMap<String, FeedTip>);
+
+impl TryIntoJs for FeedTips {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        for (author, tip) in self.0 {
+            let mut tip_obj = JsObject::create(js_env)?;
+            tip_obj.set_property("key", tip.key.try_to_js(js_env)?)?;
+            tip_obj.set_property("sequence", (tip.sequence as i64).try_to_js(js_env)?)?;
+            obj.set_property(&author, tip_obj.try_to_js(js_env)?)?;
+        }
+        obj.try_to_js(js_env)
+    }
+}
+
+// the invalid messages of a batch which failed for the same reason (error code)
+#[derive(Default)]
+struct FailureBucket {
+    count: usize,
+    // the keys of the first few messages in the bucket
+    examples: Vec<String>,
+}
+
+impl TryIntoJs for FailureBucket {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property("count", (self.count as i64).try_to_js(js_env)?)?;
+        obj.set_property("examples", self.examples.try_to_js(js_env)?)?;
+        obj.try_to_js(js_env)
+    }
+}
+
+// a summary of the validity of a batch, with the invalid messages grouped by error code
+struct BatchSummary {
+    valid: usize,
+    invalid: usize,
+    reasons: BTreeMap<&'static str, FailureBucket>,
+}
+
+impl TryIntoJs for BatchSummary {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property("valid", (self.valid as i64).try_to_js(js_env)?)?;
+        obj.set_property("invalid", (self.invalid as i64).try_to_js(js_env)?)?;
+        let mut reasons_obj = JsObject::create(js_env)?;
+        for (code, bucket) in self.reasons {
+            reasons_obj.set_property(code, bucket.try_to_js(js_env)?)?;
+        }
+        obj.set_property("reasons", reasons_obj.try_to_js(js_env)?)?;
+        obj.try_to_js(js_env)
+    }
+}
+
+// a structural classification of a batch: whether it is a contiguous, hash-chained segment of a
+// single feed, along with the author and the sequence range of the segment
+struct FeedSegment {
+    is_contiguous_feed: bool,
+    // `None` if the batch is empty or holds messages by more than one author
+    author: Option<String>,
+    start_seq: Option<u64>,
+    end_seq: Option<u64>,
+}
+
+impl TryIntoJs for FeedSegment {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property(
+            "isContiguousFeed",
+            self.is_contiguous_feed.try_to_js(js_env)?,
+        )?;
+        obj.set_property("author", self.author.try_to_js(js_env)?)?;
+        let start_seq = self.start_seq.map(|seq| seq as i64);
+        obj.set_property("startSeq", start_seq.try_to_js(js_env)?)?;
+        let end_seq = self.end_seq.map(|seq| seq as i64);
+        obj.set_property("endSeq", end_seq.try_to_js(js_env)?)?;
+        obj.try_to_js(js_env)
+    }
+}
+
+// the outcome of a batch which is validated in full, regardless of failures: the keys of all
+// valid messages (in order) and a failure entry for each invalid message
+struct SettledBatch {
+    keys: Vec<String>,
+    errors: Vec<MessageFailure>,
+    // one byte per message: 1 if valid, 0 if invalid
+    mask: Option<Vec<u8>>,
+}
+
+impl TryIntoJs for SettledBatch {
+    fn try_to_js(self, js_env: &JsEnv) -> Result<napi_value, NjError> {
+        let mut obj = JsObject::create(js_env)?;
+        obj.set_property("keys", self.keys.try_to_js(js_env)?)?;
+        obj.set_property("errors", self.errors.try_to_js(js_env)?)?;
+        if let Some(mask) = self.mask {
+            obj.set_property("mask", ArrayBuffer::new(mask).try_to_js(js_env)?)?;
+        }
+        obj.try_to_js(js_env)
+    }
+}
+
+impl SettledBatch {
+    // collect the outcome of each message (in order) into the keys of the valid messages and an
+    // entry for each invalid message, along with the mask of the valid messages if requested
+    fn from_outcomes(
+        outcomes: impl Iterator<Item = Result<String, (&'static str, String)>>,
+        with_mask: bool,
+    ) -> Self {
+        let mut keys = Vec::new();
+        let mut errors = Vec::new();
+        let mut mask = Vec::new();
+        for (index, outcome) in outcomes.enumerate() {
+            match outcome {
+                Ok(key) => {
+                    mask.push(1);
+                    keys.push(key)
+                }
+                Err((code, reason)) => {
+                    mask.push(0);
+                    errors.push(MessageFailure {
+                        index,
+                        code,
+                        reason,
+                    })
+                }
+            }
+        }
+        let mask = if with_mask { Some(mask) } else { None };
+        Self { keys, errors, mask }
+    }
+}
+
+// the hmac mode under which a message with an invalid signature would have been valid, if it is
+// not the mode which was used: `keyless` if an hmac key was used, or `keyed` if no hmac key was
+// used and the message is valid under one of the keys registered with `registerHmacKey`
+fn hmac_mismatch(msg: &[u8], hmac: Option<&[u8]>) -> Option<&'static str> {
+    match hmac {
+        Some(_) => verify_message_value(msg, None).ok().map(|_| "keyless"),
+        None => {
+            let keys = HMAC_KEYS.read().ok()?;
+            keys.iter()
+                .flatten()
+                .find(|key| verify_message_value(msg, Some(key)).is_ok())
+                .map(|_| "keyed")
+        }
+    }
+}
+
+// describe a signature verification failure. an invalid signature is checked under the opposite
+// hmac mode and, if valid, the hmac mismatch is included in the description
+fn verification_failure_reason(
+    err: &VerificationError,
+    msg: Option<&[u8]>,
+    hmac: Option<&[u8]>,
+) -> String {
+    if let (VerificationError::InvalidSignature {}, Some(msg)) = (err, msg) {
+        if let Some(mode) = hmac_mismatch(msg, hmac) {
+            return format!(
+                "{} (hmac mismatch: message appears signed under {})",
+                err, mode
+            );
+        }
+    }
+    err.to_string()
+}
+
+// a hint for inclusion in an error message when an hmac key was supplied but every message failed
+// signature verification, which suggests that the network caps key was supplied in place of the
+// message-signing hmac key
+fn caps_key_hint(msgs: &[Vec<u8>], hmac: Option<&[u8]>) -> &'static str {
+    let hmac = match hmac {
+        Some(hmac) => hmac,
+        None => return "",
+    };
+    if MsgHmacKey::from_base64(MAIN_NETWORK_CAPS_KEY)
+        .is_some_and(|caps_key| caps_key.as_bytes() == hmac)
+    {
+        return " (hint: the hmac key is the main network caps key, which is not used for message signing)";
+    }
+    if msgs
+        .iter()
+        .all(|msg| verify_message_value(msg, Some(hmac)).is_err())
+    {
+        " (hint: every message failed verification with the hmac key; it may have been confused with the network caps key)"
+    } else {
+        ""
+    }
+}
+
+// verify the signature of a single message, returning the error code and reason otherwise
+fn check_signature(msg: &[u8], hmac: Option<&[u8]>) -> Result<(), (&'static str, String)> {
+    verify_message_value(msg, hmac).map_err(|e| {
+        let reason = verification_failure_reason(&e, Some(msg), hmac);
+        (verification_error_code(&e), reason)
+    })
+}
+
+// validate a single message against its previous message (if any), returning the error code and
+// reason otherwise
+fn check_link(msg: &[u8], previous: Option<&[u8]>) -> Result<(), (&'static str, String)> {
+    validate_message_value_hash_chain(msg, previous).map_err(|e| {
+        let reason = link_failure_reason(&e, msg, previous);
+        (validation_error_code(&e), reason)
+    })
+}
+
+// verify and validate messages in order, chaining each message to the last valid message
+// preceding it (or `previous`). signatures are verified in parallel, one chunk of messages at a
+// time so that results are still reported as the batch progresses; only the hash-chain checks are
+// done in sequence. `on_result` is called with the index of each message and either its key or
+// the error code, reason and message bytes
+fn validate_in_sequence<I, F>(
+    mut msgs: I,
+    previous: Option<Vec<u8>>,
+    hmac: Option<&[u8]>,
+    key_format: KeyFormat,
+    mut on_result: F,
+) where
+    I: Iterator<Item = Vec<u8>>,
+    F: FnMut(usize, Result<String, (&'static str, String, Vec<u8>)>),
+{
+    let chunk_size = thread_count() * MESSAGES_PER_THREAD;
+    let mut last_valid = previous;
+    let mut index = 0;
+    loop {
+        let chunk: Vec<Vec<u8>> = msgs.by_ref().take(chunk_size).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        let verified: Vec<Result<(), (&'static str, String)>> = in_thread_pool(|| {
+            chunk
+                .par_iter()
+                .map(|msg| check_signature(msg, hmac))
+                .collect()
+        });
+        for (msg, verification) in chunk.into_iter().zip(verified) {
+            let result = verification.and_then(|_| check_link(&msg, last_valid.as_deref()));
+            match result {
+                Ok(_) => {
+                    let multihash = utils::multihash_from_bytes(&msg);
+                    on_result(index, Ok(format_key(multihash, key_format)));
+                    last_valid = Some(msg);
+                }
+                Err((code, reason)) => on_result(index, Err((code, reason, msg))),
+            }
+            index += 1;
+        }
+    }
+}
+
+// a stable number for each machine-readable error code, for consumers which prefer to switch on
+// an integer. the table is append-only: a code never changes its number and numbers are never
+// reused. codes in the 100 range stem from signature verification, in the 200 range from
+// validation and in the 300 range from the additional checks of this module
+const NUMERIC_ERROR_CODES: &[(&str, i64)] = &[
+    ("INVALID_SIGNATURE", 100),
+    ("INVALID_SIGNATURE_ENCODING", 101),
+    ("INVALID_AUTHOR", 102),
+    ("INVALID_HMAC_KEY", 103),
+    ("INVALID_MESSAGE", 104),
+    ("INVALID_PREVIOUS_MESSAGE", 200),
+    ("INVALID_FIELD_ORDER", 201),
+    ("AUTHOR_MISMATCH", 202),
+    ("INVALID_FIRST_SEQUENCE", 203),
+    ("INVALID_FIRST_PREVIOUS", 204),
+    ("INVALID_HASH_FUNCTION", 205),
+    ("INVALID_CONTENT_BASE64", 206),
+    ("MESSAGE_TOO_LONG", 207),
+    ("INVALID_SEQUENCE", 208),
+    ("KEY_MISMATCH", 209),
+    ("PREVIOUS_WAS_NULL", 210),
+    ("FORKED_FEED", 211),
+    ("INVALID_OPTIONS", 300),
+    ("CONTENT_LIMIT_EXCEEDED", 301),
+    ("DISALLOWED_TYPE", 302),
+    ("REVOKED_AUTHOR", 303),
+    ("NON_CANONICAL_BASE64", 304),
+    ("GENESIS_KEY_MISMATCH", 305),
+    ("MALFORMED_INPUT", 306),
+];
+
+// map a signature verification error to a stable, machine-readable code
+fn verification_error_code(err: &VerificationError) -> &'static str {
+    match err {
+        VerificationError::InvalidSignature {} => "INVALID_SIGNATURE",
+        VerificationError::InvalidSignatureBytes
+        | VerificationError::InvalidSignatureString {}
+        | VerificationError::InvalidSignatureStringBase64Encoding { .. } => {
+            "INVALID_SIGNATURE_ENCODING"
+        }
+        VerificationError::InvalidKeyBytes
+        | VerificationError::InvalidAuthorString {}
+        | VerificationError::InvalidAuthorStringBase64Encoding { .. } => "INVALID_AUTHOR",
+        VerificationError::InvalidHmac => "INVALID_HMAC_KEY",
+        VerificationError::InvalidSsbMessage { .. }
+        | VerificationError::UnableToEncodeMessageToValidSigningEncoding { .. }
+        | VerificationError::InvalidSsbMessageJson { .. }
+        | VerificationError::InvalidMessageNoValue => "INVALID_MESSAGE",
+    }
+}
+
+// map a message validation error to a stable, machine-readable code
+fn validation_error_code(err: &ValidationError) -> &'static str {
+    match err {
+        ValidationError::InvalidPreviousMessage { .. } => "INVALID_PREVIOUS_MESSAGE",
+        ValidationError::InvalidMessage { .. }
+        | ValidationError::InvalidMessageNoValue
+        | ValidationError::InvalidMessageCouldNotSerializeValue { .. } => "INVALID_MESSAGE",
+        ValidationError::InvalidMessageValueOrder { .. } => "INVALID_FIELD_ORDER",
+        ValidationError::AuthorsDidNotMatch { .. } => "AUTHOR_MISMATCH",
+        ValidationError::FirstMessageDidNotHaveSequenceOfOne { .. } => "INVALID_FIRST_SEQUENCE",
+        ValidationError::FirstMessageDidNotHavePreviousOfNull { .. } => "INVALID_FIRST_PREVIOUS",
+        ValidationError::InvalidHashFunction { .. } => "INVALID_HASH_FUNCTION",
+        ValidationError::InvalidBase64 { .. } => "INVALID_CONTENT_BASE64",
+        ValidationError::InvalidMessageValueLength { .. } => "MESSAGE_TOO_LONG",
+        ValidationError::InvalidSequenceNumber { .. } => "INVALID_SEQUENCE",
+        ValidationError::ActualHashDidNotMatchKey { .. } => "KEY_MISMATCH",
+        ValidationError::PreviousWasNull => "PREVIOUS_WAS_NULL",
+        ValidationError::ForkedFeed { .. } => "FORKED_FEED",
+    }
+}
+
+// the reason a message failed validation. a message linked to a previous message by a
+// different author is spliced into another feed, which is called out explicitly
+fn validation_failure_reason(err: &ValidationError) -> String {
+    match err {
+        ValidationError::AuthorsDidNotMatch { .. } => {
+            format!("cross-feed previous link: {}", err)
+        }
+        _ => err.to_string(),
+    }
+}
+
+// the reason a message failed validation against its previous message. a broken hash chain
+// names both the `previous` key claimed by the message and the key of the previous message
+fn link_failure_reason(err: &ValidationError, msg: &[u8], previous: Option<&[u8]>) -> String {
+    match (err, previous) {
+        (ValidationError::ForkedFeed { .. }, Some(previous)) => {
+            let expected = utils::multihash_from_bytes(previous).to_legacy_string();
+            broken_link_reason(err, msg, &expected)
+        }
+        _ => validation_failure_reason(err),
+    }
+}
+
+// `reason` extended with the `previous` key claimed by `msg` and the `expected` key, for a message
+// which does not link to the key of its previous message
+fn broken_link_reason(reason: impl std::fmt::Display, msg: &[u8], expected: &str) -> String {
+    let claimed = from_slice::<SsbMessageValue>(msg)
+        .ok()
+        .and_then(|value| value.previous)
+        .map(|previous| previous.to_legacy_string())
+        .unwrap_or_else(|| "null".to_string());
+    format!(
+        "{} (claimed previous: {}, expected previous: {})",
+        reason, claimed, expected
+    )
+}
+
+// run `op` in the thread pool configured with `setMaxThreads` (if any), so that any parallel
+// work it does is bounded by the size of that pool
+fn in_thread_pool<R: Send>(op: impl FnOnce() -> R + Send) -> R {
+    match THREAD_POOL.get() {
+        Some(pool) => pool.install(op),
+        None => op(),
+    }
+}
+
+// run `op` on the thread pool configured with `setMaxThreads` (or the global rayon pool) without
+// waiting for it, so that background work is bounded by the size of the pool rather than
+// spawning a thread per call
+fn spawn_in_thread_pool(op: impl FnOnce() + Send + 'static) {
+    match THREAD_POOL.get() {
+        Some(pool) => pool.spawn(op),
+        None => rayon::spawn(op),
+    }
+}
+
+// the number of threads used for parallel verification and validation
+fn thread_count() -> usize {
+    THREAD_POOL
+        .get()
+        .map_or_else(rayon::current_num_threads, |pool| {
+            pool.current_num_threads()
+        })
+}
+
+// decode a string of hex digits (in either case) into bytes
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    // `from_str_radix` would also accept a sign
+    if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect()
+}
+
+// The HMAC we are dealing with here is the 'message-signing HMAC' and not the 'network HMAC'
+// which is used during the secret-handshake between peers (aka network identifier, app key
+// or caps key). While both use the same hashing algorithms (`HMAC-SHA-512-256`), they are
+// employed in different ways. Message signing with an HMAC is an optional feature of
+// Scuttlebutt and is not put to use in the main network. This is why a `null` or `None`
+// value is set for the message-signing HMAC when verifying main network message signatures.
+//
+// the `Ok()` variant for `Result` represents a valid hmac key value as a byte vector
+fn is_valid_hmac_key(hmac_key: HmacKey) -> Result<Option<Vec<u8>>, String> {
+    match hmac_key {
+        HmacKey::None => Ok(None),
+        // registered keys have already been validated
+        HmacKey::Handle(handle) => {
+            let keys = match HMAC_KEYS.read() {
+                Ok(keys) => keys,
+                Err(_) => return Err("hmac key registry is unavailable".to_string()),
+            };
+            let key = match handle {
+                _ if handle.fract() != 0.0 || handle < 0.0 => None,
+                _ => keys.get(handle as usize),
+            };
+            match key {
+                Some(key) => Ok(key.clone()),
+                None => Err(format!("hmac key handle invalid: {}", handle)),
+            }
+        }
+        HmacKey::Buf(hmac) => {
+            let key = MsgHmacKey::from_slice(&hmac);
+            match key {
+                None => Err(format!(
+                    "hmac key invalid: expected 32 bytes, got {}",
+                    hmac.len()
+                )),
+                Some(key_val) => {
+                    let key_bytes = key_val.as_bytes().to_vec();
+                    Ok(Some(key_bytes))
+                }
+            }
+        }
+        HmacKey::Str(hmac) => {
+            let key = MsgHmacKey::from_base64(&hmac);
+            // the string `none` is accepted in place of `null` or `undefined` for backward
+            // compatibility
+            if hmac == "none" {
+                Ok(None)
+            } else {
+                match key {
+                    // 64 hex digits never decode to 32 bytes of base64 (but to 48), so trying
+                    // hex once base64 has failed is unambiguous
+                    None if hmac.len() == 64 => {
+                        match decode_hex(&hmac) {
+                            Some(key_bytes) => Ok(Some(key_bytes)),
+                            None => Err("hmac key invalid: string must be base64 or hex encoded"
+                                .to_string()),
+                        }
+                    }
+                    // distinguish a string which is not base64 from one of the wrong length
+                    None => match base64::decode(&hmac) {
+                        Ok(bytes) => Err(format!(
+                            "hmac key invalid: expected 32 bytes, got {} (decoded from base64)",
+                            bytes.len()
+                        )),
+                        Err(_) => {
+                            Err("hmac key invalid: string must be base64 encoded".to_string())
+                        }
+                    },
+                    Some(key_val) => {
+                        let key_bytes = key_val.as_bytes().to_vec();
+                        Ok(Some(key_bytes))
+                    }
+                }
+            }
+        }
+    }
+}
+
+// the asserted timestamp of a message, if it can be parsed
+fn message_timestamp(msg: &[u8]) -> Option<f64> {
+    from_slice::<SsbMessageValue>(msg)
+        .ok()
+        .map(|value| f64::from(value.timestamp))
+}
+
+// the asserted timestamps of `previous` (if supplied) followed by those of the messages.
+// messages which cannot be parsed have no timestamp
+fn feed_timestamps(msgs: &[Vec<u8>], previous: Option<&Vec<u8>>) -> Vec<Option<f64>> {
+    previous
+        .into_iter()
+        .chain(msgs)
+        .map(|msg| message_timestamp(msg))
+        .collect()
+}
+
+// find the indices of messages with an asserted timestamp earlier than that of the message
+// preceding them in the feed (including `previous` for the first message, if supplied).
+// messages which cannot be parsed are skipped; this is only called after validation succeeds.
+// `offset` is 1 if the timestamps start with that of `previous`, and 0 otherwise
+fn timestamp_regressions(timestamps: &[Option<f64>], offset: usize) -> Vec<i64> {
+    let mut regressions = Vec::new();
+    for (idx, pair) in timestamps.windows(2).enumerate() {
+        if let [Some(last_ts), Some(current_ts)] = pair {
+            if current_ts < last_ts {
+                regressions.push((idx + 1 - offset) as i64);
+            }
+        }
+    }
+    regressions
+}
+
+// the minimum, maximum and mean difference between the timestamps of consecutive messages, if
+// there are at least two consecutive timestamps
+fn timestamp_deltas(timestamps: &[Option<f64>]) -> Option<TimestampDeltas> {
+    let deltas: Vec<f64> = timestamps
+        .windows(2)
+        .filter_map(|pair| match pair {
+            [Some(last_ts), Some(current_ts)] => Some(current_ts - last_ts),
+            _ => None,
+        })
+        .collect();
+    if deltas.is_empty() {
+        return None;
+    }
+    Some(TimestampDeltas {
+        min: deltas.iter().cloned().fold(f64::INFINITY, f64::min),
+        max: deltas.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+        mean: deltas.iter().sum::<f64>() / deltas.len() as f64,
+    })
+}
+
+// sort messages by sequence number and ensure they form a contiguous range. messages are left
+// unsorted if any of them cannot be parsed; the parse error is reported by validation instead.
+// the error holds the expected and actual sequence number at the first discontinuity
+fn sort_by_sequence(msgs: &mut Vec<Vec<u8>>) -> Result<(), (u64, u64)> {
+    let sequences: Option<Vec<u64>> = msgs
+        .iter()
+        .map(|msg| {
+            from_slice::<SsbMessageValue>(msg)
+                .ok()
+                .map(|value| value.sequence)
+        })
+        .collect();
+    let sequences = match sequences {
+        Some(sequences) => sequences,
+        None => return Ok(()),
+    };
+
+    let mut sorted: Vec<(u64, Vec<u8>)> = sequences.into_iter().zip(msgs.drain(..)).collect();
+    sorted.sort_by_key(|(sequence, _)| *sequence);
+    for pair in sorted.windows(2) {
+        // a duplicate of `u64::MAX` is left to validation to reject
+        let expected = pair[0].0.saturating_add(1);
+        if pair[1].0 != expected {
+            return Err((expected, pair[1].0));
+        }
+    }
+    msgs.extend(sorted.into_iter().map(|(_, msg)| msg));
+    Ok(())
+}
+
+// the inclusive range of sequence numbers skipped when `actual` was found where `expected` was
+// expected, if any
+fn missing_range(expected: u64, actual: u64) -> Option<(u64, u64)> {
+    if actual > expected {
+        Some((expected, actual - 1))
+    } else {
+        None
+    }
+}
+
+// the indices of the messages whose sequence number skips ahead of that of the message preceding
+// them (or of `previous_sequence`, for the first message). messages which cannot be parsed are
+// left to validation
+fn sequence_gaps(msgs: &[Vec<u8>], previous_sequence: Option<u64>) -> Vec<usize> {
+    let mut last = previous_sequence;
+    let mut gaps = Vec::new();
+    for (index, msg) in msgs.iter().enumerate() {
+        let sequence = from_slice::<SsbMessageValue>(msg)
+            .ok()
+            .map(|value| value.sequence);
+        if let (Some(last), Some(sequence)) = (last, sequence) {
+            // no sequence can follow `u64::MAX`, which is left to validation to reject
+            if matches!(last.checked_add(1), Some(next) if sequence > next) {
+                gaps.push(index);
+            }
+        }
+        last = sequence;
+    }
+    gaps
+}
+
+// describe a sequence gap for inclusion in an error message
+fn describe_gap(range: Option<(u64, u64)>) -> String {
+    match range {
+        Some((first, last)) => format!(" (missing sequence range [{}, {}])", first, last),
+        None => String::new(),
+    }
+}
+
+// encode a message key in the given format
+fn format_key(multihash: Multihash, key_format: KeyFormat) -> String {
+    match (key_format, multihash) {
+        (KeyFormat::Uri, Multihash::Message(bytes)) => format!(
+            "ssb:message/classic/{}",
+            base64::encode_config(bytes, base64::URL_SAFE)
+        ),
+        (_, multihash) => multihash.to_legacy_string(),
+    }
+}
+
+// find the messages announcing a metafeed (`type` of `metafeed/announce`), along with the id of
+// the announced metafeed (if present)
+fn migration_markers(msgs: &[Vec<u8>]) -> Vec<MigrationMarker> {
+    let mut markers = Vec::new();
+    for (index, msg) in msgs.iter().enumerate() {
+        let content = match from_slice::<SsbMessageValue>(msg) {
+            Ok(value) => value.content.0,
+            Err(_) => continue,
+        };
+        if let Value::Object(map) = content {
+            if let Some(Value::String(msg_type)) = map.get("type") {
+                if msg_type == "metafeed/announce" {
+                    let metafeed = match map.get("metafeed") {
+                        Some(Value::String(metafeed)) => Some(metafeed.to_owned()),
+                        _ => None,
+                    };
+                    markers.push(MigrationMarker { index, metafeed });
+                }
+            }
+        }
+    }
+    markers
+}
+
+// whether the base64 part of a sigil-based value (eg. `@<base64>.ed25519`) is canonical, ie. it is
+// unchanged by a decode / encode round trip
+fn is_canonical_base64(value: &str, prefix: &str, suffix: &str) -> bool {
+    let encoded = match value
+        .strip_prefix(prefix)
+        .and_then(|value| value.strip_suffix(suffix))
+    {
+        Some(encoded) => encoded,
+        None => return false,
+    };
+    match base64::decode(encoded) {
+        Ok(bytes) => base64::encode(bytes) == encoded,
+        Err(_) => false,
+    }
+}
+
+// the `author` and `signature` fields holding non-canonical base64, for each message. messages
+// which cannot be parsed, or which lack either field, are left for validation to reject
+fn non_canonical_base64(msgs: &[Vec<u8>]) -> Vec<NonCanonicalField> {
+    let mut fields = Vec::new();
+    for (index, msg) in msgs.iter().enumerate() {
+        let map = match from_slice::<Value>(msg) {
+            Ok(Value::Object(map)) => map,
+            _ => continue,
+        };
+        if let Some(Value::String(author)) = map.get("author") {
+            if !is_canonical_base64(author, "@", ".ed25519") {
+                fields.push(NonCanonicalField {
+                    index,
+                    field: "author",
+                });
+            }
+        }
+        if let Some(Value::String(signature)) = map.get("signature") {
+            if !is_canonical_base64(signature, "", ".sig.ed25519") {
+                fields.push(NonCanonicalField {
+                    index,
+                    field: "signature",
+                });
+            }
+        }
+    }
+    fields
+}
+
+// add each well-formed feed id (`@<base64>.ed25519`) found in a content value to `feed_ids`,
+// including those nested in arrays and objects
+fn collect_feed_ids(value: &Value, feed_ids: &mut BTreeSet<String>) {
+    match value {
+        Value::String(string) if string.starts_with('@') => {
+            if let Ok((_, rest)) = Multikey::from_legacy(string.as_bytes()) {
+                if rest.is_empty() {
+                    feed_ids.insert(string.to_owned());
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                collect_feed_ids(value, feed_ids);
+            }
+        }
+        Value::Object(map) => {
+            for (_, value) in map {
+                collect_feed_ids(value, feed_ids);
+            }
+        }
+        _ => (),
+    }
+}
+
+// the deduplicated feed ids referenced in the content of the given messages
+fn referenced_feed_ids(msgs: &[Vec<u8>]) -> BTreeSet<String> {
+    let mut feed_ids = BTreeSet::new();
+    for msg in msgs {
+        if let Ok(value) = from_slice::<SsbMessageValue>(msg) {
+            collect_feed_ids(&value.content.0, &mut feed_ids);
+        }
+    }
+    feed_ids
+}
+
+// add each well-formed blob id (`&<base64>.sha256`) found in a content value to `blob_ids`,
+// including those nested in arrays and objects
+fn collect_blob_ids(value: &Value, blob_ids: &mut BTreeSet<String>) {
+    match value {
+        Value::String(string) if string.starts_with('&') => {
+            if let Ok((Multihash::Blob(_), rest)) = Multihash::from_legacy(string.as_bytes()) {
+                if rest.is_empty() {
+                    blob_ids.insert(string.to_owned());
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                collect_blob_ids(value, blob_ids);
+            }
+        }
+        Value::Object(map) => {
+            for (_, value) in map {
+                collect_blob_ids(value, blob_ids);
+            }
+        }
+        _ => (),
+    }
+}
+
+// the deduplicated (and sorted) blob ids referenced in the content of each message, in order
+fn referenced_blob_ids(msgs: &[Vec<u8>]) -> Vec<Vec<String>> {
+    msgs.iter()
+        .map(|msg| {
+            let mut blob_ids = BTreeSet::new();
+            if let Ok(value) = from_slice::<SsbMessageValue>(msg) {
+                collect_blob_ids(&value.content.0, &mut blob_ids);
+            }
+            blob_ids.into_iter().collect()
+        })
+        .collect()
+}
+
+// the signature algorithm of each message, from the suffix of its `signature` field (eg.
+// `ed25519` for `<base64>.sig.ed25519`)
+fn signature_algorithms(msgs: &[Vec<u8>]) -> Vec<Option<String>> {
+    msgs.iter()
+        .map(|msg| match from_slice::<SsbMessageValue>(msg) {
+            Ok(value) => value
+                .signature
+                .rsplit_once(".sig.")
+                .map(|(_, algorithm)| algorithm.to_owned()),
+            Err(_) => None,
+        })
+        .collect()
+}
+
+// whether the content of each message is encrypted. encrypted (box or box2) content is a string
+// rather than an object
+fn encrypted_flags(msgs: &[Vec<u8>]) -> Vec<bool> {
+    msgs.iter()
+        .map(|msg| match from_slice::<SsbMessageValue>(msg) {
+            Ok(value) => matches!(value.content.0, Value::String(_)),
+            Err(_) => false,
+        })
+        .collect()
+}
+
+// the sha256 hash of the content of each message, as `<base64>.sha256`. the hash is computed over
+// the compact json encoding of object content, or over the string itself for encrypted content
+fn content_hashes(msgs: &[Vec<u8>]) -> Vec<Option<String>> {
+    msgs.iter()
+        .map(|msg| {
+            let value = from_slice::<SsbMessageValue>(msg).ok()?;
+            let bytes = match value.content.0 {
+                Value::String(ciphertext) => ciphertext.into_bytes(),
+                content => json::to_vec(&content, true).ok()?,
+            };
+            Some(format!(
+                "{}.sha256",
+                base64::encode(ssb_crypto::hash(&bytes).0)
+            ))
+        })
+        .collect()
+}
+
+// the `author` of a message, parsed leniently so that it is available even for messages which
+// fail validation
+fn message_author(msg: &[u8]) -> Option<String> {
+    match from_slice::<Value>(msg) {
+        Ok(Value::Object(map)) => match map.get("author") {
+            Some(Value::String(author)) => Some(author.to_owned()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// the author and sequence number of each message, if it can be parsed
+fn feed_positions(msgs: &[Message]) -> Vec<Option<(String, u64)>> {
+    msgs.iter()
+        .map(|msg| {
+            from_slice::<SsbMessageValue>(msg.as_bytes())
+                .ok()
+                .map(|value| (value.author, value.sequence))
+        })
+        .collect()
+}
+
+// check the hash chain of the messages of each author in the batch, ordered by sequence number.
+// the first message of an author is only checked against its previous message if it is the first
+// message of the feed. returns the index, error message and error code of the first offending
+// message (in order of author) otherwise
+fn check_author_chains(msgs: &[Vec<u8>]) -> Result<(), (usize, String, &'static str)> {
+    let mut chains: BTreeMap<String, Vec<(u64, usize)>> = BTreeMap::new();
+    for (index, msg) in msgs.iter().enumerate() {
+        // the messages have been validated, so they can be parsed
+        if let Ok(value) = from_slice::<SsbMessageValue>(msg) {
+            chains
+                .entry(value.author)
+                .or_default()
+                .push((value.sequence, index));
+        }
+    }
+    let chains: Vec<Vec<(u64, usize)>> = chains
+        .into_values()
+        .map(|mut chain| {
+            chain.sort_unstable();
+            chain
+        })
+        .collect();
+
+    let failure = in_thread_pool(|| {
+        chains.par_iter().find_map_first(|chain| {
+            let genesis = match chain.first() {
+                Some(&(1, index)) => Some((index, None)),
+                _ => None,
+            };
+            let links = chain
+                .windows(2)
+                .map(|pair| (pair[1].1, Some(msgs[pair[0].1].as_slice())));
+            genesis
+                .into_iter()
+                .chain(links)
+                .find_map(|(index, previous)| {
+                    validate_message_value_hash_chain(&msgs[index], previous)
+                        .err()
+                        .map(|e| (index, link_failure_reason(&e, &msgs[index], previous), e))
+                })
+        })
+    });
+    match failure {
+        Some((index, reason, e)) => {
+            let err_msg = format!(
+                "found invalid message at index {}: {}: {}",
+                index,
+                reason,
+                message_str(&msgs[index])
+            );
+            Err((index, err_msg, validation_error_code(&e)))
+        }
+        None => Ok(()),
+    }
+}
+
+// the message with the highest sequence number of each author, given the position and key of
+// each message
+fn feed_tips(positions: Vec<Option<(String, u64)>>, keys: &[String]) -> FeedTips {
+    let mut tips: BTreeMap<String, FeedTip> = BTreeMap::new();
+    for (position, key) in positions.into_iter().zip(keys) {
+        let (author, sequence) = match position {
+            Some(position) => position,
+            None => continue,
+        };
+        match tips.get(&author) {
+            Some(tip) if tip.sequence >= sequence => (),
+            _ => {
+                let key = key.to_owned();
+                tips.insert(author, FeedTip { key, sequence });
+            }
+        }
+    }
+    FeedTips(tips)
+}
+
+// the maximum nesting depth of the arrays and objects in a json document. the bytes are scanned
+// rather than parsed, so that pathologically nested input cannot exhaust the stack
+fn json_depth(msg: &[u8]) -> usize {
+    let mut depth = 0usize;
+    let mut max_depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for &byte in msg {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                max_depth = max_depth.max(depth);
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+    }
+    max_depth
+}
+
+// check a message against the `maxDepth` and `maxContentBytes` limits, returning a description of
+// the first limit it exceeds. the depth of the content is that of the message minus the
+// enclosing message object
+fn exceeded_content_limit(msg: &[u8], options: &BatchOptions) -> Option<String> {
+    if let Some(max_depth) = options.max_depth {
+        let depth = json_depth(msg).saturating_sub(1);
+        if depth > max_depth {
+            return Some(format!(
+                "content nesting depth of {} exceeds the maximum of {}",
+                depth, max_depth
+            ));
+        }
+    }
+    if let Some(max_content_bytes) = options.max_content_bytes {
+        // messages which cannot be parsed are left for validation to reject
+        if let Ok(value) = from_slice::<SsbMessageValue>(msg) {
+            let content_bytes = json::to_vec(&value.content.0, true).map_or(0, |c| c.len());
+            if content_bytes > max_content_bytes {
+                return Some(format!(
+                    "content size of {} bytes exceeds the maximum of {} bytes",
+                    content_bytes, max_content_bytes
+                ));
+            }
+        }
+    }
+    None
+}
+
+// the content `type` of each message, if it has one. encrypted messages (with `string` content)
+// do not have a type
+fn content_types(msgs: &[Vec<u8>]) -> Vec<Option<String>> {
+    msgs.iter()
+        .map(|msg| match from_slice::<SsbMessageValue>(msg) {
+            Ok(value) => match value.content.0 {
+                Value::Object(map) => match map.get("type") {
+                    Some(Value::String(msg_type)) => Some(msg_type.to_owned()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            Err(_) => None,
+        })
+        .collect()
+}
+
+// group message keys by the content type of the corresponding message
+fn bucket_by_type(types: Vec<Option<String>>, keys: &[String]) -> BTreeMap<String, Vec<String>> {
+    let mut buckets: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (msg_type, key) in types.into_iter().zip(keys) {
+        if let Some(msg_type) = msg_type {
+            buckets.entry(msg_type).or_default().push(key.to_owned());
+        }
+    }
+    buckets
+}
+
+// check the key of the first message of a feed (sequence 1) against the expected key, in either
+// the legacy or the uri format. batches which do not start at sequence 1 are not checked
+fn check_genesis_key(msgs: &[Vec<u8>], expected_key: &str) -> Result<(), String> {
+    let first = match msgs.first() {
+        Some(first) => first,
+        None => return Ok(()),
+    };
+    if !matches!(from_slice::<SsbMessageValue>(first), Ok(value) if value.sequence == 1) {
+        return Ok(());
+    }
+    let multihash = utils::multihash_from_bytes(first);
+    let legacy_key = format_key(multihash.clone(), KeyFormat::Legacy);
+    if expected_key == legacy_key || expected_key == format_key(multihash, KeyFormat::Uri) {
+        Ok(())
+    } else {
+        Err(format!(
+            "genesis message key mismatch: expected {} but found {}",
+            expected_key, legacy_key
+        ))
+    }
+}
+
+// the keys of the messages with a deprecated content type, in order
+fn deprecated_messages(
+    types: &[Option<String>],
+    deprecated_types: &[String],
+    keys: &[String],
+) -> Vec<DeprecatedMessage> {
+    types
+        .iter()
+        .zip(keys)
+        .filter_map(|(msg_type, key)| match msg_type {
+            Some(msg_type) if deprecated_types.contains(msg_type) => Some(DeprecatedMessage {
+                key: key.to_owned(),
+                msg_type: msg_type.to_owned(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+// the messages with a content type which is not allowed, in order. messages without a type
+// (encrypted messages) are not checked
+fn disallowed_messages(msgs: &[Vec<u8>], allowed_types: &[String]) -> Vec<DisallowedMessage> {
+    content_types(msgs)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, msg_type)| match msg_type {
+            Some(msg_type) if !allowed_types.contains(&msg_type) => {
+                Some(DisallowedMessage { index, msg_type })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// find the first message by a revoked author at or beyond the revocation sequence, returning its
+// index along with the error message
+fn check_revoked(msgs: &[Vec<u8>], revoked_keys: &[(String, i64)]) -> Result<(), (usize, String)> {
+    for (index, msg) in msgs.iter().enumerate() {
+        // messages which cannot be parsed are left for validation to reject
+        let value = match from_slice::<SsbMessageValue>(msg) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        let revoked = revoked_keys.iter().find(|(author, sequence)| {
+            *author == value.author
+                && i64::try_from(value.sequence).map_or(true, |s| s >= *sequence)
+        });
+        if let Some((_, sequence)) = revoked {
+            let invalid_msg_str = message_str(msg);
+            let err_msg = format!(
+                "found invalid message at index {}: author revoked at sequence {}: {}",
+                index, sequence, invalid_msg_str
+            );
+            return Err((index, err_msg));
+        }
+    }
+    Ok(())
+}
+
+// check that `msg` directly follows the message with the given key and sequence number, returning
+// the error message and additional information otherwise
+fn check_previous_link(
+    msg: &[u8],
+    key: &str,
+    sequence: i64,
+) -> Result<(), Box<(String, BatchInfo)>> {
+    let invalid_msg_str = message_str(msg);
+    let failure = |err_msg: String, code: &'static str, gap: Option<(u64, u64)>| {
+        let info = BatchInfo {
+            missing_range: gap,
+            invalid_index: Some(0),
+            error_code: Some(code),
+            ..BatchInfo::default()
+        };
+        Err(Box::new((err_msg, info)))
+    };
+    if let Err(e) = validate_ooo_message_value_hash_chain::<_, &[u8]>(msg, None) {
+        let err_msg = format!(
+            "found invalid message: {}: {}",
+            link_failure_reason(&e, msg, None),
+            invalid_msg_str
+        );
+        return failure(err_msg, validation_error_code(&e), None);
+    }
+    // the message has been validated, so it can be parsed
+    let value = match from_slice::<SsbMessageValue>(msg) {
+        Ok(value) => value,
+        Err(e) => {
+            let err_msg = format!("found invalid message: {}: {}", e, invalid_msg_str);
+            return failure(err_msg, "INVALID_MESSAGE", None);
+        }
+    };
+    let expected = sequence.saturating_add(1);
+    if i64::try_from(value.sequence) != Ok(expected) {
+        let gap = u64::try_from(expected)
+            .ok()
+            .and_then(|expected| missing_range(expected, value.sequence));
+        let err_msg = format!(
+            "found invalid message: expected sequence {} but found {}{}: {}",
+            expected,
+            value.sequence,
+            describe_gap(gap),
+            invalid_msg_str
+        );
+        return failure(err_msg, "INVALID_SEQUENCE", gap);
+    }
+    let previous = value.previous.map(|previous| previous.to_legacy_string());
+    if previous.as_deref() != Some(key) {
+        let err_msg = format!(
+            "found invalid message: {}: {}",
+            broken_link_reason("previous message does not match", msg, key),
+            invalid_msg_str
+        );
+        return failure(err_msg, "FORKED_FEED", None);
+    }
+    Ok(())
+}
+
+// check that every message is by `expected_author`, returning the index of the first message by
+// any other author and the error message
+fn check_expected_author(msgs: &[Vec<u8>], expected_author: &str) -> Result<(), (usize, String)> {
+    for (index, msg) in msgs.iter().enumerate() {
+        // messages without an author are left for validation to reject
+        match message_author(msg) {
+            Some(author) if author != expected_author => {
+                let invalid_msg_str = message_str(msg);
+                let err_msg = format!(
+                    "found invalid message at index {}: author {} does not match the expected author {}: {}",
+                    index, author, expected_author, invalid_msg_str
+                );
+                return Err((index, err_msg));
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+// the raw (32 byte) sha256 digest of a multihash
+fn multihash_digest(multihash: &Multihash) -> [u8; 32] {
+    match multihash {
+        Multihash::Message(hash) | Multihash::Blob(hash) => *hash,
+    }
+}
+
+// the raw digests of message keys in legacy format. the keys are computed by this crate, so
+// they are always well-formed
+fn legacy_key_digests(keys: Vec<String>) -> Vec<[u8; 32]> {
+    keys.iter()
+        .filter_map(|key| Multihash::from_legacy(key.as_bytes()).ok())
+        .map(|(multihash, _)| multihash_digest(&multihash))
+        .collect()
+}
+
+// pack the result of a batch function into an envelope: a status byte (`0` for success and `1`
+// for failure), a little-endian u32 count and either the packed key digests or the error message
+fn encode_envelope(result: Result<Vec<[u8; 32]>, String>) -> ArrayBuffer {
+    let buf = match result {
+        Ok(digests) => {
+            let mut buf = Vec::with_capacity(5 + digests.len() * 32);
+            buf.push(0);
+            buf.extend_from_slice(&(digests.len() as u32).to_le_bytes());
+            for digest in digests {
+                buf.extend_from_slice(&digest);
+            }
+            buf
+        }
+        Err(err_msg) => {
+            let mut buf = Vec::with_capacity(5 + err_msg.len());
+            buf.push(1);
+            buf.extend_from_slice(&(err_msg.len() as u32).to_le_bytes());
+            buf.extend_from_slice(err_msg.as_bytes());
+            buf
+        }
+    };
+    ArrayBuffer::new(buf)
+}
+
+// sha256 digest over the concatenated (32 byte) message hashes, in order, as base64
+fn batch_digest(multihashes: &[Multihash]) -> String {
+    let mut bytes = Vec::with_capacity(multihashes.len() * 32);
+    for multihash in multihashes {
+        bytes.extend_from_slice(&multihash_digest(multihash));
+    }
+    base64::encode(ssb_crypto::hash(&bytes).0)
+}
+
+// compute the keys of the given messages in parallel
+fn hash(msgs: Vec<Vec<u8>>, key_format: KeyFormat) -> Vec<String> {
+    in_thread_pool(|| {
+        msgs.par_iter()
+            .map(|msg| format_key(utils::multihash_from_bytes(msg), key_format))
+            .collect()
+    })
+}
+
+/// Verify signatures for an array of messages (includes HMAC key support).
+///
+/// Takes an HMAC key as the first argument, an array of messages as the second argument and an
+/// options object as the third argument. The HMAC key must be of type `string` or `ArrayBuffer`.
+/// Message signatures are verified without an HMAC key if the value of the argument is `null`,
+/// `undefined` or the `string` value `none`. The `keyFormat` option selects the format of the
+/// returned keys (see `validateBatch`).
+///
+/// Each signature is verified over the message value with the `signature` field removed (as per
+/// the SSB spec); a signature computed over bytes which include a `signature` field is invalid.
+///
+/// The public key used to verify each signature is taken from the `author` field of the message,
+/// so a message signed by one key but claiming another author fails verification with an invalid
+/// signature. (Ed25519 signatures do not identify their signing key, so such a message cannot be
+/// told apart from one whose content was tampered with.)
+///
+/// If verification fails, the cause of the error is returned along with the offending message.
+/// Note: this method only verifies message signatures; it does not perform full message validation
+/// (use `verify_validate_message_array` for complete verification and validation).
+#[node_bindgen(name = "verifySignatures")]
+fn verify_messages(
+    hmac_key: HmacKey,
+    array: Messages,
+    options: BatchOptions,
+) -> (Option<String>, Option<Vec<String>>) {
+    let valid_hmac = match is_valid_hmac_key(hmac_key) {
+        Ok(key) => key,
+        Err(err_msg) => return (Some(err_msg), None),
+    };
+    let key_format = match options.key_format() {
+        Ok(format) => format,
+        Err(err_msg) => return (Some(err_msg), None),
+    };
+    let hmac = valid_hmac.as_deref();
+
+    let mut msgs = Vec::with_capacity(array.len());
+    for msg in array {
+        let msg_bytes = msg.into_bytes();
+        msgs.push(msg_bytes)
+    }
+
+    // attempt batch verification and match on error to find invalid message value
+    match in_thread_pool(|| par_verify_message_values(&msgs, hmac, None)) {
+        Ok(_) => (),
+        Err(e) => {
+            let invalid_msg = &msgs
+                .iter()
+                .find(|msg| verify_message_value(msg, hmac).is_err());
+            let invalid_msg_str = match invalid_msg {
+                Some(msg) => message_str(msg),
+                None => "parallel verification failed but no single invalid message was found",
+            };
+            let reason =
+                verification_failure_reason(&e, invalid_msg.map(|msg| msg.as_slice()), hmac);
+            let err_msg = format!(
+                "found invalid message: {}{}: {}",
+                reason,
+                caps_key_hint(&msgs, hmac),
+                invalid_msg_str
+            );
+            return (Some(err_msg), None);
+        }
+    }
+
+    let keys = hash(msgs, key_format);
+    (None, Some(keys))
+}
+
+/// Count the invalid signatures in an array of messages (includes HMAC key support).
+///
+/// Takes an HMAC key as the first argument and an array of messages as the second argument (as
+/// accepted by `verifySignatures`). Unlike `verifySignatures`, verification does not stop at the
+/// first invalid message: every signature is verified in parallel and the failures are tallied.
+/// Note: this method only verifies message signatures; it does not perform full message
+/// validation.
+///
+/// The return type is a tuple of the error message (if the HMAC key is invalid) and an object of
+/// shape `{ total, invalid }`.
+#[node_bindgen(name = "countInvalidSignatures")]
+fn count_invalid_signatures(
+    hmac_key: HmacKey,
+    array: Messages,
+) -> (Option<String>, Option<SignatureCount>) {
+    let valid_hmac = match is_valid_hmac_key(hmac_key) {
+        Ok(key) => key,
+        Err(err_msg) => return (Some(err_msg), None),
+    };
+    let hmac = valid_hmac.as_deref();
+
+    let invalid = in_thread_pool(|| {
+        array
+            .par_iter()
+            .filter(|msg| verify_message_value(msg.as_bytes(), hmac).is_err())
+            .count()
+    });
+    let count = SignatureCount {
+        total: array.len(),
+        invalid,
+    };
+    (None, Some(count))
+}
+
+/// Verify the signature of each message in an array independently (includes HMAC key support).
+///
+/// Takes an HMAC key as the first argument and an array of messages as the second argument (as
+/// accepted by `verifySignatures`). Unlike `verifySignatures`, verification does not stop at the
+/// first invalid message: every signature is verified in parallel. Note: this method only
+/// verifies message signatures; it does not perform full message validation.
+///
+/// The return type is a tuple of the error message (if the HMAC key is invalid) and an array of
+/// `{ index, valid, key, code, error }` objects in the order of the input, where `key` (the key of
+/// the message in legacy encoding) is only set for a valid signature and `code` (eg.
+/// `INVALID_SIGNATURE`) and `error` (the reason) are only set for an invalid signature. The keys
+/// of the verified messages are computed in the same pass, so they may be indexed right away.
+#[node_bindgen(name = "verifySignaturesPartial")]
+fn verify_messages_partial(
+    hmac_key: HmacKey,
+    array: Messages,
+) -> (Option<String>, Option<Vec<SignatureResult>>) {
+    let valid_hmac = match is_valid_hmac_key(hmac_key) {
+        Ok(key) => key,
+        Err(err_msg) => return (Some(err_msg), None),
+    };
+    let hmac = valid_hmac.as_deref();
+
+    let results = in_thread_pool(|| {
+        array
+            .par_iter()
+            .enumerate()
+            .map(|(index, msg)| {
+                let msg = msg.as_bytes();
+                let outcome = match verify_message_value(msg, hmac) {
+                    Ok(_) => Ok(utils::multihash_from_bytes(msg).to_legacy_string()),
+                    Err(e) => {
+                        let reason = verification_failure_reason(&e, Some(msg), hmac);
+                        Err((verification_error_code(&e), reason))
+                    }
+                };
+                SignatureResult { index, outcome }
+            })
+            .collect()
+    });
+    (None, Some(results))
+}
+
+/// Verify signatures and perform validation for an array of messages, each with its own HMAC key.
+///
+/// Takes an array of messages as the first argument, an array of HMAC keys (one per message, each
+/// as accepted by `validateSingle`) as the second argument and an options object as the third
+/// argument. The `keyFormat` option selects the format of the returned keys (see
+/// `validateBatch`). Each message is verified with its own HMAC key and validated standalone (as
+/// with `validateOOOBatch`), in parallel, since messages signed under different caps generally
+/// belong to different feeds. This supports archive tools that merge messages from several
+/// networks.
+///
+/// The return type is a tuple of the error message (if the options are invalid or the arrays
+/// differ in length) and an array of `{ index, valid, key, code, error }` objects in the order of
+/// the input, as returned by `verifySignaturesPartial`. An invalid HMAC key only fails its own
+/// message, with the code `INVALID_HMAC_KEY`.
+#[node_bindgen(name = "validateBatchMixedHmac")]
+fn verify_validate_messages_mixed_hmac(
+    array: Messages,
+    hmac_keys: Vec<HmacKey>,
+    options: BatchOptions,
+) -> (Option<String>, Option<Vec<SignatureResult>>) {
+    let key_format = match options.key_format() {
+        Ok(format) => format,
+        Err(err_msg) => return (Some(err_msg), None),
+    };
+    if array.len() != hmac_keys.len() {
+        let err_msg = format!(
+            "expected one hmac key per message but got {} messages and {} hmac keys",
+            array.len(),
+            hmac_keys.len()
+        );
+        return (Some(err_msg), None);
+    }
+    let hmac_keys: Vec<Result<Option<Vec<u8>>, String>> =
+        hmac_keys.into_iter().map(is_valid_hmac_key).collect();
+
+    let results = in_thread_pool(|| {
+        array
+            .par_iter()
+            .zip(hmac_keys.par_iter())
+            .enumerate()
+            .map(|(index, (msg, hmac))| {
+                let msg = msg.as_bytes();
+                let outcome = match hmac {
+                    Ok(hmac) => {
+                        let hmac = hmac.as_deref();
+                        verify_message_value(msg, hmac)
+                            .map_err(|e| {
+                                let reason = verification_failure_reason(&e, Some(msg), hmac);
+                                (verification_error_code(&e), reason)
+                            })
+                            .and_then(|_| {
+                                validate_ooo_message_value_hash_chain::<_, &[u8]>(msg, None)
+                                    .map_err(|e| {
+                                        let reason = link_failure_reason(&e, msg, None);
+                                        (validation_error_code(&e), reason)
+                                    })
+                            })
+                            .map(|_| format_key(utils::multihash_from_bytes(msg), key_format))
+                    }
+                    Err(err_msg) => Err(("INVALID_HMAC_KEY", err_msg.to_owned())),
+                };
+                SignatureResult { index, outcome }
+            })
+            .collect()
+    });
+    (None, Some(results))
+}
+
+/// Compute the keys for an array of message values, without any verification or validation.
+///
+/// Takes an array of messages as the first argument and an options object as the second
+/// argument. The `keyFormat` option selects the format of the returned keys (see
+/// `validateBatch`), including `both` and `raw`. Keys are computed in parallel. This is intended
+/// for messages which are already trusted (eg. when re-indexing a local store).
+///
+/// The return type is a tuple of the error message (if the options are invalid or an element
+/// cannot be parsed as a message value) and the keys.
+#[node_bindgen(name = "getMessageKeys")]
+fn get_message_keys(array: Messages, options: BatchOptions) -> (Option<String>, Option<BatchKeys>) {
+    let msgs: Vec<Vec<u8>> = array.into_iter().map(|msg| msg.into_bytes()).collect();
+    // only message values have a key, as for `computeMessageKey`
+    let invalid = in_thread_pool(|| {
+        msgs.par_iter().enumerate().find_map_first(|(index, msg)| {
+            from_slice::<SsbMessageValue>(msg)
+                .err()
+                .map(|e| (index, e.to_string()))
+        })
+    });
+    if let Some((index, reason)) = invalid {
+        let err_msg = format!("message at index {} invalid: {}", index, reason);
+        return (Some(err_msg), None);
+    }
+    if options.both_key_formats() {
+        let pairs = in_thread_pool(|| {
+            msgs.par_iter()
+                .map(|msg| {
+                    let multihash = utils::multihash_from_bytes(msg);
+                    (
+                        format_key(multihash.clone(), KeyFormat::Legacy),
+                        format_key(multihash, KeyFormat::Uri),
+                    )
+                })
+                .collect()
+        });
+        return (None, Some(BatchKeys::Both(pairs)));
+    }
+    if options.raw_keys() {
+        let digests = in_thread_pool(|| {
+            msgs.par_iter()
+                .map(|msg| multihash_digest(&utils::multihash_from_bytes(msg)))
+                .collect()
+        });
+        return (None, Some(BatchKeys::Raw(digests)));
+    }
+
+    let key_format = match options.key_format() {
+        Ok(format) => format,
+        Err(err_msg) => return (Some(err_msg), None),
+    };
+    (None, Some(BatchKeys::Single(hash(msgs, key_format))))
+}
+
+/// Compute the key of a single message value, without any verification or validation.
+///
+/// Takes a message `value` as the only argument. The return type is a tuple of the error message
+/// (if the value cannot be parsed as a message value) and its key in legacy encoding (eg.
+/// `%<base64>.sha256`), as returned by `validateSingle`. This is intended for a message which is
+/// already trusted (eg. when re-indexing a local, already validated log).
+#[node_bindgen(name = "computeMessageKey")]
+fn compute_message_key(msg_value: String) -> (Option<String>, Option<String>) {
+    if let Err(e) = from_slice::<SsbMessageValue>(msg_value.as_bytes()) {
+        return (Some(format!("message value invalid: {}", e)), None);
+    }
+    let key = utils::multihash_from_bytes(msg_value.as_bytes()).to_legacy_string();
+    (None, Some(key))
+}
+
+/// Extract the author (feed id) of a single message value, without any verification or
+/// validation.
+///
+/// Takes a message `value` as the only argument. The return type is a tuple of the error message
+/// (if the value cannot be parsed or its `author` field is missing or not a well-formed feed id,
+/// eg. `@<base64>.ed25519`) and the author.
+#[node_bindgen(name = "extractAuthor")]
+fn extract_author(msg_value: String) -> (Option<String>, Option<String>) {
+    let author = match message_author(msg_value.as_bytes()) {
+        Some(author) => author,
+        None => return (Some("message has no author field".to_string()), None),
+    };
+    match Multikey::from_legacy(author.as_bytes()) {
+        Ok((_, [])) => (None, Some(author)),
+        _ => (
+            Some(format!("message author is not a valid feed id: {}", author)),
+            None,
+        ),
+    }
+}
+
+/// Encode a single message value the way it is encoded for hashing and signature verification.
+///
+/// Takes a message `value` as the only argument. The message is parsed and re-encoded in the
+/// canonical legacy format (two-space indentation, in field order). The return type is a tuple of
+/// the error message (if the value cannot be parsed), the canonical encoding of the whole value
+/// (which a valid message must match character for character) and the encoding without the
+/// `signature` field, which is the data signed by the author. Comparing these against the
+/// encoding produced by a client reveals whitespace or field order mismatches.
+///
+/// The key of a message is not the hash of the utf8 bytes of the canonical encoding: it is the
+/// sha256 hash of its utf16 code units truncated to one byte each (node's `binary` encoding, ie.
+/// `Buffer.from(value, "binary")`). Both agree only for ascii content.
+#[node_bindgen(name = "canonicalizeMessage")]
+fn canonicalize_message(msg_value: String) -> (Option<String>, Option<String>, Option<String>) {
+    let mut value = match from_slice::<Value>(msg_value.as_bytes()) {
+        Ok(value) => value,
+        Err(e) => return (Some(format!("message value invalid: {}", e)), None, None),
+    };
+    let canonical = match json::to_string(&value, false) {
+        Ok(canonical) => canonical,
+        Err(e) => return (Some(format!("message value invalid: {}", e)), None, None),
+    };
+    if let Value::Object(ref mut map) = value {
+        map.remove("signature".to_owned());
+    }
+    match json::to_string(&value, false) {
+        Ok(signing) => (None, Some(canonical), Some(signing)),
+        Err(e) => (Some(format!("message value invalid: {}", e)), None, None),
+    }
+}
+
+/// Detect the feed format of a single message value, without any verification or validation.
+///
+/// Takes a message `value` as the only argument. The format is derived from the feed id in the
+/// `author` field, given either in sigil form (eg. `@<base64>.ed25519`) or as an SSB URI (eg.
+/// `ssb:feed/bendybutt-v1/<base64>`). The return type is a tuple of the error message (if the
+/// value cannot be parsed, has no `author` field or the format is not recognised) and the format:
+/// `classic`, `bendybutt-v1` or `gabbygrove-v1`. Only `classic` messages are supported by the
+/// validation functions of this module.
+#[node_bindgen(name = "detectFeedFormat")]
+fn detect_feed_format(msg_value: String) -> (Option<String>, Option<String>) {
+    let author = match message_author(msg_value.as_bytes()) {
+        Some(author) => author,
+        None => return (Some("message has no author field".to_string()), None),
+    };
+    let format = match author.strip_prefix("ssb:feed/") {
+        Some(uri) => uri.split('/').next().and_then(|format| match format {
+            "ed25519" | "classic" => Some("classic"),
+            "bendybutt-v1" => Some("bendybutt-v1"),
+            "gabbygrove-v1" => Some("gabbygrove-v1"),
+            _ => None,
+        }),
+        None if author.starts_with('@') => match author.rsplit_once('.') {
+            Some((_, "ed25519")) => Some("classic"),
+            Some((_, "bbfeed-v1")) => Some("bendybutt-v1"),
+            Some((_, "ggfeed-v1")) => Some("gabbygrove-v1"),
+            _ => None,
+        },
+        None => None,
+    };
+    match format {
+        Some(format) => (None, Some(format.to_string())),
+        None => (
+            Some(format!("unrecognised feed format of author: {}", author)),
+            None,
+        ),
+    }
+}
+
+/// Validate the hash-chain link between two adjacent message values, without any signature
+/// verification.
+///
+/// Takes the previous message `value` as the first argument and the message `value` as the second
+/// argument. The message is validated against the previous message as in `validateSingle`: its
+/// sequence number must be one greater than that of the previous message and its `previous` field
+/// must hold the key of the previous message (among the other checks of message validation).
+///
+/// The return type is a tuple of the error message (if the link is invalid), the key of the
+/// message in legacy encoding and the error code.
+#[node_bindgen(name = "validateLink")]
+fn validate_link(
+    previous: String,
+    msg_value: String,
+) -> (Option<String>, Option<String>, Option<String>) {
+    let msg_bytes = msg_value.into_bytes();
+    if let Err(e) = validate_message_value_hash_chain(&msg_bytes, Some(previous.as_bytes())) {
+        let err_msg = format!(
+            "found invalid message: {}: {}",
+            link_failure_reason(&e, &msg_bytes, Some(previous.as_bytes())),
+            message_str(&msg_bytes)
+        );
+        return (
+            Some(err_msg),
+            None,
+            Some(validation_error_code(&e).to_string()),
+        );
+    }
+
+    let key = utils::multihash_from_bytes(&msg_bytes).to_legacy_string();
+    (None, Some(key), None)
+}
+
+/// Check the structure of a single message value, without any signature verification or hashing.
+///
+/// Takes a message value as the only argument. The value must be a JSON object holding the seven
+/// required fields with the expected types: `previous` (`string` or `null`), `author`, `hash` and
+/// `signature` (`string`), `sequence` and `timestamp` (`number`) and `content` (`object` or
+/// `string`). This is a cheap filter for obviously malformed messages; a message with a valid
+/// structure may still fail full validation.
+///
+/// The return type is a tuple of the error message and the name of the offending field (if the
+/// structure is invalid).
+#[node_bindgen(name = "validateStructure")]
+fn validate_structure(msg_value: String) -> (Option<String>, Option<String>) {
+    let map = match from_slice::<Value>(msg_value.as_bytes()) {
+        Ok(Value::Object(map)) => map,
+        Ok(_) => return (Some("message value must be an object".to_string()), None),
+        Err(e) => {
+            return (
+                Some(format!("message value is not valid json: {}", e)),
+                None,
+            )
+        }
+    };
+    let fields = [
+        "previous",
+        "author",
+        "sequence",
+        "timestamp",
+        "hash",
+        "content",
+        "signature",
+    ];
+    for field in fields.iter() {
+        let value = match map.get(field) {
+            Some(value) => value,
+            None => {
+                let err_msg = format!("message field `{}` is missing", field);
+                return (Some(err_msg), Some(field.to_string()));
+            }
+        };
+        let (expected, has_type) = match *field {
+            "previous" => (
+                "a string or null",
+                matches!(value, Value::String(_) | Value::Null),
+            ),
+            "sequence" | "timestamp" => ("a number", matches!(value, Value::Float(_))),
+            "content" => (
+                "an object or a string",
+                matches!(value, Value::Object(_) | Value::String(_)),
+            ),
+            _ => ("a string", matches!(value, Value::String(_))),
+        };
+        if !has_type {
+            let err_msg = format!("message field `{}` must be {}", field, expected);
+            return (Some(err_msg), Some(field.to_string()));
+        }
+    }
+    (None, None)
+}
+
+/// Verify signature and perform validation for a single message value (includes HMAC key support).
+///
+/// Takes an HMAC key as the first argument, message `value` as the second argument, an options
+/// object as the third argument and an optional previous message `value` as the fourth argument.
+/// The `keyFormat` option selects the format of the returned key (`legacy` or `uri`, see
+/// `validateBatch`). The HMAC key must be of type `string` or `ArrayBuffer`. Message signatures are
+/// verified without an HMAC key if the value of the argument is `null`, `undefined` or the `string`
+/// value `none`. The previous message argument is expected when the message to be validated is not
+/// the first in the feed (ie. sequence number != 1 and previous != null).
+///
+/// The return type is a tuple of `Option<String>`. The first element of the tuple holds the key
+/// (hash) of `msg_value` (if validation is successful) while the second element holds the error
+/// messages (if validation fails). Only the key for `msg_value` is returned; the key for `previous`
+/// is not.
+///
+/// Successful validation will yield a return value of `(Some<key>, None)` - where `key` is of type
+/// `String`. Unsuccessful validation will yield a return value of `(None, Some<err_msg>)` - where
+/// `err_msg` is of type `String` and includes the cause of the error and the offending message.
+///
+/// A third element holds a stable, machine-readable code for the cause of the error (eg.
+/// `INVALID_SIGNATURE`, `FORKED_FEED` or `INVALID_HMAC_KEY`), which does not change across
+/// versions the way the wording of the error message may.
+#[node_bindgen(name = "validateSingle")]
+fn verify_validate_message(
+    hmac_key: HmacKey,
+    msg_value: String,
+    options: BatchOptions,
+    previous: Option<String>,
+) -> (Option<String>, Option<String>, Option<String>) {
+    let key_format = match options.key_format() {
+        Ok(format) => format,
+        Err(err_msg) => return (Some(err_msg), None, Some("INVALID_OPTIONS".to_string())),
+    };
+    let (err_msg, key, code) = validate_single(hmac_key, msg_value, previous, key_format);
+    (err_msg, key, code.map(str::to_owned))
+}
+
+/// Perform the same work as `validateSingle` on a background thread, so that the node event loop
+/// is not blocked by the verification and validation. The work is run on the thread pool used for
+/// parallel verification (see `setMaxThreads`), so concurrent calls do not each spawn a thread.
+///
+/// Takes an HMAC key as the first argument, message `value` as the second argument, an options
+/// object as the third argument (supporting `keyFormat`, as in `validateSingle`), a previous
+/// message `value` as the fourth argument (the `string` value `none` if there is no previous
+/// message) and a callback as the fifth argument. The callback is invoked once with the elements
+/// of the tuple returned by `validateSingle`: the error message, the key and the error code.
+#[node_bindgen(name = "validateSingleBackground", mt)]
+fn verify_validate_message_background<
+    F: Fn(Option<String>, Option<String>, Option<String>) + Send + 'static,
+>(
+    hmac_key: HmacKey,
+    msg_value: String,
+    options: BatchOptions,
+    previous: String,
+    cb: F,
+) {
+    let key_format = match options.key_format() {
+        Ok(format) => format,
+        Err(err_msg) => return cb(Some(err_msg), None, Some("INVALID_OPTIONS".to_string())),
+    };
+    let previous = match previous.as_str() {
+        "none" => None,
+        _ => Some(previous),
+    };
+    spawn_in_thread_pool(move || {
+        let (err_msg, key, code) = validate_single(hmac_key, msg_value, previous, key_format);
+        cb(err_msg, key, code.map(str::to_owned))
+    });
+}
+
+/// Perform the same work as `validateSingle`, returning the `author`, `sequence` and `previous`
+/// fields of the message along with its key.
+///
+/// Takes the same arguments as `validateSingle`. The return type matches that of `validateSingle`,
+/// except that the key is replaced by an object of shape `{ key, author, sequence, previous }`
+/// (`previous` is `null` for the first message of a feed). This saves parsing the message again
+/// in js to index it.
+#[node_bindgen(name = "validateSingleWithMeta")]
+fn verify_validate_message_with_meta(
+    hmac_key: HmacKey,
+    msg_value: String,
+    previous: Option<String>,
+) -> (Option<String>, Option<MessageMeta>, Option<String>) {
+    let msg_bytes = msg_value.as_bytes().to_vec();
+    let (err_msg, key, code) = validate_single(hmac_key, msg_value, previous, KeyFormat::Legacy);
+    let meta = key.and_then(|key| {
+        // the message has just been validated, so it can be parsed
+        from_slice::<SsbMessageValue>(&msg_bytes)
+            .ok()
+            .map(|value| MessageMeta {
+                key,
+                author: value.author,
+                sequence: value.sequence,
+                previous: value.previous.map(|previous| previous.to_legacy_string()),
+            })
+    });
+    (err_msg, meta, code.map(str::to_owned))
+}
+
+// shared implementation of `validateSingle`; exported functions cannot be called from rust.
+//
+// the message is parsed once by `verify_message_value` and again by
+// `validate_message_value_hash_chain`. both only accept the encoded message (the signature is
+// verified over the exact bytes and the length limit applies to them), and neither crate exposes
+// a way to pass in an already parsed value, so the parse cannot be shared without changes to
+// `ssb-verify-signatures` and `ssb-validate`
+fn validate_single(
+    hmac_key: HmacKey,
+    msg_value: String,
+    previous: Option<String>,
+    key_format: KeyFormat,
+) -> (Option<String>, Option<String>, Option<&'static str>) {
+    let valid_hmac = match is_valid_hmac_key(hmac_key) {
+        Ok(key) => key,
+        Err(err_msg) => return (Some(err_msg), None, Some("INVALID_HMAC_KEY")),
+    };
+    let hmac = valid_hmac.as_deref();
+
+    let msg_bytes = msg_value.into_bytes();
+    let previous_msg_bytes = previous.map(|msg| msg.into_bytes());
+
+    // attempt verification and match on error to find invalid message
+    match verify_message_value(&msg_bytes, hmac) {
+        Ok(_) => (),
+        Err(e) => {
+            let invalid_msg_str = message_str(&msg_bytes);
+            let reason = verification_failure_reason(&e, Some(&msg_bytes), hmac);
+            let err_msg = format!("found invalid message: {}: {}", reason, invalid_msg_str);
+            return (Some(err_msg), None, Some(verification_error_code(&e)));
+        }
+    };
+
+    // attempt validation and match on error to find invalid message
+    match validate_message_value_hash_chain(&msg_bytes, previous_msg_bytes.as_deref()) {
+        Ok(_) => (),
+        Err(e) => {
+            let invalid_msg_str = message_str(&msg_bytes);
+            let err_msg = format!(
+                "found invalid message: {}: {}",
+                link_failure_reason(&e, &msg_bytes, previous_msg_bytes.as_deref()),
+                invalid_msg_str
+            );
+            return (Some(err_msg), None, Some(validation_error_code(&e)));
+        }
+    };
+
+    // generate multihash from message value bytes
+    let multihash = utils::multihash_from_bytes(&msg_bytes);
+    let key = format_key(multihash, key_format);
+    (None, Some(key), None)
+}
+
+/// Verify signature and perform validation for a single message value which refers to off-chain
+/// content, and check that the supplied content matches the hash embedded in the message.
+///
+/// Takes an HMAC key as the first argument, message `value` as the second argument, the content
+/// (as a buffer) as the third argument and an optional previous message `value` as the fourth
+/// argument. The message is verified and validated as in `validateSingle`. The `content` field of
+/// the message value must be an object with a `contentHash` property holding the sha256 hash of
+/// the off-chain content, in legacy encoding (eg. `&<base64>.sha256`).
+///
+/// The return type matches that of `validateSingle`. If the hash of the supplied content does not
+/// match `contentHash`, the error is `content hash mismatch`.
+#[node_bindgen(name = "validateWithContent")]
+fn verify_validate_message_with_content(
+    hmac_key: HmacKey,
+    msg_value: String,
+    content: JSArrayBuffer,
+    previous: Option<String>,
+) -> (Option<String>, Option<String>) {
+    let msg_bytes = msg_value.as_bytes().to_vec();
+
+    let (err, key, _) = validate_single(hmac_key, msg_value, previous, KeyFormat::Legacy);
+    if err.is_some() {
+        return (err, None);
+    }
+
+    // extract the embedded content hash from the message value
+    let content_hash = match from_slice::<SsbMessageValue>(&msg_bytes) {
+        Ok(value) => match value.content.0 {
+            Value::Object(map) => match map.get("contentHash") {
+                Some(Value::String(hash)) => Some(hash.to_owned()),
+                _ => None,
+            },
+            _ => None,
+        },
+        Err(_) => None,
+    };
+    let expected_hash = match content_hash
+        .as_deref()
+        .and_then(|hash| Multihash::from_legacy(hash.as_bytes()).ok())
+    {
+        Some((Multihash::Message(bytes), _)) | Some((Multihash::Blob(bytes), _)) => bytes,
+        None => {
+            let invalid_msg_str = message_str(&msg_bytes);
+            let err_msg = format!(
+                "found invalid message: content hash missing or invalid: {}",
+                invalid_msg_str
+            );
+            return (Some(err_msg), None);
+        }
+    };
+
+    if ssb_crypto::hash(content.as_bytes()).0 != expected_hash {
+        return (Some("content hash mismatch".to_string()), None);
+    }
+
+    (None, key)
+}
+
+/// Verify signatures and perform validation for an array of ordered message values by a single
+/// author (includes HMAC key support).
+///
+/// Takes an HMAC key as the first argument, an array of message values as the second argument, an
+/// options object as the third argument and an optional previous message value as the fourth
+/// argument. The HMAC key must be of type `string` or `ArrayBuffer`. Message signatures are
+/// verified without an HMAC key if the value of the argument is `null`, `undefined` or the `string`
+/// value `none`. Each message value is either a `string` or a buffer (eg. a `Buffer` or
+/// `Uint8Array`) holding its utf8 encoding; the same applies to the arrays of messages taken by the
+/// other batch functions. The previous message argument is expected when the array of messages does
+/// not start from the beginning of the feed (ie. sequence number != 1 and previous != null). If
+/// verification or validation fails, the cause of the error is returned along with the offending
+/// message.
+///
+/// The return type is a tuple of the error message, the keys of the messages and additional
+/// information requested via the options object. If validation fails because of a gap in the
+/// sequence numbers, the missing (inclusive) range is included in the error message and returned
+/// as `missingRange` in the additional information. If the error is caused by a single message,
+/// its (zero-based) index in the array is returned as `invalidIndex` in the additional
+/// information, so that replication can resume from there. The following options are supported:
+///
+/// - `checkTimestamps`: report the indices of messages whose `timestamp` is earlier than that of
+///   the preceding message as `timestampRegressions`. This is a warning and does not cause
+///   validation to fail.
+/// - `timestampStats`: report the minimum, maximum and mean difference between the timestamps of
+///   consecutive messages (including `previous`) as `timestampDeltas`, an object of shape
+///   `{ min, max, mean }`, or `null` if there are fewer than two timestamps.
+/// - `timestampRange`: report the timestamps of the first and last message of the batch as
+///   `timestampRange`, an array of shape `[first, last]`, or `null` if the batch is empty.
+/// - `sortBySequence`: sort the messages by sequence number before validation. The sorted
+///   messages must form a contiguous range. Keys (and any reported indices) are returned in
+///   sequence order.
+/// - `detectMigrations`: report messages announcing a metafeed (`metafeed/announce`) as
+///   `migrationMarkers`, an array of `{ index, metafeed }` objects.
+/// - `classifyTypes`: group the keys of the messages by content `type` as `contentTypes`, an
+///   object mapping each type to an array of keys. Encrypted messages have no type and are
+///   omitted.
+/// - `digest`: compute a digest of the batch as `digest`: the sha256 hash of the concatenated
+///   32-byte message hashes (in order), encoded as base64. Two identical feed segments always
+///   have the same digest, regardless of `keyFormat`.
+/// - `verify`, `chain` and `hash`: run the signature verification, validation (including
+///   hash-chain checks) and key computation phases respectively. All phases run by default.
+///   When `hash` is `false`, no keys are returned.
+/// - `skipVerify`: skip the signature verification phase (as with `verify: false`), eg. when
+///   re-validating a local log whose signatures were verified on ingest. This also applies to the
+///   js `validateBatch`, which otherwise runs all phases. The returned keys are unaffected.
+/// - `keyFormat`: the format of the returned keys; either `legacy` (`%<base64>.sha256`, the
+///   default) or `uri` (`ssb:message/classic/<url-safe base64>`). A value of `both` returns each
+///   key as a `[legacy, uri]` pair, and a value of `raw` returns each key as a 32 byte
+///   `ArrayBuffer` holding the sha256 digest. Any other value is an error.
+/// - `expectedGenesisKey`: the trusted key (in either format) of the first message of the feed.
+///   If there is no previous message and the batch starts at sequence 1, the key of the first
+///   message must match, which rules out an internally consistent feed of forged origin.
+/// - `expectedAuthor`: the feed id the batch was requested for. Every message must be by this
+///   author, which rules out a valid subchain of another feed spliced in by a malicious peer.
+///   The error names the index of the first message by another author.
+/// - `previousKey` and `previousSequence`: the key (in legacy format) and sequence number of the
+///   previous message, in place of the previous message itself (which is then omitted). The first
+///   message must link to that key and follow that sequence number; the rest of the batch is
+///   chained to it as usual. This allows validation of a feed to be resumed from a stored key and
+///   sequence number alone.
+/// - `deprecatedTypes`: an array of deprecated content types. The keys of messages with any of
+///   these types are reported as `info.deprecated`, an array of `{ key, type }` objects.
+/// - `allowedTypes`: an array of accepted content types. Messages with any other content type are
+///   rejected before verification, with an error naming the index and type of the first such
+///   message. Encrypted messages have no type and are not filtered.
+/// - `disallowedTypes`: either `reject` (the default) or `warn`. With `warn`, messages outside
+///   `allowedTypes` are not rejected but reported as `info.disallowedTypes`, an array of
+///   `{ index, type }` objects.
+/// - `revokedKeys`: an array of `[feedId, sequence]` pairs (the js wrapper also accepts an object
+///   mapping each feed id to a sequence number). Messages by a revoked author with a sequence
+///   number at or beyond the paired revocation sequence are rejected with `author revoked`.
+/// - `signatureAlgorithms`: report the signature algorithm of each message (the suffix of its
+///   `signature` field, eg. `ed25519`) as `info.signatureAlgorithms`, in order.
+/// - `detectEncrypted`: report whether the content of each message is encrypted (a `box` or
+///   `box2` string rather than an object) as `info.encrypted`, an array of booleans in order.
+/// - `contentHashes`: report the sha256 hash of the content of each message (as
+///   `<base64>.sha256`) as `info.contentHashes`, in order. The hash is computed over the compact
+///   JSON encoding of the content, or over the content string itself for encrypted messages, so
+///   that identical content can be deduplicated without parsing the messages in js.
+/// - `stats`: report the time spent verifying signatures and validating the hash chain (in
+///   milliseconds, `0` for a skipped phase) and the number of messages processed as
+///   `info.stats`, an object of shape `{ verifyMs, validateMs, count }`.
+/// - `returnPreviousKey`: report the key of `previous` (in the requested `keyFormat`) as
+///   `info.previousKey`, or `null` if no previous message was given. This allows the caller to
+///   check that the message passed as `previous` is the one expected.
+/// - `collectFeedIds`: collect the well-formed feed ids (`@<base64>.ed25519`) referenced anywhere
+///   in the content of the messages (eg. `contact` targets and mentions) as `info.feedIds`, a
+///   sorted array without duplicates.
+/// - `collectBlobIds`: report the well-formed blob ids (`&<base64>.sha256`) referenced anywhere in
+///   the content of each message (eg. images in a post) as `info.blobIds`, an array holding a
+///   sorted array without duplicates per message, in order. Encrypted messages reference none.
+/// - `checkBase64`: report the `author` and `signature` fields holding non-canonical base64 (eg.
+///   incorrect padding) as `info.nonCanonicalBase64`, an array of `{ index, field }` objects.
+///   Such values may be decoded differently by other implementations.
+/// - `strictBase64`: reject any message with non-canonical base64 in its `author` or
+///   `signature` field.
+/// - `maxDepth` and `maxContentBytes`: reject any message whose content is nested deeper than
+///   `maxDepth` levels or whose compact JSON encoding is larger than `maxContentBytes` bytes.
+///   These limits are checked before verification, which guards against pathological input from
+///   untrusted peers. Not set by default.
+/// - `reportGaps`: rather than failing on a message whose sequence number skips ahead (eg. `5`
+///   followed by `7`), validate the batch up to the first such gap and report the indices of the
+///   messages following each gap as `info.gaps`. Only the keys of the validated prefix are
+///   returned, so that a repair tool can fetch the missing messages and resume from there.
+#[node_bindgen(name = "validateBatch")]
+fn verify_validate_messages(
+    hmac_key: HmacKey,
+    array: Messages,
+    options: BatchOptions,
+    previous: Option<String>,
+) -> (Option<String>, Option<BatchKeys>, Option<BatchInfo>) {
+    validate_batch(hmac_key, array, options, previous)
+}
+
+// the additional information returned along with a batch error with the given code, caused by
+// the message at `index` (if a single message is at fault)
+fn failure_info(code: &'static str, index: Option<usize>) -> Option<BatchInfo> {
+    Some(BatchInfo {
+        invalid_index: index,
+        error_code: Some(code),
+        ..BatchInfo::default()
+    })
+}
+
+// shared implementation of `validateBatch` and `validateBatchEnvelope`
+fn validate_batch(
+    hmac_key: HmacKey,
+    array: Messages,
+    options: BatchOptions,
+    previous: Option<String>,
+) -> (Option<String>, Option<BatchKeys>, Option<BatchInfo>) {
+    let valid_hmac = match is_valid_hmac_key(hmac_key) {
+        Ok(key) => key,
+        Err(err_msg) => return (Some(err_msg), None, failure_info("INVALID_HMAC_KEY", None)),
+    };
+    // with both key formats or raw keys, legacy keys are used for any additional information
+    let both_keys = options.both_key_formats();
+    let raw_keys = options.raw_keys();
+    let key_format = match options.key_format() {
+        _ if both_keys || raw_keys => KeyFormat::Legacy,
+        Ok(format) => format,
+        Err(err_msg) => return (Some(err_msg), None, failure_info("INVALID_OPTIONS", None)),
+    };
+    // the key and sequence number of the previous message stand in for the message itself, so
+    // they are only meaningful together and in its absence
+    let link_err = match (&options.previous_key, options.previous_sequence, &previous) {
+        (None, None, _) | (Some(_), Some(_), None) => None,
+        (_, _, Some(_)) => {
+            Some("previousKey and previousSequence cannot be combined with a previous message")
+        }
+        _ => Some("previousKey and previousSequence must be given together"),
+    };
+    if let Some(err_msg) = link_err {
+        return (
+            Some(err_msg.to_string()),
+            None,
+            failure_info("INVALID_OPTIONS", None),
+        );
+    }
+    let hmac = valid_hmac.as_deref();
+
+    // the message buffers are reused by the next batch once validation is done
+    let mut msgs = PooledBuffers(Vec::with_capacity(array.len()));
+    for msg in array {
+        let msg_bytes = msg.into_bytes();
+        msgs.push(msg_bytes)
+    }
+
+    // reject oversized or deeply nested content before any message is parsed (eg. to
+    // sort the batch or find gaps), since the parser is recursive
+    if options.max_depth.is_some() || options.max_content_bytes.is_some() {
+        let exceeded = msgs.iter().enumerate().find_map(|(index, msg)| {
+            exceeded_content_limit(msg, &options).map(|e| (index, e, msg))
+        });
+        if let Some((index, reason, msg)) = exceeded {
+            let invalid_msg_str = message_str(msg);
+            let err_msg = format!(
+                "found invalid message at index {}: {}: {}",
+                index, reason, invalid_msg_str
+            );
+            return (
+                Some(err_msg),
+                None,
+                failure_info("CONTENT_LIMIT_EXCEEDED", Some(index)),
+            );
+        }
+    }
+
+    if options.sort_by_sequence {
+        if let Err((expected, actual)) = sort_by_sequence(&mut msgs) {
+            let gap = missing_range(expected, actual);
+            let err_msg = format!(
+                "messages do not form a contiguous sequence range: expected sequence {} but found {}{}",
+                expected,
+                actual,
+                describe_gap(gap)
+            );
+            let info = BatchInfo {
+                missing_range: gap,
+                error_code: Some("INVALID_SEQUENCE"),
+                ..BatchInfo::default()
+            };
+            return (Some(err_msg), None, Some(info));
+        }
+    }
+
+    let previous_msg = previous.map(|msg| msg.into_bytes());
+
+    // only the contiguous prefix of the batch (up to the first gap) is validated
+    let gaps = if options.report_gaps {
+        let previous_sequence = match (&previous_msg, options.previous_sequence) {
+            (Some(msg), _) => from_slice::<SsbMessageValue>(msg)
+                .ok()
+                .map(|value| value.sequence),
+            (None, Some(sequence)) => match u64::try_from(sequence) {
+                Ok(sequence) => Some(sequence),
+                Err(_) => {
+                    let err_msg = format!(
+                        "previousSequence invalid: expected a non-negative integer but got {}",
+                        sequence
+                    );
+                    return (Some(err_msg), None, failure_info("INVALID_OPTIONS", None));
+                }
+            },
+            (None, None) => None,
+        };
+        let gaps = sequence_gaps(&msgs, previous_sequence);
+        if let Some(&first_gap) = gaps.first() {
+            msgs.truncate(first_gap);
+        }
+        Some(gaps.into_iter().map(|index| index as i64).collect())
+    } else {
+        None
+    };
+
+    // filter content types before verification, so disallowed messages cost as little as possible
+    let disallowed = match &options.allowed_types {
+        Some(allowed_types) => disallowed_messages(&msgs, allowed_types),
+        None => Vec::new(),
+    };
+    if !options.warn_disallowed_types {
+        if let Some(disallowed) = disallowed.first() {
+            let invalid_msg_str = message_str(&msgs[disallowed.index]);
+            let err_msg = format!(
+                "found invalid message at index {}: content type `{}` is not allowed: {}",
+                disallowed.index, disallowed.msg_type, invalid_msg_str
+            );
+            return (
+                Some(err_msg),
+                None,
+                failure_info("DISALLOWED_TYPE", Some(disallowed.index)),
+            );
+        }
+    }
+
+    if let Some(revoked_keys) = &options.revoked_keys {
+        if let Err((index, err_msg)) = check_revoked(&msgs, revoked_keys) {
+            return (
+                Some(err_msg),
+                None,
+                failure_info("REVOKED_AUTHOR", Some(index)),
+            );
+        }
+    }
+
+    if let Some(expected_author) = &options.expected_author {
+        if let Err((index, err_msg)) = check_expected_author(&msgs, expected_author) {
+            return (
+                Some(err_msg),
+                None,
+                failure_info("AUTHOR_MISMATCH", Some(index)),
+            );
+        }
+    }
+
+    let non_canonical = if options.check_base64 || options.strict_base64 {
+        non_canonical_base64(&msgs)
+    } else {
+        Vec::new()
+    };
+    if options.strict_base64 {
+        if let Some(field) = non_canonical.first() {
+            let invalid_msg_str = message_str(&msgs[field.index]);
+            let err_msg = format!(
+                "found invalid message at index {}: non-canonical base64 in `{}` field: {}",
+                field.index, field.field, invalid_msg_str
+            );
+            return (
+                Some(err_msg),
+                None,
+                failure_info("NON_CANONICAL_BASE64", Some(field.index)),
+            );
+        }
+    }
+
+    let verify_start = Instant::now();
+    if !options.skip_verify {
+        // attempt batch verification and match on error to find invalid message value
+        match in_thread_pool(|| par_verify_message_values(&msgs, hmac, None)) {
+            Ok(_) => (),
+            Err(e) => {
+                let invalid_index = msgs
+                    .iter()
+                    .position(|msg| verify_message_value(msg, hmac).is_err());
+                let invalid_msg = &invalid_index.map(|index| &msgs[index]);
+                let invalid_msg_str = match invalid_msg {
+                    Some(msg) => message_str(msg),
+                    None => "parallel verification failed but no single invalid message was found",
+                };
+                let reason =
+                    verification_failure_reason(&e, invalid_msg.map(|msg| msg.as_slice()), hmac);
+                let err_msg = format!(
+                    "found invalid message: {}{}: {}",
+                    reason,
+                    caps_key_hint(&msgs, hmac),
+                    invalid_msg_str
+                );
+                let code = verification_error_code(&e);
+                return (Some(err_msg), None, failure_info(code, invalid_index));
+            }
+        };
+    }
+    let verify_ms = verify_start.elapsed().as_secs_f64() * 1000.0;
+
+    let validate_start = Instant::now();
+    if !options.skip_chain {
+        // given only the key and sequence number of the previous message, the first message is
+        // checked against them and the rest of the batch is chained to the first message
+        let (chain_start, chain_previous) = match (
+            &options.previous_key,
+            options.previous_sequence,
+            &previous_msg,
+        ) {
+            (Some(key), Some(sequence), None) if !msgs.is_empty() => {
+                if let Err(failure) = check_previous_link(&msgs[0], key, sequence) {
+                    let (err_msg, info) = *failure;
+                    return (Some(err_msg), None, Some(info));
+                }
+                (1, msgs.first())
+            }
+            _ => (0, previous_msg.as_ref()),
+        };
+        // attempt batch validation and match on error to find invalid message value
+        match in_thread_pool(|| {
+            par_validate_message_value_hash_chain_of_feed(&msgs[chain_start..], chain_previous)
+        }) {
+            Ok(_) => (),
+            Err(e) => {
+                // each message is validated against the message preceding it (or `previous`)
+                let previous_of = |index: usize| match index {
+                    _ if index == chain_start => chain_previous.map(|msg| msg.as_slice()),
+                    _ => Some(msgs[index - 1].as_slice()),
+                };
+                let invalid_index = (chain_start..msgs.len()).find(|&index| {
+                    validate_message_value_hash_chain(&msgs[index], previous_of(index)).is_err()
+                });
+                let invalid_msg = &invalid_index.map(|index| &msgs[index]);
+                let invalid_msg_str = match invalid_msg {
+                    Some(msg) => message_str(msg),
+                    None => "parallel validation failed but no single invalid message was found",
+                };
+                // report the sequence numbers skipped by the batch, if any
+                let gap = match e {
+                    ValidationError::InvalidSequenceNumber {
+                        actual, expected, ..
+                    } => missing_range(expected, actual),
+                    _ => None,
+                };
+                let reason = match invalid_index {
+                    Some(index) => link_failure_reason(&e, &msgs[index], previous_of(index)),
+                    None => validation_failure_reason(&e),
+                };
+                let err_msg = format!(
+                    "found invalid message: {}{}: {}",
+                    reason,
+                    describe_gap(gap),
+                    invalid_msg_str
+                );
+                let info = BatchInfo {
+                    missing_range: gap,
+                    invalid_index,
+                    error_code: Some(validation_error_code(&e)),
+                    ..BatchInfo::default()
+                };
+                return (Some(err_msg), None, Some(info));
+            }
+        }
+    }
+    let validate_ms = validate_start.elapsed().as_secs_f64() * 1000.0;
+
+    // the genesis message is only part of the batch if there is no previous message
+    if let (Some(expected_key), None, None) = (
+        &options.expected_genesis_key,
+        &previous_msg,
+        &options.previous_key,
+    ) {
+        if let Err(reason) = check_genesis_key(&msgs, expected_key) {
+            let invalid_msg_str = message_str(&msgs[0]);
+            let err_msg = format!("found invalid message: {}: {}", reason, invalid_msg_str);
+            return (
+                Some(err_msg),
+                None,
+                failure_info("GENESIS_KEY_MISMATCH", Some(0)),
+            );
+        }
+    }
+
+    let mut info = BatchInfo {
+        gaps,
+        ..BatchInfo::default()
+    };
+    if options.stats {
+        info.stats = Some(BatchStats {
+            verify_ms,
+            validate_ms,
+            count: msgs.len(),
+        });
+    }
+    if options.check_timestamps || options.timestamp_stats {
+        let timestamps = feed_timestamps(&msgs, previous_msg.as_ref());
+        if options.check_timestamps {
+            let offset = if previous_msg.is_some() { 1 } else { 0 };
+            info.timestamp_regressions = Some(timestamp_regressions(&timestamps, offset));
+        }
+        if options.timestamp_stats {
+            info.timestamp_deltas = Some(timestamp_deltas(&timestamps));
+        }
+    }
+    if options.timestamp_range {
+        let first = msgs.first().and_then(|msg| message_timestamp(msg));
+        let last = msgs.last().and_then(|msg| message_timestamp(msg));
+        info.timestamp_range = Some(first.zip(last));
+    }
+    if options.detect_migrations {
+        info.migration_markers = Some(migration_markers(&msgs));
+    }
+    if options.check_base64 {
+        info.non_canonical_base64 = Some(non_canonical);
+    }
+    if options.allowed_types.is_some() && options.warn_disallowed_types {
+        info.disallowed_types = Some(disallowed);
+    }
+    if options.collect_feed_ids {
+        info.feed_ids = Some(referenced_feed_ids(&msgs));
+    }
+    if options.collect_blob_ids {
+        info.blob_ids = Some(referenced_blob_ids(&msgs));
+    }
+    if options.signature_algorithms {
+        info.signature_algorithms = Some(signature_algorithms(&msgs));
+    }
+    if options.detect_encrypted {
+        info.encrypted = Some(encrypted_flags(&msgs));
+    }
+    if options.content_hashes {
+        info.content_hashes = Some(content_hashes(&msgs));
+    }
+    if options.return_previous_key {
+        info.previous_key = Some(
+            previous_msg
+                .as_ref()
+                .map(|msg| format_key(utils::multihash_from_bytes(msg), key_format)),
+        );
+    }
+    let types = if options.classify_types || options.deprecated_types.is_some() {
+        Some(content_types(&msgs))
+    } else {
+        None
+    };
+
+    // keys are only computed if they are returned or needed for additional information
+    let multihashes: Vec<Multihash> = if !options.skip_hash || types.is_some() || options.digest {
+        msgs.iter()
+            .map(|msg| utils::multihash_from_bytes(msg))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    if options.digest {
+        info.digest = Some(batch_digest(&multihashes));
+    }
+    let uri_keys: Option<Vec<String>> = if both_keys {
+        Some(
+            multihashes
+                .iter()
+                .map(|multihash| format_key(multihash.clone(), KeyFormat::Uri))
+                .collect(),
+        )
+    } else {
+        None
+    };
+    let digests: Option<Vec<[u8; 32]>> = if raw_keys {
+        Some(multihashes.iter().map(multihash_digest).collect())
+    } else {
+        None
+    };
+
+    let keys: Vec<String> = multihashes
+        .into_iter()
+        .map(|multihash| format_key(multihash, key_format))
+        .collect();
+    if let Some(types) = types {
+        if let Some(deprecated_types) = &options.deprecated_types {
+            info.deprecated = Some(deprecated_messages(&types, deprecated_types, &keys));
+        }
+        if options.classify_types {
+            info.content_types = Some(bucket_by_type(types, &keys));
+        }
+    }
+
+    let info = if options.wants_info() {
+        Some(info)
+    } else {
+        None
+    };
+    let keys = match (uri_keys, digests) {
+        _ if options.skip_hash => None,
+        (Some(uri_keys), _) => Some(BatchKeys::Both(keys.into_iter().zip(uri_keys).collect())),
+        (_, Some(digests)) => Some(BatchKeys::Raw(digests)),
+        _ => Some(BatchKeys::Single(keys)),
+    };
+    (None, keys, info)
+}
+
+/// Verify signatures and perform validation for an array of ordered message values by a single
+/// author, without aborting on the first invalid message (includes HMAC key support).
+///
+/// Takes the same arguments as `validateBatch` and supports the `keyFormat` option. Every message
+/// is verified and validated: each message is chained to the last *valid* message preceding it
+/// (or `previous`), so the returned keys always form a contiguous, valid feed segment.
+///
+/// The return type is a tuple of the error message and an object of shape
+/// `{ keys: [...], errors: [{ index, code, reason }] }`, where `keys` holds the keys of the valid
+/// messages in order and `errors` holds an entry for each invalid message. The error message is
+/// only set if the batch could not be processed at all (eg. due to an invalid HMAC key).
+///
+/// If the `mask` option is `true`, the object also holds a `mask` property: an `ArrayBuffer` with
+/// one byte per message, which is `1` if the message is valid and `0` otherwise. This is a compact
+/// alternative to matching `errors` against the input for large batches.
+#[node_bindgen(name = "validateBatchSettled")]
+fn verify_validate_messages_settled(
+    hmac_key: HmacKey,
+    array: Messages,
+    options: BatchOptions,
+    previous: Option<String>,
+) -> (Option<String>, Option<SettledBatch>) {
+    let valid_hmac = match is_valid_hmac_key(hmac_key) {
+        Ok(key) => key,
+        Err(err_msg) => return (Some(err_msg), None),
+    };
+    let key_format = match options.key_format() {
+        Ok(format) => format,
+        Err(err_msg) => return (Some(err_msg), None),
+    };
+    let hmac = valid_hmac.as_deref();
+
+    let mut outcomes = Vec::with_capacity(array.len());
+    let msgs = array.into_iter().map(|msg| msg.into_bytes());
+    let previous_msg = previous.map(|msg| msg.into_bytes());
+    validate_in_sequence(msgs, previous_msg, hmac, key_format, |_, result| {
+        outcomes.push(result.map_err(|(code, reason, _)| (code, reason)))
+    });
 
-// custom `enum` to allow type conversion of the message-signing hmac from js
-enum HmacKey {
-    Buf(JSArrayBuffer),
-    Str(String),
+    let settled = SettledBatch::from_outcomes(outcomes.into_iter(), options.mask);
+    (None, Some(settled))
 }
 
-// implement type conversion for our custom `HmacKey` enum
-// we're primarily interested in strings and array buffers
-impl JSValue<'_> for HmacKey {
-    fn convert_to_rust(env: &JsEnv, n_value: napi_value) -> Result<Self, NjError> {
-        if let Ok(string_value) = env.convert_to_rust::<String>(n_value) {
-            Ok(Self::Str(string_value))
-        } else if let Ok(buffer_value) = env.convert_to_rust::<JSArrayBuffer>(n_value) {
-            Ok(Self::Buf(buffer_value))
-        } else {
-            Err(NjError::Other(
-                "hmacKey must be of type string, array buffer, null or undefined".to_owned(),
-            ))
-        }
-    }
+/// Verify signatures and perform validation for an array of ordered message values by a single
+/// author, reporting every invalid message (includes HMAC key support).
+///
+/// Takes the same arguments as `validateBatch` and supports the `keyFormat` and `mask` options.
+/// The return type matches that of `validateBatchSettled`. Unlike `validateBatchSettled`, each
+/// message is verified and validated against the message preceding it in the array (or
+/// `previous`), whether or not that message is valid, in parallel. A single corrupt message is
+/// therefore reported along with the broken link of its successor only, rather than with every
+/// message following it, which pinpoints the damage when repairing a feed imported from an
+/// untrusted peer. The returned keys are those of the valid messages in order, which do not
+/// necessarily form a contiguous feed segment.
+#[node_bindgen(name = "validateBatchVerbose")]
+fn verify_validate_messages_verbose(
+    hmac_key: HmacKey,
+    array: Messages,
+    options: BatchOptions,
+    previous: Option<String>,
+) -> (Option<String>, Option<SettledBatch>) {
+    let valid_hmac = match is_valid_hmac_key(hmac_key) {
+        Ok(key) => key,
+        Err(err_msg) => return (Some(err_msg), None),
+    };
+    let key_format = match options.key_format() {
+        Ok(format) => format,
+        Err(err_msg) => return (Some(err_msg), None),
+    };
+    let hmac = valid_hmac.as_deref();
+
+    let previous_msg = previous.map(|msg| msg.into_bytes());
+    let outcomes: Vec<Result<String, (&'static str, String)>> = in_thread_pool(|| {
+        (0..array.len())
+            .into_par_iter()
+            .map(|index| {
+                let msg = array[index].as_bytes();
+                let previous = match index {
+                    0 => previous_msg.as_deref(),
+                    _ => Some(array[index - 1].as_bytes()),
+                };
+                check_signature(msg, hmac)
+                    .and_then(|_| check_link(msg, previous))
+                    .map(|_| format_key(utils::multihash_from_bytes(msg), key_format))
+            })
+            .collect()
+    });
+
+    let settled = SettledBatch::from_outcomes(outcomes.into_iter(), options.mask);
+    (None, Some(settled))
 }
 
-// The HMAC we are dealing with here is the 'message-signing HMAC' and not the 'network HMAC'
-// which is used during the secret-handshake between peers (aka network identifier, app key
-// or caps key). While both use the same hashing algorithms (`HMAC-SHA-512-256`), they are
-// employed in different ways. Message signing with an HMAC is an optional feature of
-// Scuttlebutt and is not put to use in the main network. This is why a `null` or `None`
-// value is set for the message-signing HMAC when verifying main network message signatures.
-//
-// the `Ok()` variant for `Result` represents a valid hmac key value as a byte vector
-fn is_valid_hmac_key(hmac_key: HmacKey) -> Result<Option<Vec<u8>>, String> {
-    match hmac_key {
-        HmacKey::Buf(hmac) => {
-            let key = MsgHmacKey::from_slice(&hmac);
-            match key {
-                None => Err("hmac key invalid: byte length must equal 32".to_string()),
-                Some(key_val) => {
-                    let key_bytes = key_val.as_bytes().to_vec();
-                    Ok(Some(key_bytes))
-                }
-            }
-        }
-        HmacKey::Str(hmac) => {
-            let key = MsgHmacKey::from_base64(&hmac);
-            // match on what would have been `null` or `undefined` for `hmacKey`
-            // in the js function call. these values are considered valid.
-            if hmac == "none" {
-                Ok(None)
-            } else {
-                match key {
-                    None => Err("hmac key invalid: string must be base64 encoded".to_string()),
-                    Some(key_val) => {
-                        let key_bytes = key_val.as_bytes().to_vec();
-                        Ok(Some(key_bytes))
-                    }
-                }
+/// Find the invalid messages in an array of ordered message values by a single author (includes
+/// HMAC key support).
+///
+/// Takes an HMAC key as the first argument, an array of messages as the second argument and an
+/// optional previous message value as the third argument. Each message is verified and validated
+/// against the message preceding it in the array (or `previous`), in parallel. No keys are
+/// computed.
+///
+/// The return type is a tuple of the error message (if the HMAC key is invalid) and the indices
+/// of the messages which failed verification or validation, in ascending order.
+#[node_bindgen(name = "findInvalid")]
+fn find_invalid_messages(
+    hmac_key: HmacKey,
+    array: Messages,
+    previous: Option<String>,
+) -> (Option<String>, Option<Vec<i64>>) {
+    let valid_hmac = match is_valid_hmac_key(hmac_key) {
+        Ok(key) => key,
+        Err(err_msg) => return (Some(err_msg), None),
+    };
+    let hmac = valid_hmac.as_deref();
+
+    let previous_msg = previous.map(|msg| msg.into_bytes());
+    let invalid = in_thread_pool(|| {
+        (0..array.len())
+            .into_par_iter()
+            .filter(|&index| {
+                let msg = array[index].as_bytes();
+                let previous = match index {
+                    0 => previous_msg.as_deref(),
+                    _ => Some(array[index - 1].as_bytes()),
+                };
+                verify_message_value(msg, hmac).is_err()
+                    || validate_message_value_hash_chain(msg, previous).is_err()
+            })
+            .map(|index| index as i64)
+            .collect()
+    });
+    (None, Some(invalid))
+}
+
+/// Classify an array of ordered message values as a contiguous segment (prefix) of a single feed
+/// or not, without performing full validation (includes HMAC key support).
+///
+/// Takes an HMAC key as the first argument, an array of messages as the second argument and a
+/// `boolean` as the third argument, which selects whether signatures are verified as well. The
+/// messages are parsed once: a batch is a contiguous feed segment if all messages have the same
+/// author, their sequence numbers increase by one and the `previous` field of each message is the
+/// key of the message preceding it. Signatures are only verified if requested, in which case
+/// a batch with an invalid signature is not a contiguous feed segment.
+///
+/// The return type is a tuple of the error message (if the HMAC key is invalid) and an object of
+/// shape `{ isContiguousFeed, author, startSeq, endSeq }`. `author` is `null` if the batch is
+/// empty or has more than one author; `startSeq` and `endSeq` are the sequence numbers of the
+/// first and last message (or `null` if they cannot be parsed).
+#[node_bindgen(name = "classifyBatch")]
+fn classify_batch(
+    hmac_key: HmacKey,
+    array: Messages,
+    verify: bool,
+) -> (Option<String>, Option<FeedSegment>) {
+    let valid_hmac = match is_valid_hmac_key(hmac_key) {
+        Ok(key) => key,
+        Err(err_msg) => return (Some(err_msg), None),
+    };
+    let hmac = valid_hmac.as_deref();
+
+    let values: Vec<Option<SsbMessageValue>> = array
+        .iter()
+        .map(|msg| from_slice::<SsbMessageValue>(msg.as_bytes()).ok())
+        .collect();
+    let author = match values.first() {
+        Some(Some(first)) => Some(first.author.to_owned()),
+        _ => None,
+    };
+    let single_author = author.is_some()
+        && values
+            .iter()
+            .all(|value| matches!(value, Some(value) if Some(&value.author) == author.as_ref()));
+
+    // each message must follow the one before it by sequence and `previous` link
+    let linked = values
+        .windows(2)
+        .zip(array.iter())
+        .all(|(pair, prev_msg)| match pair {
+            [Some(prev), Some(value)] => {
+                let prev_key = utils::multihash_from_bytes(prev_msg.as_bytes());
+                prev.sequence.checked_add(1) == Some(value.sequence)
+                    && value.previous.as_ref() == Some(&prev_key)
             }
-        }
-    }
+            _ => false,
+        });
+
+    let verified =
+        !verify || in_thread_pool(|| par_verify_message_values(&array, hmac, None)).is_ok();
+
+    let segment = FeedSegment {
+        is_contiguous_feed: single_author && linked && verified,
+        author: author.filter(|_| single_author),
+        start_seq: values
+            .first()
+            .and_then(|value| value.as_ref().map(|v| v.sequence)),
+        end_seq: values
+            .last()
+            .and_then(|value| value.as_ref().map(|v| v.sequence)),
+    };
+    (None, Some(segment))
 }
 
-fn hash(msgs: Vec<Vec<u8>>) -> Vec<String> {
-    let mut keys = Vec::new();
-    for msg in msgs {
-        let multihash = utils::multihash_from_bytes(&msg);
-        let key = multihash.to_legacy_string();
-        keys.push(key);
-    }
-    keys
+/// Verify signatures and perform validation for an array of ordered message values by a single
+/// author, reporting the result for each message as soon as it is available (includes HMAC key
+/// support).
+///
+/// Takes an HMAC key as the first argument, an array of messages as the second argument, a
+/// previous message value as the third argument (the `string` value `none` if there is no
+/// previous message), an options object as the fourth argument and a callback as the fifth
+/// argument. The `keyFormat` option selects the format of the returned keys (see
+/// `validateBatch`). Messages are validated on the thread pool used for parallel verification (see
+/// `setMaxThreads`), as in `validateBatchSettled`: signatures are verified in parallel, a chunk of
+/// messages at a time, and each message is chained to the last valid message preceding it.
+///
+/// The callback is invoked with the index of the message, the error message (if the message is
+/// invalid) and the key (if the message is valid). Once all messages have been validated, the
+/// callback is invoked one final time with an index of `-1` and, if the batch could not be
+/// processed at all (eg. due to an invalid HMAC key), an error message.
+#[node_bindgen(name = "validateEach", mt)]
+fn verify_validate_messages_each<F: Fn(i64, Option<String>, Option<String>) + Send + 'static>(
+    hmac_key: HmacKey,
+    array: Messages,
+    previous: String,
+    options: BatchOptions,
+    cb: F,
+) {
+    let valid_hmac = match is_valid_hmac_key(hmac_key) {
+        Ok(key) => key,
+        Err(err_msg) => return cb(-1, Some(err_msg), None),
+    };
+    let key_format = match options.key_format() {
+        Ok(format) => format,
+        Err(err_msg) => return cb(-1, Some(err_msg), None),
+    };
+    let previous_msg = match previous.as_str() {
+        "none" => None,
+        _ => Some(previous.into_bytes()),
+    };
+
+    spawn_in_thread_pool(move || {
+        let msgs = array.into_iter().map(|msg| msg.into_bytes());
+        let hmac = valid_hmac.as_deref();
+        validate_in_sequence(
+            msgs,
+            previous_msg,
+            hmac,
+            key_format,
+            |index, result| match result {
+                Ok(key) => cb(index as i64, None, Some(key)),
+                Err((_, reason, msg)) => {
+                    let invalid_msg_str = message_str(&msg);
+                    let err_msg = format!("found invalid message: {}: {}", reason, invalid_msg_str);
+                    cb(index as i64, Some(err_msg), None)
+                }
+            },
+        );
+        cb(-1, None, None)
+    });
 }
 
-/// Verify signatures for an array of messages (includes HMAC key support).
+/// Perform the same work as `validateBatch` on a background thread, so that the node event loop
+/// is not blocked for the duration of the verification and validation of a large batch. The work
+/// is run on the thread pool used for parallel verification (see `setMaxThreads`), so concurrent
+/// batches share its threads rather than each spawning a thread.
 ///
-/// Takes an HMAC key as the first argument and an array of messages as the second argument.
-/// The HMAC key must be of type `string` or `ArrayBuffer`. Message signatures are verified without
-/// an HMAC key if the value of the argument is a `string` with value `none`.
+/// Takes an HMAC key as the first argument, an array of messages as the second argument, an
+/// options object as the third argument, a previous message value as the fourth argument (the
+/// `string` value `none` if there is no previous message) and a callback as the fifth argument.
+/// All options of `validateBatch` are supported. The callback is invoked once with the elements
+/// of the tuple returned by `validateBatch`: the error message, the keys and the additional
+/// information.
+#[node_bindgen(name = "validateBatchBackground", mt)]
+fn verify_validate_messages_background<
+    F: Fn(Option<String>, Option<BatchKeys>, Option<BatchInfo>) + Send + 'static,
+>(
+    hmac_key: HmacKey,
+    array: Messages,
+    options: BatchOptions,
+    previous: String,
+    cb: F,
+) {
+    let previous = match previous.as_str() {
+        "none" => None,
+        _ => Some(previous),
+    };
+    spawn_in_thread_pool(move || {
+        let (err_msg, keys, info) = validate_batch(hmac_key, array, options, previous);
+        cb(err_msg, keys, info)
+    });
+}
+
+/// Verify signatures and perform validation for an array of messages, each against its own
+/// previous message (includes HMAC key support).
 ///
-/// If verification fails, the cause of the error is returned along with the offending message.
-/// Note: this method only verifies message signatures; it does not perform full message validation
-/// (use `verify_validate_message_array` for complete verification and validation).
-#[node_bindgen(name = "verifySignatures")]
-fn verify_messages(hmac_key: HmacKey, array: Vec<String>) -> (Option<String>, Option<Vec<String>>) {
+/// Takes an HMAC key as the first argument, an array of messages as the second argument, an
+/// array of previous messages (one per message) as the third argument and an options object as
+/// the fourth argument. A previous message with the `string` value `none` means that the
+/// corresponding message is validated standalone, without any hash-chain checks. The `keyFormat`
+/// option selects the format of the returned keys (see `validateBatch`). If verification or
+/// validation fails, the cause of the error is returned along with the offending message and its
+/// index.
+#[node_bindgen(name = "validateWithPrevious")]
+fn verify_validate_messages_with_previous(
+    hmac_key: HmacKey,
+    array: Messages,
+    previous: Vec<String>,
+    options: BatchOptions,
+) -> (Option<String>, Option<Vec<String>>) {
     let valid_hmac = match is_valid_hmac_key(hmac_key) {
         Ok(key) => key,
         Err(err_msg) => return (Some(err_msg), None),
     };
+    let key_format = match options.key_format() {
+        Ok(format) => format,
+        Err(err_msg) => return (Some(err_msg), None),
+    };
     let hmac = valid_hmac.as_deref();
 
-    let mut msgs = Vec::new();
+    if array.len() != previous.len() {
+        let err_msg = format!(
+            "expected one previous message per message but got {} messages and {} previous messages",
+            array.len(),
+            previous.len()
+        );
+        return (Some(err_msg), None);
+    }
+
+    let mut msgs = Vec::with_capacity(array.len());
     for msg in array {
         let msg_bytes = msg.into_bytes();
         msgs.push(msg_bytes)
     }
+    let previous_msgs: Vec<Option<Vec<u8>>> = previous
+        .into_iter()
+        .map(|msg| match msg.as_str() {
+            "none" => None,
+            _ => Some(msg.into_bytes()),
+        })
+        .collect();
 
     // attempt batch verification and match on error to find invalid message value
-    match par_verify_message_values(&msgs, hmac, None) {
+    match in_thread_pool(|| par_verify_message_values(&msgs, hmac, None)) {
         Ok(_) => (),
         Err(e) => {
             let invalid_msg = &msgs
                 .iter()
                 .find(|msg| verify_message_value(msg, hmac).is_err());
             let invalid_msg_str = match invalid_msg {
-                Some(msg) => std::str::from_utf8(msg).unwrap_or(
-                    "unable to convert invalid message bytes to string slice; not valid utf8",
-                ),
+                Some(msg) => message_str(msg),
                 None => "parallel verification failed but no single invalid message was found",
             };
-            let err_msg = format!("found invalid message: {}: {}", e, invalid_msg_str);
+            let reason =
+                verification_failure_reason(&e, invalid_msg.map(|msg| msg.as_slice()), hmac);
+            let err_msg = format!(
+                "found invalid message: {}{}: {}",
+                reason,
+                caps_key_hint(&msgs, hmac),
+                invalid_msg_str
+            );
             return (Some(err_msg), None);
         }
+    };
+
+    // validate each message against its own previous message (if any) and find the first
+    // invalid message
+    let invalid = in_thread_pool(|| {
+        msgs.par_iter()
+            .zip(previous_msgs.par_iter())
+            .enumerate()
+            .map(|(idx, (msg, prev))| {
+                let result = match prev {
+                    Some(prev) => validate_message_value_hash_chain(msg, Some(prev)),
+                    None => validate_message_value(msg),
+                };
+                result.err().map(|e| (idx, e))
+            })
+            .find_first(|result| result.is_some())
+            .flatten()
+    });
+    if let Some((idx, e)) = invalid {
+        let invalid_msg_str = message_str(&msgs[idx]);
+        let err_msg = format!(
+            "found invalid message at index {}: {}: {}",
+            idx,
+            link_failure_reason(&e, &msgs[idx], previous_msgs[idx].as_deref()),
+            invalid_msg_str
+        );
+        return (Some(err_msg), None);
     }
 
-    let keys = hash(msgs);
+    let keys = hash(msgs, key_format);
     (None, Some(keys))
 }
 
-/// Verify signature and perform validation for a single message value (includes HMAC key support).
-///
-/// Takes an HMAC key as the first argument, message `value` as the second argument and an optional
-/// previous message `value` as the third argument. The HMAC key must be of type `string` or
-/// `ArrayBuffer`. Message signatures are verified without an HMAC key if the value of the argument
-/// is a `string` with value `none`. The previous message argument is expected when the message to
-/// be validated is not the first in the feed (ie. sequence number != 1 and previous != null).
+// the return type of `validateOOOBatch`: the error message, the keys, the failure details and
+// the indices of the unlinked messages
+type OutOfOrderResult = (
+    Option<String>,
+    Option<Vec<String>>,
+    Option<FailureDetails>,
+    Option<Vec<i64>>,
+);
+
+/// Verify signatures and perform validation for an array of out-of-order messages by a single
+/// author (includes HMAC key support).
 ///
-/// The return type is a tuple of `Option<String>`. The first element of the tuple holds the key
-/// (hash) of `msg_value` (if validation is successful) while the second element holds the error
-/// messages (if validation fails). Only the key for `msg_value` is returned; the key for `previous`
-/// is not.
+/// Takes an HMAC key as the first argument, an array of messages as the second argument and an
+/// options object as the third argument. The HMAC key must be of type `string` or `ArrayBuffer`.
+/// Message signatures are verified without an HMAC key if the value of the argument is `null`,
+/// `undefined` or the `string` value `none`. The `keyFormat` and `revokedKeys` options are
+/// supported as in `validateBatch`. If verification or validation fails, the cause of the error is
+/// returned along with the offending message, followed by an object of shape `{ code, index }`
+/// holding a stable, machine-readable code for the cause of the error and the index of the
+/// offending message (if a single message is at fault).
 ///
-/// Successful validation will yield a return value of `(Some<key>, None)` - where `key` is of type
-/// `String`. Unsuccessful validation will yield a return value of `(None, Some<err_msg>)` - where
-/// `err_msg` is of type `String` and includes the cause of the error and the offending message.
-#[node_bindgen(name = "validateSingle")]
-fn verify_validate_message(
+/// The `knownMessages` option is an array of `[key, sequence, previous]` triples describing
+/// messages of the feed already known to the caller (`previous` is the `string` value `none` for
+/// the first message of the feed). The links between the messages of the batch and the known
+/// messages are then checked wherever both ends are present: a message whose `previous` key
+/// belongs to a message in the combined set must directly follow it, and a known message whose
+/// `previous` key belongs to a message in the batch must directly follow that message. A broken
+/// link is an error (with the code `INVALID_SEQUENCE`). The last element of the returned tuple
+/// holds the indices of the messages of the batch whose `previous` message is not in the combined
+/// set (and which are therefore only validated on their own), if `knownMessages` was given.
+#[node_bindgen(name = "validateOOOBatch")]
+fn verify_validate_out_of_order_messages(
     hmac_key: HmacKey,
-    msg_value: String,
+    array: Messages,
+    options: BatchOptions,
+) -> OutOfOrderResult {
+    let links = options
+        .known_messages
+        .as_ref()
+        .map(|_| message_links(&array));
+    let known_messages = options.known_messages.clone();
+    match validate_ooo_batch(hmac_key, array, options) {
+        (None, Some(keys), None) => match (links, known_messages) {
+            (Some(links), Some(known)) => match check_known_links(&links, &known) {
+                Ok(unlinked) => {
+                    let unlinked = unlinked.into_iter().map(|index| index as i64).collect();
+                    (None, Some(keys), None, Some(unlinked))
+                }
+                Err((index, err_msg)) => {
+                    let details = FailureDetails::new("INVALID_SEQUENCE", Some(index));
+                    (Some(err_msg), None, details, None)
+                }
+            },
+            _ => (None, Some(keys), None, None),
+        },
+        (err_msg, keys, details) => (err_msg, keys, details, None),
+    }
+}
+
+// the legacy key, sequence number and previous key of a message, if it can be parsed
+struct MessageLink {
+    key: String,
+    sequence: u64,
     previous: Option<String>,
-) -> (Option<String>, Option<String>) {
+    msg: String,
+}
+
+fn message_links(msgs: &[Message]) -> Vec<Option<MessageLink>> {
+    msgs.iter()
+        .map(|msg| {
+            from_slice::<SsbMessageValue>(msg.as_bytes())
+                .ok()
+                .map(|value| MessageLink {
+                    key: utils::multihash_from_bytes(msg.as_bytes()).to_legacy_string(),
+                    sequence: value.sequence,
+                    previous: value.previous.map(|previous| previous.to_legacy_string()),
+                    msg: message_str(msg.as_bytes()).to_owned(),
+                })
+        })
+        .collect()
+}
+
+// check the links between the messages of an out-of-order batch (given by `links`) and the
+// messages known to the caller, wherever both ends of a link are present. returns the indices of
+// the messages whose previous message is in neither set, or the index and error message of the
+// first message with a broken link
+fn check_known_links(
+    links: &[Option<MessageLink>],
+    known: &[(String, i64, String)],
+) -> Result<Vec<usize>, (usize, String)> {
+    // the sequence number of each message in the combined set, by key
+    let mut sequences: BTreeMap<&str, i64> = BTreeMap::new();
+    for (key, sequence, _) in known {
+        sequences.insert(key, *sequence);
+    }
+    for link in links.iter().flatten() {
+        sequences.insert(&link.key, link.sequence as i64);
+    }
+    // the known messages which follow a message of the batch, by the key of that message
+    let mut successors: BTreeMap<&str, Vec<(&str, i64)>> = BTreeMap::new();
+    for (key, sequence, previous) in known {
+        if previous != "none" {
+            successors
+                .entry(previous)
+                .or_default()
+                .push((key, *sequence));
+        }
+    }
+
+    let mut unlinked = Vec::new();
+    for (index, link) in links.iter().enumerate() {
+        // messages which could not be parsed have been rejected by validation
+        let link = match link {
+            Some(link) => link,
+            None => continue,
+        };
+        let sequence = link.sequence as i64;
+        // the first message of the feed has no previous message to link to
+        if let Some(previous) = &link.previous {
+            match sequences.get(previous.as_str()) {
+                Some(&previous_sequence) if previous_sequence != sequence - 1 => {
+                    let err_msg = format!(
+                        "found invalid message at index {}: previous message {} has sequence {} but expected {}: {}",
+                        index, previous, previous_sequence, sequence - 1, link.msg
+                    );
+                    return Err((index, err_msg));
+                }
+                Some(_) => (),
+                None => unlinked.push(index),
+            }
+        }
+        for (key, successor_sequence) in successors.get(link.key.as_str()).into_iter().flatten() {
+            if *successor_sequence != sequence + 1 {
+                let err_msg = format!(
+                    "found invalid message at index {}: known message {} with sequence {} follows this message, expected sequence {}: {}",
+                    index, key, successor_sequence, sequence + 1, link.msg
+                );
+                return Err((index, err_msg));
+            }
+        }
+    }
+    Ok(unlinked)
+}
+
+// shared implementation of `validateOOOBatch` and `validateOOOBatchEnvelope`
+fn validate_ooo_batch(
+    hmac_key: HmacKey,
+    array: Messages,
+    options: BatchOptions,
+) -> (Option<String>, Option<Vec<String>>, Option<FailureDetails>) {
     let valid_hmac = match is_valid_hmac_key(hmac_key) {
         Ok(key) => key,
-        Err(err_msg) => return (Some(err_msg), None),
+        Err(err_msg) => {
+            return (
+                Some(err_msg),
+                None,
+                FailureDetails::new("INVALID_HMAC_KEY", None),
+            )
+        }
+    };
+    let key_format = match options.key_format() {
+        Ok(format) => format,
+        Err(err_msg) => {
+            return (
+                Some(err_msg),
+                None,
+                FailureDetails::new("INVALID_OPTIONS", None),
+            )
+        }
     };
     let hmac = valid_hmac.as_deref();
 
-    let msg_bytes = msg_value.into_bytes();
-    let previous_msg_bytes = previous.map(|msg| msg.into_bytes());
+    let mut msgs = Vec::with_capacity(array.len());
+    for msg in array {
+        let msg_bytes = msg.into_bytes();
+        msgs.push(msg_bytes)
+    }
 
-    // attempt verification and match on error to find invalid message
-    match verify_message_value(&msg_bytes, hmac) {
+    if let Some(revoked_keys) = &options.revoked_keys {
+        if let Err((index, err_msg)) = check_revoked(&msgs, revoked_keys) {
+            let details = FailureDetails::new("REVOKED_AUTHOR", Some(index));
+            return (Some(err_msg), None, details);
+        }
+    }
+
+    // attempt batch verification and match on error to find invalid message value
+    match in_thread_pool(|| par_verify_message_values(&msgs, hmac, None)) {
         Ok(_) => (),
         Err(e) => {
-            let invalid_msg_str = std::str::from_utf8(&msg_bytes).unwrap_or(
-                "unable to convert invalid message bytes to string slice; not valid utf8",
+            let invalid_index = msgs
+                .iter()
+                .position(|msg| verify_message_value(msg, hmac).is_err());
+            let invalid_msg = &invalid_index.map(|index| &msgs[index]);
+            let invalid_msg_str = match invalid_msg {
+                Some(msg) => message_str(msg),
+                None => "parallel verification failed but no single invalid message was found",
+            };
+            let reason =
+                verification_failure_reason(&e, invalid_msg.map(|msg| msg.as_slice()), hmac);
+            let err_msg = format!(
+                "found invalid message: {}{}: {}",
+                reason,
+                caps_key_hint(&msgs, hmac),
+                invalid_msg_str
             );
-            let err_msg = format!("found invalid message: {}: {}", e, invalid_msg_str);
-            return (Some(err_msg), None);
+            let details = FailureDetails::new(verification_error_code(&e), invalid_index);
+            return (Some(err_msg), None, details);
         }
     };
 
-    // attempt validation and match on error to find invalid message
-    match validate_message_value_hash_chain(&msg_bytes, previous_msg_bytes) {
+    // attempt batch validation and match on error to find invalid message
+    match in_thread_pool(|| {
+        par_validate_ooo_message_value_hash_chain_of_feed::<_, &[u8]>(&msgs, None)
+    }) {
         Ok(_) => (),
         Err(e) => {
-            let invalid_msg_str = std::str::from_utf8(&msg_bytes).unwrap_or(
-                "unable to convert invalid message bytes to string slice; not valid utf8",
-            );
+            let invalid_index = msgs.iter().position(|msg| {
+                validate_ooo_message_value_hash_chain::<_, &[u8]>(msg, None).is_err()
+            });
+            let invalid_msg = &invalid_index.map(|index| &msgs[index]);
+            let invalid_msg_str = match invalid_msg {
+                Some(msg) => message_str(msg),
+                None => "parallel validation failed but no single invalid message was found",
+            };
+            let reason = match invalid_msg {
+                Some(msg) => link_failure_reason(&e, msg, None),
+                None => validation_failure_reason(&e),
+            };
+            let err_msg = format!("found invalid message: {}: {}", reason, invalid_msg_str);
+            let details = FailureDetails::new(validation_error_code(&e), invalid_index);
+            return (Some(err_msg), None, details);
+        }
+    }
+
+    let keys = hash(msgs, key_format);
+    (None, Some(keys), None)
+}
+
+/// Verify signatures and perform validation for an array of out-of-order messages, summarising
+/// the result by reason of failure (includes HMAC key support).
+///
+/// Takes an HMAC key as the first argument, an array of messages as the second argument and an
+/// options object as the third argument. The `keyFormat` option selects the format of the example
+/// keys (see `validateBatch`). Unlike `validateOOOBatch`, validation does not stop at the first
+/// invalid message: each message is verified and validated on its own (in parallel), which is
+/// useful to characterise the quality of a large dump of messages from a peer.
+///
+/// The return type is a tuple of the error message (if the batch could not be processed at all)
+/// and an object of shape `{ valid, invalid, reasons }`, where `valid` and `invalid` are the
+/// number of valid and invalid messages and `reasons` maps the error code of each failure (as
+/// reported by `validateBatchSettled`, eg. `INVALID_SIGNATURE` or `INVALID_HASH_FUNCTION`) to an
+/// object of shape `{ count, examples }`. `examples` holds the keys of the first (up to five)
+/// messages which failed for that reason.
+#[node_bindgen(name = "validateOOOBatchSummary")]
+fn summarise_out_of_order_messages(
+    hmac_key: HmacKey,
+    array: Messages,
+    options: BatchOptions,
+) -> (Option<String>, Option<BatchSummary>) {
+    let valid_hmac = match is_valid_hmac_key(hmac_key) {
+        Ok(key) => key,
+        Err(err_msg) => return (Some(err_msg), None),
+    };
+    let key_format = match options.key_format() {
+        Ok(format) => format,
+        Err(err_msg) => return (Some(err_msg), None),
+    };
+    let hmac = valid_hmac.as_deref();
+
+    let codes: Vec<Option<&'static str>> = in_thread_pool(|| {
+        array
+            .par_iter()
+            .map(|msg| {
+                let msg = msg.as_bytes();
+                if let Err(e) = verify_message_value(msg, hmac) {
+                    return Some(verification_error_code(&e));
+                }
+                validate_ooo_message_value_hash_chain::<_, &[u8]>(msg, None)
+                    .err()
+                    .map(|e| validation_error_code(&e))
+            })
+            .collect()
+    });
+
+    let mut summary = BatchSummary {
+        valid: 0,
+        invalid: 0,
+        reasons: BTreeMap::new(),
+    };
+    for (msg, code) in array.iter().zip(codes) {
+        let code = match code {
+            Some(code) => code,
+            None => {
+                summary.valid += 1;
+                continue;
+            }
+        };
+        summary.invalid += 1;
+        let bucket = summary.reasons.entry(code).or_default();
+        bucket.count += 1;
+        if bucket.examples.len() < MAX_EXAMPLE_KEYS {
+            let multihash = utils::multihash_from_bytes(msg.as_bytes());
+            bucket.examples.push(format_key(multihash, key_format));
+        }
+    }
+    (None, Some(summary))
+}
+
+/// Verify signature and perform validation for a single out-of-order message value which is
+/// expected at a known position in its feed (includes HMAC key support).
+///
+/// Takes an HMAC key as the first argument, a message value as the second argument, the expected
+/// `previous` key (in legacy format) as the third argument and the expected sequence number as
+/// the fourth argument. The expected key is the `string` value `none` if the message is expected
+/// to be the first in its feed. The message is validated as in `validateOOOBatch` and must have
+/// the expected `previous` and `sequence` values. This allows a message to be inserted into a gap
+/// of an existing feed.
+///
+/// The return type is a tuple of the error message and the key of the message.
+#[node_bindgen(name = "validateOOOSingle")]
+fn verify_validate_out_of_order_message(
+    hmac_key: HmacKey,
+    msg_value: String,
+    expected_previous: String,
+    expected_sequence: i64,
+) -> (Option<String>, Option<String>) {
+    let valid_hmac = match is_valid_hmac_key(hmac_key) {
+        Ok(key) => key,
+        Err(err_msg) => return (Some(err_msg), None),
+    };
+    let hmac = valid_hmac.as_deref();
+
+    let msg_bytes = msg_value.into_bytes();
+    let invalid_msg_str = message_str(&msg_bytes);
+
+    if let Err(e) = verify_message_value(&msg_bytes, hmac) {
+        let reason = verification_failure_reason(&e, Some(&msg_bytes), hmac);
+        let err_msg = format!("found invalid message: {}: {}", reason, invalid_msg_str);
+        return (Some(err_msg), None);
+    }
+    if let Err(e) = validate_ooo_message_value_hash_chain::<_, &[u8]>(&msg_bytes, None) {
+        let reason = link_failure_reason(&e, &msg_bytes, None);
+        let err_msg = format!("found invalid message: {}: {}", reason, invalid_msg_str);
+        return (Some(err_msg), None);
+    }
+
+    // the message has been validated, so it can be parsed
+    let value = match from_slice::<SsbMessageValue>(&msg_bytes) {
+        Ok(value) => value,
+        Err(e) => {
             let err_msg = format!("found invalid message: {}: {}", e, invalid_msg_str);
             return (Some(err_msg), None);
         }
     };
+    if i64::try_from(value.sequence) != Ok(expected_sequence) {
+        let err_msg = format!(
+            "found invalid message: expected sequence {} but found {}: {}",
+            expected_sequence, value.sequence, invalid_msg_str
+        );
+        return (Some(err_msg), None);
+    }
+    let previous = value
+        .previous
+        .map(|previous| previous.to_legacy_string())
+        .unwrap_or_else(|| "none".to_string());
+    if previous != expected_previous {
+        let expected = match expected_previous.as_str() {
+            "none" => "null",
+            key => key,
+        };
+        let err_msg = format!(
+            "found invalid message: {}: {}",
+            broken_link_reason("previous message does not match", &msg_bytes, expected),
+            invalid_msg_str
+        );
+        return (Some(err_msg), None);
+    }
+
+    let multihash = utils::multihash_from_bytes(&msg_bytes);
+    (None, Some(multihash.to_legacy_string()))
+}
+
+/// Verify signature and perform validation for a single message value, and check that it fits
+/// between a claimed predecessor and successor in its feed (includes HMAC key support).
+///
+/// Takes an HMAC key as the first argument, a message value as the second argument, the value of
+/// the previous message as the third argument and the value of the next message as the fourth
+/// argument. Either neighbour is the `string` value `none` if it is not to be checked (eg. for the
+/// first or latest message of a feed). The message is verified and validated on its own, then
+/// validated against the previous message (as in `validateSingle`), and finally the next message
+/// is validated against it (its `previous` must be the key of the message). The signature of the
+/// neighbours is not verified.
+///
+/// The return type is a tuple of the error message, the broken link (`previous` or `next`, if the
+/// error concerns a link rather than the message itself) and the key of the message.
+#[node_bindgen(name = "validateInPlace")]
+fn verify_validate_message_in_place(
+    hmac_key: HmacKey,
+    msg_value: String,
+    previous: String,
+    next: String,
+) -> (Option<String>, Option<String>, Option<String>) {
+    let valid_hmac = match is_valid_hmac_key(hmac_key) {
+        Ok(key) => key,
+        Err(err_msg) => return (Some(err_msg), None, None),
+    };
+    let hmac = valid_hmac.as_deref();
+
+    let msg_bytes = msg_value.into_bytes();
+    let invalid_msg_str = message_str(&msg_bytes);
+
+    if let Err(e) = verify_message_value(&msg_bytes, hmac) {
+        let reason = verification_failure_reason(&e, Some(&msg_bytes), hmac);
+        let err_msg = format!("found invalid message: {}: {}", reason, invalid_msg_str);
+        return (Some(err_msg), None, None);
+    }
+    // validate the message on its own first, so that the links are only blamed for link errors
+    if let Err(e) = validate_ooo_message_value_hash_chain::<_, &[u8]>(&msg_bytes, None) {
+        let reason = link_failure_reason(&e, &msg_bytes, None);
+        let err_msg = format!("found invalid message: {}: {}", reason, invalid_msg_str);
+        return (Some(err_msg), None, None);
+    }
+
+    if previous != "none" {
+        if let Err(e) = validate_message_value_hash_chain(&msg_bytes, Some(previous.as_bytes())) {
+            let err_msg = format!(
+                "broken link to previous message: {}: {}",
+                link_failure_reason(&e, &msg_bytes, Some(previous.as_bytes())),
+                invalid_msg_str
+            );
+            return (Some(err_msg), Some("previous".to_string()), None);
+        }
+    }
+    if next != "none" {
+        if let Err(e) = validate_message_value_hash_chain(next.as_bytes(), Some(&msg_bytes)) {
+            let err_msg = format!(
+                "broken link to next message: {}: {}",
+                link_failure_reason(&e, next.as_bytes(), Some(&msg_bytes)),
+                next
+            );
+            return (Some(err_msg), Some("next".to_string()), None);
+        }
+    }
 
-    // generate multihash from message value bytes
     let multihash = utils::multihash_from_bytes(&msg_bytes);
-    let key = multihash.to_legacy_string();
-    (None, Some(key))
+    (None, None, Some(multihash.to_legacy_string()))
 }
 
-/// Verify signatures and perform validation for an array of ordered message values by a single
-/// author (includes HMAC key support).
+// the return type of `validateMultiAuthorBatch`: the error message, the keys, the author of the
+// offending message, the feed tips and the failure details
+type MultiAuthorResult = (
+    Option<String>,
+    Option<Vec<String>>,
+    Option<String>,
+    Option<FeedTips>,
+    Option<FailureDetails>,
+);
+
+/// Verify signatures and perform validation for an array of out-of-order messages by multiple
+/// authors (includes HMAC key support).
 ///
-/// Takes an HMAC key as the first argument, an array of message values as the second argument
-/// and an optional previous message value as the third argument. The HMAC key must be of type
-/// `string` or `ArrayBuffer`. Message signatures are verified without an HMAC key if the value
-/// of the argument is a `string` with value `none`. The previous message argument is expected
-/// when the array of messages does not start from the beginning of the feed (ie. sequence number
-/// != 1 and previous != null). If verification or validation fails, the cause of the error is
+/// Takes an HMAC key as the first argument, an array of messages as the second argument and an
+/// options object as the third argument. The HMAC key must be of type `string` or `ArrayBuffer`.
+/// Message signatures are verified without an HMAC key if the value of the argument is `null`,
+/// `undefined` or the `string` value `none`. The `keyFormat` and `revokedKeys` options are
+/// supported as in `validateBatch`. If verification or validation fails, the cause of the error is
 /// returned along with the offending message.
-#[node_bindgen(name = "validateBatch")]
-fn verify_validate_messages(
+///
+/// If the `feedTips` option is `true`, the latest message of each feed in the batch is returned
+/// on success: an object mapping each author to an object of shape `{ key, sequence }`. This
+/// allows an index to advance the state of each feed without recomputing it.
+///
+/// If the `chainPerAuthor` option is `true`, the messages are also grouped by author and the hash
+/// chain of each feed is validated in order of sequence number, as in `validateBatch`, so that
+/// the messages of each author in the batch must form a contiguous, unforked chain. The first
+/// message of a feed (sequence 1) is checked as such; otherwise the first message of an author
+/// is only validated on its own. The keys are returned in the order of the input, as usual.
+///
+/// The return type is a tuple of the error message, the keys of the messages, the author of the
+/// offending message (if verification or validation failed and the author could be parsed), the
+/// feed tips (if requested) and the error code and index of the offending message (as returned by
+/// `validateOOOBatch`).
+#[node_bindgen(name = "validateMultiAuthorBatch")]
+fn verify_validate_multi_author_messages(
     hmac_key: HmacKey,
-    array: Vec<String>,
-    previous: Option<String>,
-) -> (Option<String>, Option<Vec<String>>) {
+    array: Messages,
+    options: BatchOptions,
+) -> MultiAuthorResult {
+    let positions = if options.feed_tips {
+        Some(feed_positions(&array))
+    } else {
+        None
+    };
+    match validate_multi_author_batch(hmac_key, array, options) {
+        (None, Some(keys), None, None) => {
+            let tips = positions.map(|positions| feed_tips(positions, &keys));
+            (None, Some(keys), None, tips, None)
+        }
+        (err_msg, keys, author, details) => (err_msg, keys, author, None, details),
+    }
+}
+
+// shared implementation of `validateMultiAuthorBatch` and `validateMultiAuthorBatchEnvelope`
+fn validate_multi_author_batch(
+    hmac_key: HmacKey,
+    array: Messages,
+    options: BatchOptions,
+) -> (
+    Option<String>,
+    Option<Vec<String>>,
+    Option<String>,
+    Option<FailureDetails>,
+) {
     let valid_hmac = match is_valid_hmac_key(hmac_key) {
         Ok(key) => key,
-        Err(err_msg) => return (Some(err_msg), None),
+        Err(err_msg) => {
+            let details = FailureDetails::new("INVALID_HMAC_KEY", None);
+            return (Some(err_msg), None, None, details);
+        }
+    };
+    let key_format = match options.key_format() {
+        Ok(format) => format,
+        Err(err_msg) => {
+            let details = FailureDetails::new("INVALID_OPTIONS", None);
+            return (Some(err_msg), None, None, details);
+        }
     };
     let hmac = valid_hmac.as_deref();
 
-    let mut msgs = Vec::new();
+    let mut msgs = Vec::with_capacity(array.len());
     for msg in array {
         let msg_bytes = msg.into_bytes();
         msgs.push(msg_bytes)
     }
 
-    let previous_msg = previous.map(|msg| msg.into_bytes());
+    if let Some(revoked_keys) = &options.revoked_keys {
+        if let Err((index, err_msg)) = check_revoked(&msgs, revoked_keys) {
+            let details = FailureDetails::new("REVOKED_AUTHOR", Some(index));
+            return (Some(err_msg), None, message_author(&msgs[index]), details);
+        }
+    }
 
     // attempt batch verification and match on error to find invalid message value
-    match par_verify_message_values(&msgs, hmac, None) {
+    match in_thread_pool(|| par_verify_message_values(&msgs, hmac, None)) {
         Ok(_) => (),
         Err(e) => {
-            let invalid_msg = &msgs
+            let invalid_index = msgs
                 .iter()
-                .find(|msg| verify_message_value(msg, hmac).is_err());
+                .position(|msg| verify_message_value(msg, hmac).is_err());
+            let invalid_msg = &invalid_index.map(|index| &msgs[index]);
             let invalid_msg_str = match invalid_msg {
-                Some(msg) => std::str::from_utf8(msg).unwrap_or(
-                    "unable to convert invalid message bytes to string slice; not valid utf8",
-                ),
+                Some(msg) => message_str(msg),
                 None => "parallel verification failed but no single invalid message was found",
             };
-            let err_msg = format!("found invalid message: {}: {}", e, invalid_msg_str);
-            return (Some(err_msg), None);
+            let author = invalid_msg.and_then(|msg| message_author(msg));
+            let reason =
+                verification_failure_reason(&e, invalid_msg.map(|msg| msg.as_slice()), hmac);
+            let err_msg = format!(
+                "found invalid message: {}{}: {}",
+                reason,
+                caps_key_hint(&msgs, hmac),
+                invalid_msg_str
+            );
+            let details = FailureDetails::new(verification_error_code(&e), invalid_index);
+            return (Some(err_msg), None, author, details);
         }
     };
 
-    // attempt batch validation and match on error to find invalid message value
-    match par_validate_message_value_hash_chain_of_feed(&msgs, previous_msg.as_ref()) {
+    // attempt batch validation and match on error to find invalid message
+    match in_thread_pool(|| par_validate_message_value(&msgs)) {
         Ok(_) => (),
         Err(e) => {
-            let invalid_msg = &msgs
+            let invalid_index = msgs
                 .iter()
-                .find(|msg| validate_message_value_hash_chain(msg, previous_msg.as_ref()).is_err());
+                .position(|msg| validate_message_value(msg).is_err());
+            let invalid_msg = &invalid_index.map(|index| &msgs[index]);
             let invalid_msg_str = match invalid_msg {
-                Some(msg) => std::str::from_utf8(msg).unwrap_or(
-                    "unable to convert invalid message bytes to string slice; not valid utf8",
-                ),
+                Some(msg) => message_str(msg),
                 None => "parallel validation failed but no single invalid message was found",
             };
-            let err_msg = format!("found invalid message: {}: {}", e, invalid_msg_str);
-            return (Some(err_msg), None);
+            let author = invalid_msg.and_then(|msg| message_author(msg));
+            let reason = match invalid_msg {
+                Some(msg) => link_failure_reason(&e, msg, None),
+                None => validation_failure_reason(&e),
+            };
+            let err_msg = format!("found invalid message: {}: {}", reason, invalid_msg_str);
+            let details = FailureDetails::new(validation_error_code(&e), invalid_index);
+            return (Some(err_msg), None, author, details);
         }
     }
 
-    let keys = hash(msgs);
-    (None, Some(keys))
+    if options.chain_per_author {
+        if let Err((index, err_msg, code)) = check_author_chains(&msgs) {
+            let details = FailureDetails::new(code, Some(index));
+            return (Some(err_msg), None, message_author(&msgs[index]), details);
+        }
+    }
+
+    let keys = hash(msgs, key_format);
+    (None, Some(keys), None, None)
 }
 
-/// Verify signatures and perform validation for an array of out-of-order messages by a single
-/// author (includes HMAC key support).
+/// Perform the same work as `validateBatch`, hardened against adversarial input from untrusted
+/// peers.
 ///
-/// Takes an HMAC key as the first argument and an array of messages as the second argument.
-/// The HMAC key must be of type `string` or `ArrayBuffer`. Message signatures are verified
-/// without an HMAC key if the value of the argument is a `string` with value `none`. If
-/// verification or validation fails, the cause of the error is returned along with the
-/// offending message.
-#[node_bindgen(name = "validateOOOBatch")]
-fn verify_validate_out_of_order_messages(
+/// Takes the same arguments and supports the same options as `validateBatch`. Any panic raised
+/// while parsing, verifying or validating the messages is caught and returned as an error rather
+/// than unwinding into node. Stack exhaustion cannot be caught, so the `maxDepth` limit defaults
+/// to 128 levels here (rather than being unset) and also applies to the previous message.
+#[node_bindgen(name = "validateBatchHardened")]
+fn verify_validate_messages_hardened(
     hmac_key: HmacKey,
-    array: Vec<String>,
-) -> (Option<String>, Option<Vec<String>>) {
+    array: Messages,
+    mut options: BatchOptions,
+    previous: Option<String>,
+) -> (Option<String>, Option<BatchKeys>, Option<BatchInfo>) {
+    let max_depth = *options.max_depth.get_or_insert(HARDENED_MAX_DEPTH);
+    if let Some(previous) = &previous {
+        let depth = json_depth(previous.as_bytes()).saturating_sub(1);
+        if depth > max_depth {
+            let err_msg = format!(
+                "found invalid previous message: content nesting depth of {} exceeds the maximum of {}",
+                depth, max_depth
+            );
+            return (
+                Some(err_msg),
+                None,
+                failure_info("CONTENT_LIMIT_EXCEEDED", None),
+            );
+        }
+    }
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        validate_batch(hmac_key, array, options, previous)
+    }));
+    match result {
+        Ok(result) => result,
+        Err(payload) => {
+            let reason = payload
+                .downcast_ref::<&str>()
+                .map(|reason| reason.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown cause".to_string());
+            let err_msg = format!("validation aborted on malformed input: {}", reason);
+            (Some(err_msg), None, failure_info("MALFORMED_INPUT", None))
+        }
+    }
+}
+
+/// Perform the same work as `validateBatch`, but return the entire result as a single compact
+/// `ArrayBuffer` (a result envelope) instead of a tuple of js values.
+///
+/// Takes the same arguments as `validateBatch`. The `keyFormat` option is ignored (keys are
+/// always packed as raw digests) and no additional information is returned. Building a single
+/// buffer avoids creating a js string per key, which reduces the overhead of crossing the N-API
+/// boundary and the pressure on the garbage collector for large batches.
+///
+/// The envelope has the following layout:
+///
+/// - byte 0: the status; `0` if the batch is valid and `1` otherwise.
+/// - bytes 1 to 4: a count, as an unsigned 32 bit little-endian integer. This is the number of
+///   keys if the batch is valid, or the length in bytes of the error message otherwise.
+/// - the remaining bytes: either the keys, each as a 32 byte sha256 digest in order, or the
+///   (utf8 encoded) error message. No keys are included if the `hash` phase is skipped.
+#[node_bindgen(name = "validateBatchEnvelope")]
+fn verify_validate_messages_envelope(
+    hmac_key: HmacKey,
+    array: Messages,
+    mut options: BatchOptions,
+    previous: Option<String>,
+) -> ArrayBuffer {
+    options.key_format = Some("raw".to_string());
+    let result = match validate_batch(hmac_key, array, options, previous) {
+        (Some(err_msg), _, _) => Err(err_msg),
+        (None, Some(BatchKeys::Raw(digests)), _) => Ok(digests),
+        (None, _, _) => Ok(Vec::new()),
+    };
+    encode_envelope(result)
+}
+
+/// Perform the same work as `validateOOOBatch`, but return the result as an envelope (see
+/// `validateBatchEnvelope` for the layout).
+#[node_bindgen(name = "validateOOOBatchEnvelope")]
+fn verify_validate_out_of_order_messages_envelope(
+    hmac_key: HmacKey,
+    array: Messages,
+    mut options: BatchOptions,
+) -> ArrayBuffer {
+    options.key_format = None;
+    let result = match validate_ooo_batch(hmac_key, array, options) {
+        (Some(err_msg), _, _) => Err(err_msg),
+        (None, keys, _) => Ok(legacy_key_digests(keys.unwrap_or_default())),
+    };
+    encode_envelope(result)
+}
+
+/// Perform the same work as `validateMultiAuthorBatch`, but return the result as an envelope (see
+/// `validateBatchEnvelope` for the layout). The author of the offending message is not returned.
+#[node_bindgen(name = "validateMultiAuthorBatchEnvelope")]
+fn verify_validate_multi_author_messages_envelope(
+    hmac_key: HmacKey,
+    array: Messages,
+    mut options: BatchOptions,
+) -> ArrayBuffer {
+    options.key_format = None;
+    let result = match validate_multi_author_batch(hmac_key, array, options) {
+        (Some(err_msg), _, _, _) => Err(err_msg),
+        (None, keys, _, _) => Ok(legacy_key_digests(keys.unwrap_or_default())),
+    };
+    encode_envelope(result)
+}
+
+/// Limit the number of threads used for parallel verification and validation.
+///
+/// Takes the maximum number of threads as the only argument. A dedicated thread pool of that
+/// size is created and used by all batch functions from then on; by default, the global pool
+/// (with one thread per logical core) is used. The pool can only be created once, so this should
+/// be called at startup, before any messages are validated.
+///
+/// The return type is the error message, if the number of threads is not a positive integer, if
+/// the pool has already been created or if it could not be built.
+#[node_bindgen(name = "setMaxThreads")]
+fn set_max_threads(max_threads: f64) -> Option<String> {
+    if max_threads < 1.0 || max_threads.fract() != 0.0 {
+        return Some(format!(
+            "max threads must be a positive integer, found {}",
+            max_threads
+        ));
+    }
+    if THREAD_POOL.get().is_some() {
+        return Some("the thread pool has already been created".to_string());
+    }
+    let pool = match rayon::ThreadPoolBuilder::new()
+        .num_threads(max_threads as usize)
+        .build()
+    {
+        Ok(pool) => pool,
+        Err(e) => return Some(format!("unable to build the thread pool: {}", e)),
+    };
+    match THREAD_POOL.set(pool) {
+        Ok(()) => None,
+        Err(_) => Some("the thread pool has already been created".to_string()),
+    }
+}
+
+/// Limit the number of messages accepted by the batch functions.
+///
+/// Takes the maximum number of messages as the only argument (`Infinity` removes the limit,
+/// which is the default). Batches with more messages are rejected with `batch too large` before
+/// any memory is allocated for them, which guards against accidentally (or maliciously) passing
+/// an entire log as a single batch. Unlike the thread pool size, the limit may be changed at any
+/// time.
+///
+/// The return type is the error message, if the limit is not a positive integer.
+#[node_bindgen(name = "setMaxBatchSize")]
+fn set_max_batch_size(max_batch_size: f64) -> Option<String> {
+    if max_batch_size == f64::INFINITY {
+        MAX_BATCH_SIZE.store(usize::MAX, Ordering::Relaxed);
+        return None;
+    }
+    if max_batch_size < 1.0 || max_batch_size.fract() != 0.0 {
+        return Some(format!(
+            "max batch size must be a positive integer, found {}",
+            max_batch_size
+        ));
+    }
+    MAX_BATCH_SIZE.store(max_batch_size as usize, Ordering::Relaxed);
+    None
+}
+
+/// Set the number of message buffers kept for reuse by the batch functions.
+///
+/// Takes the maximum number of buffers as the only argument (`0` disables reuse; the default is
+/// 4096). The buffers holding the messages of a `validateBatch` (including
+/// `Validator.pushBatch`) are kept once validation is done and filled with the messages of the
+/// next batch, which reduces allocator churn when thousands of small batches are validated per
+/// second. Buffers beyond the new size are released.
+///
+/// The return type is the error message, if the size is not a non-negative integer.
+#[node_bindgen(name = "setBufferPoolSize")]
+fn set_buffer_pool_size(pool_size: f64) -> Option<String> {
+    if pool_size < 0.0 || pool_size.fract() != 0.0 || pool_size.is_infinite() {
+        return Some(format!(
+            "buffer pool size must be a non-negative integer, found {}",
+            pool_size
+        ));
+    }
+    MAX_POOLED_BUFFERS.store(pool_size as usize, Ordering::Relaxed);
+    if let Ok(mut pool) = BUFFER_POOL.lock() {
+        pool.truncate(pool_size as usize);
+        pool.shrink_to_fit();
+    }
+    None
+}
+
+/// Recommend a batch size (number of messages) for the batch validation functions.
+///
+/// Takes an optional sample message size (in bytes) as the only argument. The recommendation
+/// is a heuristic based on the number of threads in the pool used for parallel verification and
+/// validation: each thread is given roughly 256 KiB of message data when a sample size is
+/// provided, or 250 messages otherwise. Batches of this size keep all threads busy without
+/// holding the event loop for longer than necessary.
+#[node_bindgen(name = "recommendedBatchSize")]
+fn recommended_batch_size(sample_msg_size: Option<f64>) -> i64 {
+    let threads = thread_count();
+    let per_thread = match sample_msg_size {
+        Some(size) if size >= 1.0 => ((BYTES_PER_THREAD as f64 / size) as usize)
+            .clamp(MIN_MESSAGES_PER_THREAD, MAX_MESSAGES_PER_THREAD),
+        _ => MESSAGES_PER_THREAD,
+    };
+    (threads * per_thread) as i64
+}
+
+/// Compute a fingerprint of the validator configuration.
+///
+/// Takes an HMAC key as the first argument and an options object (as accepted by
+/// `validateBatch`) as the second argument. The fingerprint is a short hex string derived from
+/// the crate version, the maximum message length, the limit set with `setMaxBatchSize`, the HMAC
+/// key (if any) and every option which applies to all batches: the previous link
+/// (`previousKey`, `previousSequence`) and `knownMessages` describe the input of a single call
+/// and are left out. Validation results produced under a different fingerprint may differ and
+/// should be recomputed.
+///
+/// The return type is a tuple of the error message (if the HMAC key or options are invalid) and
+/// the fingerprint.
+#[node_bindgen(name = "configFingerprint")]
+fn config_fingerprint(
+    hmac_key: HmacKey,
+    mut options: BatchOptions,
+) -> (Option<String>, Option<String>) {
     let valid_hmac = match is_valid_hmac_key(hmac_key) {
         Ok(key) => key,
         Err(err_msg) => return (Some(err_msg), None),
     };
-    let hmac = valid_hmac.as_deref();
+    if !options.both_key_formats() && !options.raw_keys() {
+        if let Err(err_msg) = options.key_format() {
+            return (Some(err_msg), None);
+        }
+    }
+    // `legacy` is the default key format
+    if options.key_format.as_deref() == Some("legacy") {
+        options.key_format = None;
+    }
 
-    let mut msgs = Vec::new();
-    for msg in array {
-        let msg_bytes = msg.into_bytes();
-        msgs.push(msg_bytes)
+    // the hmac key itself is hashed so that it cannot be recovered from the fingerprint
+    let hmac = valid_hmac.map(|key| ssb_crypto::hash(&key).0);
+    let config = fingerprint_config(&options, hmac);
+    let digest = ssb_crypto::hash(config.as_bytes());
+    let fingerprint: String = digest.0[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    (None, Some(fingerprint))
+}
+
+// version of the serialisation digested by `configFingerprint`, to be bumped whenever it changes
+const FINGERPRINT_FORMAT: u32 = 1;
+
+// the canonical serialisation of everything which may change the result of validation, as
+// digested by `configFingerprint`. each value is written as `name=<length>:<value>;` (or `name;`
+// if it is not set) in a fixed order, so that the encoding is unambiguous and stable across
+// releases of rust. lists whose order does not matter are sorted
+fn fingerprint_config(options: &BatchOptions, hmac: Option<[u8; 32]>) -> String {
+    // destructured in full, so that a new option cannot be left out by accident
+    let BatchOptions {
+        check_timestamps,
+        timestamp_stats,
+        timestamp_range,
+        key_format,
+        sort_by_sequence,
+        detect_migrations,
+        classify_types,
+        digest,
+        skip_verify,
+        skip_chain,
+        skip_hash,
+        expected_genesis_key,
+        expected_author,
+        // the previous link and the known messages are part of the input of a single call
+        previous_key: _,
+        previous_sequence: _,
+        deprecated_types,
+        allowed_types,
+        warn_disallowed_types,
+        revoked_keys,
+        signature_algorithms,
+        detect_encrypted,
+        content_hashes,
+        return_previous_key,
+        stats,
+        collect_feed_ids,
+        collect_blob_ids,
+        mask,
+        feed_tips,
+        chain_per_author,
+        known_messages: _,
+        check_base64,
+        strict_base64,
+        max_depth,
+        max_content_bytes,
+        report_gaps,
+    } = options;
+
+    let mut config = String::new();
+    let mut field = |name: &str, value: Option<String>| {
+        config.push_str(name);
+        if let Some(value) = value {
+            config.push_str(&format!("={}:{}", value.len(), value));
+        }
+        config.push(';');
+    };
+    let list = |items: &Option<Vec<String>>| {
+        items.as_ref().map(|items| {
+            let sorted: BTreeSet<&String> = items.iter().collect();
+            sorted
+                .into_iter()
+                .map(|item| format!("{}:{}", item.len(), item))
+                .collect::<String>()
+        })
+    };
+
+    field("format", Some(FINGERPRINT_FORMAT.to_string()));
+    field("version", Some(env!("CARGO_PKG_VERSION").to_string()));
+    field("max_message_length", Some(MAX_MESSAGE_LENGTH.to_string()));
+    let max_batch_size = MAX_BATCH_SIZE.load(Ordering::Relaxed);
+    field("max_batch_size", Some(max_batch_size.to_string()));
+    field("hmac", hmac.map(base64::encode));
+    for &(name, flag) in &[
+        ("check_timestamps", check_timestamps),
+        ("timestamp_stats", timestamp_stats),
+        ("timestamp_range", timestamp_range),
+        ("sort_by_sequence", sort_by_sequence),
+        ("detect_migrations", detect_migrations),
+        ("classify_types", classify_types),
+        ("digest", digest),
+        ("skip_verify", skip_verify),
+        ("skip_chain", skip_chain),
+        ("skip_hash", skip_hash),
+        ("warn_disallowed_types", warn_disallowed_types),
+        ("signature_algorithms", signature_algorithms),
+        ("detect_encrypted", detect_encrypted),
+        ("content_hashes", content_hashes),
+        ("return_previous_key", return_previous_key),
+        ("stats", stats),
+        ("collect_feed_ids", collect_feed_ids),
+        ("collect_blob_ids", collect_blob_ids),
+        ("mask", mask),
+        ("feed_tips", feed_tips),
+        ("chain_per_author", chain_per_author),
+        ("check_base64", check_base64),
+        ("strict_base64", strict_base64),
+        ("report_gaps", report_gaps),
+    ] {
+        field(name, Some(flag.to_string()));
     }
+    field("key_format", key_format.clone());
+    field("expected_genesis_key", expected_genesis_key.clone());
+    field("expected_author", expected_author.clone());
+    field("deprecated_types", list(deprecated_types));
+    field("allowed_types", list(allowed_types));
+    let revoked = revoked_keys.as_ref().map(|revoked| {
+        revoked
+            .iter()
+            .map(|(author, sequence)| format!("{} {}", author, sequence))
+            .collect::<Vec<String>>()
+    });
+    field("revoked_keys", list(&revoked));
+    field("max_depth", max_depth.map(|depth| depth.to_string()));
+    field(
+        "max_content_bytes",
+        max_content_bytes.map(|bytes| bytes.to_string()),
+    );
+    config
+}
 
-    // attempt batch verification and match on error to find invalid message value
-    match par_verify_message_values(&msgs, hmac, None) {
-        Ok(_) => (),
+/// A validator for a stream of batches, as during live replication.
+///
+/// Holds the last validated message (tip) of each author on the native side, so that each batch
+/// is chained onto the tip of its author without passing the previous message through js again.
+struct Validator {
+    tips: BTreeMap<String, String>,
+}
+
+#[node_bindgen]
+impl Validator {
+    #[node_bindgen(constructor)]
+    fn new() -> Self {
+        Self {
+            tips: BTreeMap::new(),
+        }
+    }
+
+    /// Perform the same work as `validateBatch`, chaining the batch onto the tip held by the
+    /// validator.
+    ///
+    /// Takes the arguments of `validateBatch`. If neither a previous message nor a previous link
+    /// (the `previousKey` and `previousSequence` options) is given, the tip of the author of the
+    /// batch is used as the previous message (if there is none, the batch must start at the
+    /// beginning of the feed). On success, the validated message with the highest sequence number
+    /// becomes the tip of its author, so that the next batch is chained onto it. With the
+    /// `reportGaps` option, this is the last message preceding the first gap. No tip is recorded
+    /// if signature verification or the hash-chain checks are skipped.
+    ///
+    /// The return type is the tuple returned by `validateBatch`.
+    #[node_bindgen(name = "pushBatch")]
+    fn push_batch(
+        &mut self,
+        hmac_key: HmacKey,
+        array: Messages,
+        options: BatchOptions,
+        previous: Option<String>,
+    ) -> (Option<String>, Option<BatchKeys>, Option<BatchInfo>) {
+        // messages exceeding the content limits are rejected before they are parsed here
+        let exceeded = array
+            .iter()
+            .any(|msg| exceeded_content_limit(msg.as_bytes(), &options).is_some());
+        if exceeded {
+            return validate_batch(hmac_key, array, options, previous);
+        }
+        let author = array.last().and_then(|msg| message_author(msg.as_bytes()));
+        // the candidate tips, in the order in which they are validated
+        let mut candidates: Vec<(Option<u64>, String)> = feed_positions(&array)
+            .into_iter()
+            .zip(array.iter())
+            .map(|(position, msg)| {
+                let sequence = position.map(|(_, sequence)| sequence);
+                (sequence, message_str(msg.as_bytes()).to_owned())
+            })
+            .collect();
+        if options.sort_by_sequence {
+            candidates.sort_by_key(|(sequence, _)| *sequence);
+        }
+        // a previous link takes the place of the tip
+        let linked = options.previous_key.is_some() || options.previous_sequence.is_some();
+        let previous = if linked {
+            previous
+        } else {
+            previous.or_else(|| {
+                author
+                    .as_ref()
+                    .and_then(|author| self.tips.get(author).cloned())
+            })
+        };
+        // messages which have not been verified and chained must not become a tip
+        let record_tip = !options.skip_verify && !options.skip_chain;
+
+        let result = validate_batch(hmac_key, array, options, previous);
+        // with `reportGaps`, only the messages preceding the first gap have been validated
+        let first_gap = result
+            .2
+            .as_ref()
+            .and_then(|info| info.gaps.as_ref())
+            .and_then(|gaps| gaps.first());
+        if let Some(&first_gap) = first_gap {
+            candidates.truncate(first_gap as usize);
+        }
+        let tip = candidates
+            .into_iter()
+            .max_by_key(|(sequence, _)| *sequence)
+            .map(|(_, msg)| msg);
+        if let (true, None, Some(author), Some(tip)) = (record_tip, &result.0, author, tip) {
+            self.tips.insert(author, tip);
+        }
+        result
+    }
+
+    /// Forget the tip of the given author (feed id), or the tips of all authors if no author is
+    /// given.
+    #[node_bindgen]
+    fn reset(&mut self, author: Option<String>) {
+        match author {
+            Some(author) => {
+                self.tips.remove(&author);
+            }
+            None => self.tips.clear(),
+        }
+    }
+}
+
+/// Register an HMAC key, returning a handle which is accepted in place of the key by every function
+/// taking an HMAC key (and by `verifySignatureBuffer`).
+///
+/// Takes an HMAC key as the only argument (as accepted by `verifySignatures`) and returns a tuple
+/// of the error message (if the key is invalid) and a numeric handle for the key. Registering the
+/// same key more than once returns the same handle.
+#[node_bindgen(name = "registerHmacKey")]
+fn register_hmac_key(hmac_key: HmacKey) -> (Option<String>, Option<i64>) {
+    match is_valid_hmac_key(hmac_key).and_then(store_hmac_key) {
+        Ok(handle) => (None, Some(handle)),
+        Err(err_msg) => (Some(err_msg), None),
+    }
+}
+
+// add a validated hmac key to the registry (unless it is already registered), returning its
+// handle
+fn store_hmac_key(valid_hmac: Option<Vec<u8>>) -> Result<i64, String> {
+    let mut keys = match HMAC_KEYS.write() {
+        Ok(keys) => keys,
+        Err(_) => return Err("hmac key registry is unavailable".to_string()),
+    };
+    let handle = match keys.iter().position(|key| *key == valid_hmac) {
+        Some(handle) => handle,
+        None => {
+            keys.push(valid_hmac);
+            keys.len() - 1
+        }
+    };
+    Ok(handle as i64)
+}
+
+/// Register an HMAC key read from an environment variable, as with `registerHmacKey`.
+///
+/// Takes the name of the environment variable as the only argument. The variable must hold a
+/// base64 or hex encoded key (surrounding whitespace is ignored). Unlike with `registerHmacKey`,
+/// an empty value or the string `none` is an error rather than the absence of a key. The key is
+/// read and registered by the native module, so that it is never passed through js.
+///
+/// The return type is a tuple of the error message (if the variable is not set or does not hold a
+/// valid key) and a numeric handle for the key, as returned by `registerHmacKey`.
+#[node_bindgen(name = "loadHmacKeyFromEnv")]
+fn load_hmac_key_from_env(var_name: String) -> (Option<String>, Option<i64>) {
+    let hmac = match std::env::var(&var_name) {
+        Ok(hmac) => hmac,
         Err(e) => {
-            let invalid_msg = &msgs
-                .iter()
-                .find(|msg| verify_message_value(msg, hmac).is_err());
-            let invalid_msg_str = match invalid_msg {
-                Some(msg) => std::str::from_utf8(msg).unwrap_or(
-                    "unable to convert invalid message bytes to string slice; not valid utf8",
-                ),
-                None => "parallel verification failed but no single invalid message was found",
-            };
-            let err_msg = format!("found invalid message: {}: {}", e, invalid_msg_str);
+            let err_msg = format!(
+                "hmac key environment variable `{}` invalid: {}",
+                var_name, e
+            );
             return (Some(err_msg), None);
         }
     };
+    let source = format!("hmac key environment variable `{}`", var_name);
+    load_hmac_key(&source, &hmac)
+}
 
-    // attempt batch validation and match on error to find invalid message
-    match par_validate_ooo_message_value_hash_chain_of_feed::<_, &[u8]>(&msgs, None) {
-        Ok(_) => (),
+/// Register an HMAC key read from a file, as with `registerHmacKey`.
+///
+/// Takes the path of the file as the only argument. The file must hold a base64 or hex encoded
+/// key (surrounding whitespace is ignored). As with `loadHmacKeyFromEnv`, an empty file or one
+/// holding `none` is an error and the key is never passed through js.
+///
+/// The return type is a tuple of the error message (if the file cannot be read or does not hold a
+/// valid key) and a numeric handle for the key, as returned by `registerHmacKey`.
+#[node_bindgen(name = "loadHmacKeyFromFile")]
+fn load_hmac_key_from_file(path: String) -> (Option<String>, Option<i64>) {
+    let hmac = match std::fs::read_to_string(&path) {
+        Ok(hmac) => hmac,
         Err(e) => {
-            let invalid_msg = &msgs
-                .iter()
-                .find(|msg| validate_ooo_message_value_hash_chain::<_, &[u8]>(msg, None).is_err());
-            let invalid_msg_str = match invalid_msg {
-                Some(msg) => std::str::from_utf8(msg).unwrap_or(
-                    "unable to convert invalid message bytes to string slice; not valid utf8",
-                ),
-                None => "parallel validation failed but no single invalid message was found",
-            };
-            let err_msg = format!("found invalid message: {}: {}", e, invalid_msg_str);
+            let err_msg = format!("hmac key file `{}` could not be read: {}", path, e);
             return (Some(err_msg), None);
         }
+    };
+    let source = format!("hmac key file `{}`", path);
+    load_hmac_key(&source, &hmac)
+}
+
+// shared implementation of `loadHmacKeyFromEnv` and `loadHmacKeyFromFile`. a key loaded from
+// configuration must be present: `none` (accepted by `registerHmacKey` for backward
+// compatibility) would otherwise silently register the absence of a key
+fn load_hmac_key(source: &str, hmac: &str) -> (Option<String>, Option<i64>) {
+    let hmac = hmac.trim();
+    if hmac.is_empty() || hmac == "none" {
+        return (Some(format!("{} does not hold a key", source)), None);
     }
+    match is_valid_hmac_key(HmacKey::Str(hmac.to_string())).and_then(store_hmac_key) {
+        Ok(handle) => (None, Some(handle)),
+        Err(err_msg) => (Some(err_msg), None),
+    }
+}
 
-    let keys = hash(msgs);
-    (None, Some(keys))
+/// Verify a detached ed25519 signature of an arbitrary payload.
+///
+/// Takes the public key (base64, optionally as a feed id, eg. `@<base64>.ed25519`) as the first
+/// argument, the signature (base64, optionally with the `.sig.ed25519` suffix) as the second
+/// argument and a buffer holding the signed payload as the third argument. Unlike the other
+/// verification functions, the payload is not interpreted as a message: this is intended for
+/// custom protocols built on the same key material.
+///
+/// The return type is a tuple of the error message (if the public key or signature is malformed)
+/// and whether the signature is valid.
+#[node_bindgen(name = "verifyDetached")]
+fn verify_detached(
+    public_key: String,
+    signature: String,
+    payload: JSArrayBuffer,
+) -> (Option<String>, Option<bool>) {
+    let public_key = match PublicKey::from_base64(&public_key) {
+        Some(public_key) => public_key,
+        None => return (Some(format!("public key invalid: {}", public_key)), None),
+    };
+    let signature = match Signature::from_base64(&signature) {
+        Some(signature) => signature,
+        None => return (Some(format!("signature invalid: {}", signature)), None),
+    };
+    (None, Some(public_key.verify(&signature, &payload)))
 }
 
-/// Verify signatures and perform validation for an array of out-of-order messages by multiple
-/// authors (includes HMAC key support).
+/// Verify the signature of a single message value held in a buffer, using a registered HMAC key.
 ///
-/// Takes an HMAC key as the first argument and an array of messages as the second argument. The
-/// HMAC key must be of type `string` or `ArrayBuffer`. Message signatures are verified without
-/// an HMAC key if the value of the argument is a `string` with value `none`. If  verification
-/// or validation fails, the cause of the error is returned along with the offending message.
-#[node_bindgen(name = "validateMultiAuthorBatch")]
-fn verify_validate_multi_author_messages(
+/// Takes a handle returned by `registerHmacKey` as the first argument and a buffer holding the
+/// message value as the second argument. The value must be encoded exactly as it was signed (ie.
+/// `JSON.stringify(value, null, 2)`). The buffer is borrowed for the duration of the call, so no
+/// intermediate copy of the message is made. Note: this method only verifies the message
+/// signature; it does not perform full message validation.
+///
+/// Returns the error message if verification fails, or `null` if the signature is valid.
+#[node_bindgen(name = "verifySignatureBuffer")]
+fn verify_message_buffer(hmac_handle: i64, msg: JSArrayBuffer) -> Option<String> {
+    let keys = match HMAC_KEYS.read() {
+        Ok(keys) => keys,
+        Err(_) => return Some("hmac key registry is unavailable".to_string()),
+    };
+    let hmac = match usize::try_from(hmac_handle)
+        .ok()
+        .and_then(|handle| keys.get(handle))
+    {
+        Some(key) => key.as_deref(),
+        None => return Some(format!("hmac key handle invalid: {}", hmac_handle)),
+    };
+
+    match verify_message_value(msg.as_bytes(), hmac) {
+        Ok(_) => None,
+        Err(e) => {
+            let invalid_msg_str = message_str(msg.as_bytes());
+            // release the registry lock before the diagnostic, which reads it again
+            let hmac = hmac.map(|key| key.to_vec());
+            drop(keys);
+            let reason = verification_failure_reason(&e, Some(msg.as_bytes()), hmac.as_deref());
+            Some(format!(
+                "found invalid message: {}: {}",
+                reason, invalid_msg_str
+            ))
+        }
+    }
+}
+
+/// Verify the signature of a single message value, without hash-chain validation.
+///
+/// Takes an HMAC key as the first argument and a message `value` as the second argument. This is
+/// intended for re-checking the signature of a message whose hash chain is already known to be
+/// valid (eg. after an upgrade of the signature verification code). Note: this method only
+/// verifies the message signature; it does not perform full message validation.
+///
+/// The return type is a tuple of the error message (if the hmac key is invalid or verification
+/// fails), the key of the message in legacy encoding and the error code.
+#[node_bindgen(name = "verifySingleSignature")]
+fn verify_single_signature(
     hmac_key: HmacKey,
-    array: Vec<String>,
-) -> (Option<String>, Option<Vec<String>>) {
+    msg_value: String,
+) -> (Option<String>, Option<String>, Option<String>) {
     let valid_hmac = match is_valid_hmac_key(hmac_key) {
         Ok(key) => key,
-        Err(err_msg) => return (Some(err_msg), None),
+        Err(err_msg) => return (Some(err_msg), None, Some("INVALID_HMAC_KEY".to_string())),
     };
     let hmac = valid_hmac.as_deref();
 
-    let mut msgs = Vec::new();
-    for msg in array {
-        let msg_bytes = msg.into_bytes();
-        msgs.push(msg_bytes)
+    let msg_bytes = msg_value.into_bytes();
+    if let Err(e) = verify_message_value(&msg_bytes, hmac) {
+        let invalid_msg_str = message_str(&msg_bytes);
+        let reason = verification_failure_reason(&e, Some(&msg_bytes), hmac);
+        let err_msg = format!("found invalid message: {}: {}", reason, invalid_msg_str);
+        return (
+            Some(err_msg),
+            None,
+            Some(verification_error_code(&e).to_string()),
+        );
     }
 
-    // attempt batch verification and match on error to find invalid message value
-    match par_verify_message_values(&msgs, hmac, None) {
-        Ok(_) => (),
-        Err(e) => {
-            let invalid_msg = &msgs
-                .iter()
-                .find(|msg| verify_message_value(msg, hmac).is_err());
-            let invalid_msg_str = match invalid_msg {
-                Some(msg) => std::str::from_utf8(msg).unwrap_or(
-                    "unable to convert invalid message bytes to string slice; not valid utf8",
-                ),
-                None => "parallel verification failed but no single invalid message was found",
-            };
-            let err_msg = format!("found invalid message: {}: {}", e, invalid_msg_str);
-            return (Some(err_msg), None);
+    let key = utils::multihash_from_bytes(&msg_bytes).to_legacy_string();
+    (None, Some(key), None)
+}
+
+/// Verify the signature of a single message value against several HMAC keys, as during an HMAC
+/// key rotation.
+///
+/// Takes a message value as the first argument and an array of HMAC keys as the second argument.
+/// Each key is accepted in any of the forms accepted by `verifySignatures`, including the `string`
+/// value `none` for keyless verification.
+/// The keys are tried in order. Note: this method only verifies the message signature; it does
+/// not perform full message validation.
+///
+/// The return type is a tuple of the error message (if a key is invalid or the signature is not
+/// valid under any of the keys) and the index of the first key under which the signature is valid.
+#[node_bindgen(name = "verifySingleMultiKey")]
+fn verify_message_multi_key(msg: String, hmac_keys: Vec<HmacKey>) -> (Option<String>, Option<i64>) {
+    let mut valid_hmacs = Vec::with_capacity(hmac_keys.len());
+    for hmac_key in hmac_keys {
+        match is_valid_hmac_key(hmac_key) {
+            Ok(key) => valid_hmacs.push(key),
+            Err(err_msg) => return (Some(err_msg), None),
         }
-    };
+    }
+    if valid_hmacs.is_empty() {
+        return (
+            Some("hmac keys invalid: at least one key is required".to_string()),
+            None,
+        );
+    }
 
-    // attempt batch validation and match on error to find invalid message
-    match par_validate_message_value(&msgs) {
-        Ok(_) => (),
-        Err(e) => {
-            let invalid_msg = &msgs.iter().find(|msg| validate_message_value(msg).is_err());
-            let invalid_msg_str = match invalid_msg {
-                Some(msg) => std::str::from_utf8(msg).unwrap_or(
-                    "unable to convert invalid message bytes to string slice; not valid utf8",
-                ),
-                None => "parallel validation failed but no single invalid message was found",
-            };
-            let err_msg = format!("found invalid message: {}: {}", e, invalid_msg_str);
-            return (Some(err_msg), None);
+    let msg_bytes = msg.into_bytes();
+    match valid_hmacs
+        .iter()
+        .position(|hmac| verify_message_value(&msg_bytes, hmac.as_deref()).is_ok())
+    {
+        Some(index) => (None, Some(index as i64)),
+        None => {
+            let invalid_msg_str = message_str(&msg_bytes);
+            let err_msg = format!(
+                "found invalid message: signature is not valid under any of the {} hmac keys: {}",
+                valid_hmacs.len(),
+                invalid_msg_str
+            );
+            (Some(err_msg), None)
         }
     }
+}
 
-    let keys = hash(msgs);
-    (None, Some(keys))
+// whether the crypto backend compiled in uses SIMD instructions, as opposed to whether the cpu
+// supports them
+fn simd_enabled() -> bool {
+    CRYPTO_BACKEND == "simd"
+}
+
+// whether the cpu supports the SIMD instructions used by accelerated ed25519 backends
+#[cfg(target_arch = "x86_64")]
+fn simd_supported() -> bool {
+    is_x86_feature_detected!("avx2")
+}
+
+#[cfg(target_arch = "aarch64")]
+fn simd_supported() -> bool {
+    std::arch::is_aarch64_feature_detected!("neon")
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn simd_supported() -> bool {
+    false
+}
+
+/// Run a known-good validation vector through the native module, as a post-install sanity check.
+///
+/// A valid message must be verified and validated (with the expected key) and a tampered copy of
+/// it must be rejected. This distinguishes a native module which loads but is broken (eg. due to
+/// a miscompiled crypto backend) from one which works.
+///
+/// The returned object is of shape `{ passed, reason, versionInfo }`, where `reason` describes the
+/// failure (or is `null`) and `versionInfo` is the object returned by `versionInfo`.
+#[node_bindgen(name = "selfTest")]
+fn self_test() -> SelfTestResult {
+    let reason = match validate_single(
+        no_hmac(),
+        SELF_TEST_MESSAGE.to_string(),
+        None,
+        KeyFormat::Legacy,
+    ) {
+        (Some(err_msg), _, _) => Some(format!("valid test vector was rejected: {}", err_msg)),
+        (None, Some(key), _) if key != SELF_TEST_KEY => Some(format!(
+            "valid test vector has key {} but expected {}",
+            key, SELF_TEST_KEY
+        )),
+        _ => {
+            let tampered = SELF_TEST_MESSAGE.replace("1600000000000", "1600000000001");
+            match validate_single(no_hmac(), tampered, None, KeyFormat::Legacy) {
+                (None, _, _) => Some("tampered test vector was accepted".to_string()),
+                (Some(_), _, _) => None,
+            }
+        }
+    };
+    SelfTestResult {
+        passed: reason.is_none(),
+        reason,
+        info: build_info(),
+    }
+}
+
+// the absence of an hmac key, as passed from js
+fn no_hmac() -> HmacKey {
+    HmacKey::None
+}
+
+/// Return the stable numeric code of each machine-readable error code.
+///
+/// The returned array holds a `[code, number]` pair for each error code (eg.
+/// `["INVALID_SIGNATURE", 100]`). Numbers in the 100 range are used for signature verification
+/// errors, in the 200 range for validation errors and in the 300 range for the errors of the
+/// additional checks requested via options. The mapping is append-only: the number of a code
+/// never changes and is never reused for another code.
+#[node_bindgen(name = "errorCodes")]
+fn error_codes() -> Vec<(String, i64)> {
+    NUMERIC_ERROR_CODES
+        .iter()
+        .map(|(code, number)| (code.to_string(), *number))
+        .collect()
+}
+
+/// Return build and runtime information about the native module.
+///
+/// The returned object holds the crate `version`, the curve25519-dalek `cryptoBackend` compiled in
+/// for signature verification (eg. `u64`), whether that backend uses SIMD instructions
+/// (`simdEnabled`) and whether the current cpu supports them (`simdSupported`). A binary with
+/// `simdEnabled` of `false` uses the portable verification path regardless of `simdSupported`.
+#[node_bindgen(name = "versionInfo")]
+fn version_info() -> VersionInfo {
+    build_info()
+}
+
+// shared implementation of `versionInfo` and `selfTest`
+fn build_info() -> VersionInfo {
+    VersionInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        crypto_backend: CRYPTO_BACKEND,
+        simd_enabled: simd_enabled(),
+        simd_supported: simd_supported(),
+    }
 }
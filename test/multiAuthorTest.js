@@ -93,3 +93,53 @@ test("batch validation of out-of-order multi-author messages", (t) => {
     );
   });
 });
+
+test("batch validation of multi-author messages reports the offending author", (t) => {
+  db.onReady(() => {
+    query(
+      fromDB(db),
+      toCallback((err, kvtMsgs) => {
+        if (err) t.fail(err);
+        const msgs = kvtMsgs.map((msg) => msg.value);
+        // invalidate the signature of the last message
+        const invalid = msgs.length - 1;
+        msgs[invalid] = Object.assign({}, msgs[invalid], {
+          timestamp: msgs[invalid].timestamp + 1,
+        });
+        validate.validateMultiAuthorBatch(hmacKey, msgs, (err) => {
+          t.match(err.message, /Signature was invalid/, "invalid signature");
+          t.equal(err.author, msgs[invalid].author, "author is reported");
+          t.end();
+        });
+      })
+    );
+  });
+});
+
+test("batch validation of multi-author messages returns the feed tips", (t) => {
+  db.onReady(() => {
+    query(
+      fromDB(db),
+      toCallback((err, kvtMsgs) => {
+        if (err) t.fail(err);
+        // the latest message of each author
+        const expected = {};
+        kvtMsgs.forEach(({ key, value }) => {
+          const tip = expected[value.author];
+          if (!tip || tip.sequence < value.sequence) {
+            expected[value.author] = { key, sequence: value.sequence };
+          }
+        });
+        const msgs = kvtMsgs.map((msg) => msg.value);
+        msgs.sort(() => Math.random() - 0.5);
+        const opts = { feedTips: true };
+        const cb = (err, keys, tips) => {
+          t.error(err);
+          t.deepEqual(tips, expected, "tip of each feed");
+          t.end();
+        };
+        validate.validateMultiAuthorBatch(hmacKey, msgs, opts, cb);
+      })
+    );
+  });
+});
@@ -38,6 +38,11 @@ const MESSAGES = 100;
 const AUTHORS = 1;
 // run each test x times
 const ITERATIONS = 10;
+// number of copies of the fixture in the large batch benchmark
+const LARGE_BATCH_REPEATS = 100;
+// number and size of the batches in the small batch benchmark
+const SMALL_BATCHES = 1000;
+const SMALL_BATCH_SIZE = 10;
 
 const hmacKey = null;
 
@@ -107,6 +112,45 @@ test("verifySignatures", (t) => {
   });
 });
 
+// batch verification of signatures for a large array of messages, where the
+// cost of allocating the message and key vectors is most noticeable. the same
+// fixture is verified as batches of increasing size: with the vectors
+// pre-allocated, the time per message should stay close to that of the
+// smallest batch (the baseline) rather than grow with the size of the batch
+test("verifySignatures (large batch)", (t) => {
+  db.onReady(() => {
+    query(
+      fromDB(db),
+      toCallback((err, kvtMsgs) => {
+        if (err) t.fail(err);
+        let baseline;
+        [1, 10, LARGE_BATCH_REPEATS].forEach((repeats) => {
+          // repeat the fixture messages to build a large batch
+          const msgs = [];
+          for (var j = 0; j < repeats; j++) {
+            kvtMsgs.forEach((msg) => msgs.push(msg.value));
+          }
+          var totalDuration = 0;
+          for (var i = 0; i < ITERATIONS; i++) {
+            const start = process.hrtime.bigint();
+            validate.verifySignatures(hmacKey, msgs, () => {});
+            totalDuration += Number(process.hrtime.bigint() - start) / 1e6;
+          }
+          avgDuration = totalDuration / ITERATIONS;
+          const perMessage = (avgDuration * 1000) / msgs.length;
+          if (baseline === undefined) baseline = perMessage;
+          t.pass(
+            `verified ${msgs.length} message signatures in ${avgDuration} ms ` +
+              `on average (${perMessage.toFixed(2)} µs per message, ` +
+              `${(perMessage / baseline).toFixed(2)}x the baseline)`
+          );
+        });
+        t.end();
+      })
+    );
+  });
+});
+
 // verification and validation for a single message
 test("validateSingle", (t) => {
   t.plan(ITERATIONS);
@@ -134,6 +178,34 @@ test("validateSingle", (t) => {
   });
 });
 
+// signature verification for a single message buffer with a registered hmac key
+test("verifySignatureBuffer", (t) => {
+  t.plan(ITERATIONS);
+  db.onReady(() => {
+    query(
+      fromDB(db),
+      toCallback((err, kvtMsgs) => {
+        if (err) t.fail(err);
+        const handle = validate.registerHmacKey(hmacKey);
+        const buf = Buffer.from(JSON.stringify(kvtMsgs[0].value, null, 2));
+        var i;
+        var totalDuration = 0;
+        for (i = 0; i < ITERATIONS; i++) {
+          const start = Date.now();
+          validate.verifySignatureBuffer(handle, buf, () => {
+            const duration = Date.now() - start;
+            totalDuration += duration;
+            t.pass(`verified 1 message signature in ${duration} ms`);
+          });
+        }
+        avgDuration = totalDuration / ITERATIONS;
+        console.log(`average duration: ${avgDuration} ms`);
+        t.end();
+      })
+    );
+  });
+});
+
 // batch verification and validation for an array of messages
 test("validateBatch", (t) => {
   t.plan(ITERATIONS);
@@ -163,6 +235,37 @@ test("validateBatch", (t) => {
   });
 });
 
+// batch verification and validation for many small batches, as during live
+// replication, with and without reuse of the message buffers across batches
+test("validateBatch (small batches, buffer reuse)", (t) => {
+  db.onReady(() => {
+    query(
+      fromDB(db),
+      toCallback((err, kvtMsgs) => {
+        if (err) t.fail(err);
+        const msgs = kvtMsgs.slice(0, SMALL_BATCH_SIZE).map((msg) => msg.value);
+        [0, 4096].forEach((poolSize) => {
+          validate.setBufferPoolSize(poolSize);
+          var totalDuration = 0;
+          for (var i = 0; i < ITERATIONS; i++) {
+            const start = Date.now();
+            for (var j = 0; j < SMALL_BATCHES; j++) {
+              validate.validateBatch(hmacKey, msgs, null, () => {});
+            }
+            totalDuration += Date.now() - start;
+          }
+          avgDuration = totalDuration / ITERATIONS;
+          t.pass(
+            `validated ${SMALL_BATCHES} batches of ${msgs.length} messages ` +
+              `(buffer pool size ${poolSize}) in ${avgDuration} ms on average`
+          );
+        });
+        t.end();
+      })
+    );
+  });
+});
+
 // batch verification and validation for an array of out-of-order messages
 test("validateOOOBatch", (t) => {
   t.plan(ITERATIONS);
@@ -4,6 +4,7 @@
 
 const validate = require("../");
 const test = require("tape");
+const ssbKeys = require("ssb-keys");
 const fs = require("fs");
 const path = require("path");
 const Log = require("async-append-only-log");
@@ -36,6 +37,30 @@ const SEED = "sloop";
 const MESSAGES = 5;
 const AUTHORS = 1;
 
+// create a valid feed for `keys` with one message (value) per entry in `timestamps`.
+// `content` optionally maps the index of each message to its content
+const createFeed = (keys, timestamps, content) => {
+  const msgs = [];
+  timestamps.forEach((timestamp, i) => {
+    const previous = i > 0 ? msgs[i - 1] : null;
+    msgs.push(
+      ssbKeys.signObj(keys, null, {
+        previous: previous
+          ? "%" + ssbKeys.hash(JSON.stringify(previous, null, 2))
+          : null,
+        author: keys.id,
+        sequence: i + 1,
+        timestamp,
+        hash: "sha256",
+        content: content
+          ? content(i)
+          : { type: "test", text: `message ${i + 1}` },
+      })
+    );
+  });
+  return msgs;
+};
+
 const validMsg = {
   previous: "%IIjwbJbV3WBE/SBLnXEv5XM3Pr+PnMkrAJ8F+7TsUVQ=.sha256",
   author: "@U5GvOKP/YUza9k53DSXxT0mk3PIrnyAmessvNfZl5E0=.ed25519",
@@ -407,3 +432,2163 @@ test("batch validation of out-of-order messages", (t) => {
     );
   });
 });
+
+test("async batch validation of full feed in chunks", (t) => {
+  db.onReady(() => {
+    query(
+      fromDB(db),
+      toCallback((err, kvtMsgs) => {
+        if (err) t.fail(err);
+        const msgs = kvtMsgs.map((msg) => msg.value);
+        validate.validateBatch(hmacKey1, msgs, null, (err, expectedKeys) => {
+          // use a small chunk size to exercise chaining between chunks
+          validate
+            .validateBatchAsync(hmacKey1, msgs, null, { chunkSize: 2 })
+            .then((keys) => {
+              t.deepEqual(keys, expectedKeys, "success: keys match");
+              t.end();
+            })
+            .catch((err) => {
+              t.fail(err);
+              t.end();
+            });
+        });
+      })
+    );
+  });
+});
+
+test("async batch validation with an aborted signal", (t) => {
+  db.onReady(() => {
+    query(
+      fromDB(db),
+      toCallback((err, kvtMsgs) => {
+        if (err) t.fail(err);
+        const msgs = kvtMsgs.map((msg) => msg.value);
+        const controller = new AbortController();
+        controller.abort();
+        validate
+          .validateBatchAsync(hmacKey1, msgs, null, {
+            signal: controller.signal,
+          })
+          .then(() => {
+            t.fail("validation should have been cancelled");
+            t.end();
+          })
+          .catch((err) => {
+            t.equal(err.message, "cancelled", "rejected as cancelled");
+            t.deepEqual(err.keys, [], "no keys were validated");
+            t.end();
+          });
+      })
+    );
+  });
+});
+
+test("batch validation with timestamp checks", (t) => {
+  db.onReady(() => {
+    query(
+      fromDB(db),
+      toCallback((err, kvtMsgs) => {
+        if (err) t.fail(err);
+        const msgs = kvtMsgs.map((msg) => msg.value);
+        validate.validateBatch(
+          hmacKey1,
+          msgs,
+          null,
+          { checkTimestamps: true },
+          (err, res, info) => {
+            t.equal(err, null, "success: err is null");
+            t.deepEqual(info.timestampRegressions, [], "no regressions");
+            t.end();
+          }
+        );
+      })
+    );
+  });
+});
+
+test("batch validation reports timestamp regressions", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 1500, 3000, 2999]);
+  validate.validateBatch(
+    hmacKey1,
+    msgs,
+    null,
+    { checkTimestamps: true },
+    (err, res, info) => {
+      t.equal(err, null, "success: regressions do not fail validation");
+      t.equal(res.length, 5, "keys are returned for all messages");
+      t.deepEqual(info.timestampRegressions, [2, 4], "regressions reported");
+      t.end();
+    }
+  );
+});
+
+test("batch validation with uri key format", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  validate.validateBatch(hmacKey1, msgs, null, (err, legacyKeys) => {
+    validate.validateBatch(
+      hmacKey1,
+      msgs,
+      null,
+      { keyFormat: "uri" },
+      (err, uriKeys) => {
+        t.equal(err, null, "success: err is null");
+        const expected = legacyKeys.map(
+          (key) =>
+            "ssb:message/classic/" +
+            key.slice(1, -7).replace(/\+/g, "-").replace(/\//g, "_")
+        );
+        t.deepEqual(uriKeys, expected, "keys are ssb uris");
+        t.end();
+      }
+    );
+  });
+});
+
+test("batch validation with unknown key format", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000]);
+  validate.validateOOOBatch(hmacKey1, msgs, { keyFormat: "bfe" }, (err) => {
+    t.match(err.message, /key format invalid/, "unknown key format errors");
+    t.end();
+  });
+});
+
+test("validation of a message with off-chain content", (t) => {
+  const keys = ssbKeys.generate();
+  const content = "off-chain content";
+  const contentHash = "&" + ssbKeys.hash(content);
+  const [msg] = createFeed(keys, [1000], () => ({ type: "test", contentHash }));
+  validate.validateWithContent(hmacKey1, msg, content, null, (err, res) => {
+    t.equal(err, null, "success: err is null");
+    t.equal(res, "%" + ssbKeys.hash(JSON.stringify(msg, null, 2)), "key");
+    validate.validateWithContent(hmacKey1, msg, "tampered", null, (err) => {
+      t.equal(err.message, "content hash mismatch", "tampered content");
+      t.end();
+    });
+  });
+});
+
+test("settled batch validation of a feed with invalid messages", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000]);
+  // tamper with the second message, which breaks the chain for the rest
+  msgs[1] = Object.assign({}, msgs[1], { timestamp: 2001 });
+  validate.validateBatchSettled(hmacKey1, msgs, null, (err, res) => {
+    t.equal(err, null, "success: err is null");
+    t.equal(res.keys.length, 1, "key of the first message is returned");
+    t.deepEqual(
+      res.errors.map((e) => [e.index, e.code]),
+      [
+        [1, "INVALID_SIGNATURE"],
+        [2, "INVALID_SEQUENCE"],
+        [3, "INVALID_SEQUENCE"],
+      ],
+      "each invalid message is reported"
+    );
+    t.end();
+  });
+});
+
+test("recommended batch size", (t) => {
+  const defaultSize = validate.recommendedBatchSize();
+  t.true(Number.isInteger(defaultSize) && defaultSize > 0, "positive integer");
+  const small = validate.recommendedBatchSize(200);
+  const large = validate.recommendedBatchSize(validMsg);
+  const huge = validate.recommendedBatchSize(8192);
+  t.true(small >= large && large >= huge, "smaller messages, larger batches");
+  t.end();
+});
+
+test("batch validation of a reordered feed with sortBySequence", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000]);
+  const reordered = [msgs[1], msgs[0], msgs[3], msgs[2]];
+  validate.validateBatch(hmacKey1, msgs, null, (err, expectedKeys) => {
+    validate.validateBatch(
+      hmacKey1,
+      reordered,
+      null,
+      { sortBySequence: true },
+      (err, res) => {
+        t.equal(err, null, "success: err is null");
+        t.deepEqual(res, expectedKeys, "keys are in sequence order");
+        // remove a message from the middle of the range
+        validate.validateBatch(
+          hmacKey1,
+          [msgs[3], msgs[0], msgs[1]],
+          null,
+          { sortBySequence: true },
+          (err) => {
+            t.match(err.message, /contiguous/, "gap is reported");
+            t.end();
+          }
+        );
+      }
+    );
+  });
+});
+
+test("configuration fingerprint", (t) => {
+  const fingerprint = validate.configFingerprint(hmacKey1);
+  t.match(fingerprint, /^[0-9a-f]{16}$/, "fingerprint is a short hex string");
+  t.equal(validate.configFingerprint(null, {}), fingerprint, "stable");
+  t.notEqual(
+    validate.configFingerprint(hmacKey2),
+    fingerprint,
+    "changes with the hmac key"
+  );
+  t.notEqual(
+    validate.configFingerprint(hmacKey1, { keyFormat: "uri" }),
+    fingerprint,
+    "changes with the options"
+  );
+  const known = (previous) => ({
+    knownMessages: { "%a.sha256": { sequence: 2, previous } },
+  });
+  t.equal(
+    validate.configFingerprint(hmacKey1, known("%b.sha256")),
+    fingerprint,
+    "known messages are part of the input, not the configuration"
+  );
+  const link = { previousKey: "%a.sha256", previousSequence: 2 };
+  t.equal(
+    validate.configFingerprint(hmacKey1, link),
+    fingerprint,
+    "a previous link is part of the input, not the configuration"
+  );
+  t.equal(
+    validate.configFingerprint(hmacKey1, { allowedTypes: ["post", "vote"] }),
+    validate.configFingerprint(hmacKey1, { allowedTypes: ["vote", "post"] }),
+    "independent of the order of the allowed types"
+  );
+  validate.setBufferPoolSize(16);
+  t.equal(
+    validate.configFingerprint(hmacKey1),
+    fingerprint,
+    "independent of the buffer pool size"
+  );
+  validate.setBufferPoolSize(4096);
+  validate.setMaxBatchSize(1000);
+  t.notEqual(
+    validate.configFingerprint(hmacKey1),
+    fingerprint,
+    "changes with the batch size limit"
+  );
+  validate.setMaxBatchSize(Infinity);
+  t.equal(validate.configFingerprint(hmacKey1), fingerprint, "limit restored");
+  t.end();
+});
+
+test("batch validation reports metafeed migration markers", (t) => {
+  const keys = ssbKeys.generate();
+  const metafeed =
+    "ssb:feed/bendybutt-v1/dmXTaR4hU6bKtaWUaA6e6tGnp37Td5GsMEXsJ03_kIk=";
+  const msgs = createFeed(keys, [1000, 2000, 3000], (i) =>
+    i === 1 ? { type: "metafeed/announce", metafeed } : { type: "test" }
+  );
+  validate.validateBatch(
+    hmacKey1,
+    msgs,
+    null,
+    { detectMigrations: true },
+    (err, res, info) => {
+      t.equal(err, null, "success: err is null");
+      t.deepEqual(
+        info.migrationMarkers,
+        [{ index: 1, metafeed }],
+        "announcement is reported"
+      );
+      t.end();
+    }
+  );
+});
+
+test("verification of a message buffer with a registered hmac key", (t) => {
+  const handle = validate.registerHmacKey(hmacKey2);
+  t.equal(validate.registerHmacKey(hmacKey2), handle, "handle is reused");
+  const buf = Buffer.from(JSON.stringify(hmacMsg, null, 2));
+  validate.verifySignatureBuffer(handle, buf, (err) => {
+    t.equal(err, null, "success: err is null");
+    const noKey = validate.registerHmacKey(null);
+    validate.verifySignatureBuffer(noKey, buf, (err) => {
+      t.match(err.message, /Signature was invalid/, "hmac key is required");
+      t.end();
+    });
+  });
+});
+
+test("batch validation reports the missing sequence range", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000, 5000, 6000]);
+  // omit the messages with sequence 3 and 4
+  const gapped = [msgs[0], msgs[1], msgs[4], msgs[5]];
+  validate.validateBatch(hmacKey1, gapped, null, (err) => {
+    t.match(err.message, /missing sequence range \[3, 4\]/, "range in message");
+    t.deepEqual(err.missingRange, [3, 4], "range on error");
+    validate.validateBatch(
+      hmacKey1,
+      gapped,
+      null,
+      { sortBySequence: true },
+      (err) => {
+        t.deepEqual(err.missingRange, [3, 4], "range when sorting");
+        t.end();
+      }
+    );
+  });
+});
+
+test("batch validation classifies messages by content type", (t) => {
+  const keys = ssbKeys.generate();
+  const types = ["post", "contact", "post", "vote"];
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000], (i) => ({
+    type: types[i],
+  }));
+  validate.validateBatch(
+    hmacKey1,
+    msgs,
+    null,
+    { classifyTypes: true },
+    (err, res, info) => {
+      t.equal(err, null, "success: err is null");
+      t.deepEqual(
+        info.contentTypes,
+        { post: [res[0], res[2]], contact: [res[1]], vote: [res[3]] },
+        "keys are grouped by type"
+      );
+      t.end();
+    }
+  );
+});
+
+test("validation with a previous message per message", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000, 5000]);
+  validate.validateBatch(hmacKey1, msgs, null, (err, expectedKeys) => {
+    // arbitrary subset of the feed, in arbitrary order
+    const subset = [msgs[3], msgs[0], msgs[2]];
+    const previous = [msgs[2], null, msgs[1]];
+    validate.validateWithPrevious(hmacKey1, subset, previous, (err, res) => {
+      t.equal(err, null, "success: err is null");
+      t.deepEqual(
+        res,
+        [expectedKeys[3], expectedKeys[0], expectedKeys[2]],
+        "keys are returned in input order"
+      );
+      // the wrong previous message for the second entry
+      const wrong = [msgs[2], msgs[3], msgs[1]];
+      validate.validateWithPrevious(hmacKey1, subset, wrong, (err) => {
+        t.match(err.message, /at index 1/, "invalid message is identified");
+        t.end();
+      });
+    });
+  });
+});
+
+test("version info", (t) => {
+  const info = validate.versionInfo();
+  t.match(info.version, /^\d+\.\d+\.\d+/, "version");
+  t.equal(info.cryptoBackend, "u64", "crypto backend");
+  t.equal(info.simdEnabled, false, "simd enabled by the backend");
+  t.equal(typeof info.simdSupported, "boolean", "simd supported");
+  t.end();
+});
+
+test("batch validation returns a digest of the keys", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  validate.validateBatch(
+    hmacKey1,
+    msgs,
+    null,
+    { digest: true },
+    (err, res, info) => {
+      t.equal(err, null, "success: err is null");
+      const hashes = res.map((key) => Buffer.from(key.slice(1, -7), "base64"));
+      const expected = ssbKeys
+        .hash(Buffer.concat(hashes))
+        .replace(/\.sha256$/, "");
+      t.equal(info.digest, expected, "digest is sha256 over the hashes");
+      t.end();
+    }
+  );
+});
+
+test("validation with selected phases", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  // an invalid signature which should go unnoticed without the verify phase
+  const tampered = [msgs[0], Object.assign({}, msgs[1], { signature: "x" })];
+  validate.validate(
+    hmacKey1,
+    tampered,
+    null,
+    { verify: false, hash: false },
+    (err, res) => {
+      t.equal(err, null, "success: err is null");
+      t.equal(res, null, "no keys without the hash phase");
+      // a gap in the feed which should go unnoticed without the chain phase
+      validate.validate(
+        hmacKey1,
+        [msgs[0], msgs[2]],
+        null,
+        { chain: false },
+        (err, res) => {
+          t.equal(err, null, "success: err is null");
+          t.equal(res.length, 2, "keys are returned");
+          t.end();
+        }
+      );
+    }
+  );
+});
+
+test("verification rejects a signature over the signature field", (t) => {
+  const keys = ssbKeys.generate();
+  const value = {
+    previous: null,
+    author: keys.id,
+    sequence: 1,
+    timestamp: 1000,
+    hash: "sha256",
+    content: { type: "test" },
+  };
+  // a correct signer signs the value without the `signature` field
+  const msg = ssbKeys.signObj(keys, null, Object.assign({}, value));
+  // a buggy signer includes a (placeholder) `signature` field in the signed bytes
+  const buggyMsg = ssbKeys.signObj(
+    keys,
+    null,
+    Object.assign({}, value, { signature: "" })
+  );
+  t.notEqual(msg.signature, buggyMsg.signature, "signatures differ");
+  validate.verifySignatures(hmacKey1, [msg], (err) => {
+    t.equal(err, null, "success: signature over the value is valid");
+    validate.verifySignatures(hmacKey1, [buggyMsg], (err) => {
+      t.match(err.message, /Signature was invalid/, "buggy signature rejected");
+      validate.validateSingle(hmacKey1, buggyMsg, null, (err) => {
+        t.match(err.message, /Signature was invalid/, "rejected by validation");
+        t.end();
+      });
+    });
+  });
+});
+
+test("keys for an array of messages without validation", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  // messages are not validated, so out-of-order input is fine
+  const reversed = msgs.slice().reverse();
+  validate.getMessageKeys(reversed, (err, res) => {
+    t.equal(err, null, "success: err is null");
+    t.deepEqual(
+      res,
+      reversed.map((msg) => "%" + ssbKeys.hash(JSON.stringify(msg, null, 2))),
+      "keys are correct"
+    );
+    t.end();
+  });
+});
+
+test("validation reports an hmac mismatch", (t) => {
+  const keys = ssbKeys.generate();
+  const [msg] = createFeed(keys, [1000]);
+  validate.validateSingle(hmacKey2, msg, null, (err) => {
+    t.match(
+      err.message,
+      /hmac mismatch: message appears signed under keyless/,
+      "keyless message validated with an hmac key"
+    );
+    // keyed messages are only detected for registered hmac keys.
+    // `hmacMsg` is signed with `hmacKey2`
+    validate.registerHmacKey(hmacKey2);
+    validate.validateSingle(hmacKey1, hmacMsg, null, (err) => {
+      t.match(
+        err.message,
+        /hmac mismatch: message appears signed under keyed/,
+        "keyed message validated without an hmac key"
+      );
+      t.end();
+    });
+  });
+});
+
+test("verification hints at a confused network caps key", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000]);
+  const capsKey = "1KHLiKZvAvjbY1ziZEHMXawbCEIM6qwjCDm3VYRan/s=";
+  validate.verifySignatures(capsKey, msgs, (err) => {
+    t.match(err.message, /main network caps key/, "caps key is detected");
+    validate.verifySignatures(hmacKey2, msgs, (err) => {
+      t.match(err.message, /network caps key/, "hint for every failure");
+      t.end();
+    });
+  });
+});
+
+test("validateEach yields a result per message", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  msgs[2] = Object.assign({}, msgs[2], { timestamp: 3001 });
+  const results = [];
+  validate.validateEach(
+    null,
+    msgs,
+    null,
+    (err, key, index) => results.push({ err, key, index }),
+    (err) => {
+      t.error(err, "batch is processed");
+      t.deepEqual(
+        results.map((r) => r.index),
+        [0, 1, 2],
+        "results are yielded in order"
+      );
+      t.ok(results[0].key.startsWith("%"), "valid message yields a key");
+      t.ok(results[1].key, "valid message yields a key");
+      t.match(
+        results[2].err.message,
+        /found invalid message/,
+        "tampered message yields an error"
+      );
+      t.end();
+    }
+  );
+});
+
+test("batch validation enforces content depth and size limits", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000], (i) =>
+    i === 1
+      ? { type: "test", nested: { a: { b: ["deep"] } } }
+      : { type: "test", text: "x".repeat(100) }
+  );
+  validate.validateBatch(null, msgs, null, { maxDepth: 2 }, (err) => {
+    t.match(
+      err.message,
+      /index 1: content nesting depth of 4 exceeds the maximum of 2/,
+      "deeply nested content is rejected"
+    );
+    validate.validateBatch(null, msgs, null, { maxContentBytes: 64 }, (err) => {
+      t.match(
+        err.message,
+        /index 0: content size of \d+ bytes exceeds the maximum of 64 bytes/,
+        "large content is rejected"
+      );
+      const limits = { maxDepth: 4, maxContentBytes: 1024 };
+      validate.validateBatch(null, msgs, null, limits, (err, keys) => {
+        t.error(err, "content within the limits is accepted");
+        t.equal(keys.length, 2, "keys are returned");
+        t.end();
+      });
+    });
+  });
+});
+
+test("verifySingleMultiKey returns the index of the matching hmac key", (t) => {
+  const [msg] = createFeed(ssbKeys.generate(), [1000]);
+  validate.verifySingleMultiKey(hmacMsg, [null, hmacKey2], (err, index) => {
+    t.error(err, "keyed message is verified");
+    t.equal(index, 1, "second key matches");
+    validate.verifySingleMultiKey(msg, [hmacKey2, null], (err, index) => {
+      t.error(err, "keyless message is verified");
+      t.equal(index, 1, "keyless verification matches");
+      validate.verifySingleMultiKey(hmacMsg, [null], (err) => {
+        t.match(
+          err.message,
+          /not valid under any of the 1 hmac keys/,
+          "no matching key is an error"
+        );
+        t.end();
+      });
+    });
+  });
+});
+
+test("keys can be returned in both formats", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000]);
+  const opts = { keyFormat: "both" };
+  validate.validateBatch(null, msgs, null, opts, (err, res) => {
+    t.error(err, "batch is valid");
+    t.equal(res.length, 2, "a pair per message");
+    validate.getMessageKeys(msgs, { keyFormat: "uri" }, (err, uriKeys) => {
+      t.error(err);
+      validate.getMessageKeys(msgs, (err, legacyKeys) => {
+        t.error(err);
+        t.deepEqual(res[0], [legacyKeys[0], uriKeys[0]], "[legacy, uri] pair");
+        validate.getMessageKeys(msgs, { keyFormat: "both" }, (err, pairs) => {
+          t.error(err);
+          t.deepEqual(pairs, res, "getMessageKeys returns the same pairs");
+          t.end();
+        });
+      });
+    });
+  });
+});
+
+test("non-canonical base64 is reported or rejected", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000]);
+  // set non-zero trailing bits in the final base64 character of the signature
+  const sig = msgs[1].signature;
+  const i = sig.indexOf("==");
+  const last = sig[i - 1] === "B" ? "C" : "B";
+  msgs[1] = Object.assign({}, msgs[1], {
+    signature: sig.slice(0, i - 1) + last + sig.slice(i),
+  });
+  const opts = { checkBase64: true, verify: false, hash: false };
+  validate.validate(null, msgs, null, opts, (err, res, info) => {
+    t.error(err, "non-canonical base64 is a warning");
+    t.deepEqual(
+      info.nonCanonicalBase64,
+      [{ index: 1, field: "signature" }],
+      "signature is reported"
+    );
+    const strict = { strictBase64: true };
+    validate.validateBatch(null, msgs, null, strict, (err) => {
+      t.match(
+        err.message,
+        /index 1: non-canonical base64 in `signature` field/,
+        "strict mode rejects the message"
+      );
+      t.end();
+    });
+  });
+});
+
+test("validateOOOSingle checks the expected position of a message", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  validate.getMessageKeys(msgs, (err, msgKeys) => {
+    t.error(err);
+    validate.validateOOOSingle(null, msgs[1], msgKeys[0], 2, (err, key) => {
+      t.error(err, "message fills the gap");
+      t.equal(key, msgKeys[1], "key is returned");
+      validate.validateOOOSingle(null, msgs[2], msgKeys[0], 3, (err) => {
+        t.match(err.message, /expected previous/, "wrong previous");
+        validate.validateOOOSingle(null, msgs[0], null, 2, (err) => {
+          t.match(err.message, /expected sequence 2 but found 1/, "wrong seq");
+          validate.validateOOOSingle(null, msgs[0], null, 1, (err) => {
+            t.error(err, "first message of a feed");
+            t.end();
+          });
+        });
+      });
+    });
+  });
+});
+
+test("verifySignatures can count invalid signatures", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000]);
+  msgs[1] = Object.assign({}, msgs[1], { timestamp: 2001 });
+  msgs[3] = Object.assign({}, msgs[3], { timestamp: 4001 });
+  validate.verifySignatures(null, msgs, { countInvalid: true }, (err, res) => {
+    t.error(err, "counting does not fail on invalid signatures");
+    t.deepEqual(res, { total: 4, invalid: 2 }, "invalid signatures counted");
+    t.end();
+  });
+});
+
+test("messages with deprecated content types are reported", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000], (i) => ({
+    type: i === 1 ? "old-post" : "post",
+    text: `message ${i + 1}`,
+  }));
+  const opts = { deprecatedTypes: ["old-post", "older-post"] };
+  validate.validateBatch(null, msgs, null, opts, (err, res, info) => {
+    t.error(err, "deprecated types are not an error");
+    t.deepEqual(
+      info.deprecated,
+      [{ key: res[1], type: "old-post" }],
+      "deprecated message is reported"
+    );
+    t.end();
+  });
+});
+
+test("the genesis message can be pinned to a known key", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000]);
+  const forged = createFeed(ssbKeys.generate(), [1000, 2000]);
+  validate.getMessageKeys(msgs, { keyFormat: "uri" }, (err, msgKeys) => {
+    t.error(err);
+    const opts = { expectedGenesisKey: msgKeys[0] };
+    validate.validateBatch(null, msgs, null, opts, (err) => {
+      t.error(err, "genesis key matches");
+      validate.validateBatch(null, forged, null, opts, (err) => {
+        t.match(err.message, /genesis message key mismatch/, "forged origin");
+        // the pinned key only applies to batches starting at sequence 1
+        validate.validateBatch(null, [forged[1]], forged[0], opts, (err) => {
+          t.error(err, "batch without the genesis message");
+          t.end();
+        });
+      });
+    });
+  });
+});
+
+test("validateStructure checks the required fields", (t) => {
+  validate.validateStructure(validMsg, (err) => {
+    t.error(err, "valid structure");
+    const { sequence, ...noSequence } = validMsg;
+    validate.validateStructure(noSequence, (err) => {
+      t.match(err.message, /`sequence` is missing/, "missing field");
+      t.equal(err.field, "sequence", "offending field is reported");
+      const badAuthor = Object.assign({}, validMsg, { author: 42 });
+      validate.validateStructure(badAuthor, (err) => {
+        t.match(err.message, /`author` must be a string/, "wrong type");
+        t.equal(err.field, "author", "offending field is reported");
+        validate.validateStructure("[1, 2]", (err) => {
+          t.match(err.message, /must be an object/, "not an object");
+          t.end();
+        });
+      });
+    });
+  });
+});
+
+test("validateBatchSettled can return a mask of valid messages", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  msgs[1] = Object.assign({}, msgs[1], { timestamp: 2001 });
+  const opts = { mask: true };
+  validate.validateBatchSettled(null, msgs, null, opts, (err, res) => {
+    t.error(err);
+    t.ok(res.mask instanceof Uint8Array, "mask is a Uint8Array");
+    // the third message follows the (invalid) second message
+    t.deepEqual(Array.from(res.mask), [1, 0, 0], "invalid messages are masked");
+    t.end();
+  });
+});
+
+test("referenced feed ids are collected from message content", (t) => {
+  const keys = ssbKeys.generate();
+  const alice = ssbKeys.generate().id;
+  const bob = ssbKeys.generate().id;
+  const msgs = createFeed(keys, [1000, 2000, 3000], (i) =>
+    i === 0
+      ? { type: "contact", contact: alice, following: true }
+      : {
+          type: "post",
+          text: "hi",
+          mentions: [{ link: bob }, { link: alice }, { link: "@invalid" }],
+        }
+  );
+  const opts = { collectFeedIds: true };
+  validate.validateBatch(null, msgs, null, opts, (err, res, info) => {
+    t.error(err);
+    t.deepEqual(info.feedIds, [alice, bob].sort(), "deduplicated feed ids");
+    t.end();
+  });
+});
+
+test("validateBatch returns partial results after a timeout", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000]);
+  const realNow = Date.now;
+  // advance the clock by 10ms each time it is read
+  let now = realNow();
+  Date.now = () => (now += 10);
+  const opts = { timeoutMs: 25, chunkSize: 1 };
+  validate.validateBatch(null, msgs, null, opts, (err, res, info) => {
+    Date.now = realNow;
+    t.error(err, "a timeout is not an error");
+    t.deepEqual(info, { timeout: true, index: 2 }, "timeout and index");
+    t.equal(res.length, 2, "keys of the validated messages");
+    const longOpts = { timeoutMs: 60000 };
+    validate.validateBatch(null, msgs, null, longOpts, (err, res, info) => {
+      t.error(err);
+      t.equal(res.length, 4, "all messages validated within the limit");
+      t.equal(info, undefined, "no timeout");
+      t.end();
+    });
+  });
+});
+
+test("validateBatch with a timeout applies the options to each chunk", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000]);
+  const opts = { timeoutMs: 60000, chunkSize: 1, keyFormat: "uri" };
+  validate.validateBatch(null, msgs, null, opts, (err, res) => {
+    t.error(err);
+    const uris = res.every((key) => key.startsWith("ssb:"));
+    t.ok(uris, "keys in the chosen format");
+    const tampered = msgs.slice();
+    tampered[2] = Object.assign({}, msgs[2], { timestamp: 3001 });
+    validate.validateBatch(null, tampered, null, opts, (err) => {
+      t.equal(err.code, "INVALID_SIGNATURE", "rejected with the code");
+      t.equal(err.index, 2, "index within the whole batch");
+      const gapOpts = { timeoutMs: 60000, reportGaps: true };
+      validate.validateBatch(null, msgs, null, gapOpts, (err) => {
+        t.match(err.message, /reportGaps cannot be combined/, "rejected");
+        t.end();
+      });
+    });
+  });
+});
+
+test("a previous link to another author's message is rejected", (t) => {
+  const alice = ssbKeys.generate();
+  const mallory = ssbKeys.generate();
+  const [aliceMsg] = createFeed(alice, [1000]);
+  // a message by mallory which links to alice's message as its previous
+  const splicedMsg = ssbKeys.signObj(mallory, null, {
+    previous: "%" + ssbKeys.hash(JSON.stringify(aliceMsg, null, 2)),
+    author: mallory.id,
+    sequence: 2,
+    timestamp: 2000,
+    hash: "sha256",
+    content: { type: "test", text: "spliced" },
+  });
+  validate.validateSingle(null, splicedMsg, aliceMsg, (err) => {
+    t.match(err.message, /cross-feed previous link/, "single message");
+    validate.validateBatch(null, [splicedMsg], aliceMsg, (err) => {
+      t.match(err.message, /cross-feed previous link/, "batch");
+      t.end();
+    });
+  });
+});
+
+test("the signature algorithm of each message is reported", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000]);
+  const opts = { signatureAlgorithms: true };
+  validate.validateBatch(null, msgs, null, opts, (err, res, info) => {
+    t.error(err);
+    t.deepEqual(info.signatureAlgorithms, ["ed25519", "ed25519"], "ed25519");
+    t.end();
+  });
+});
+
+test("findInvalid returns the indices of invalid messages", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000]);
+  msgs[1] = Object.assign({}, msgs[1], { timestamp: 2001 });
+  validate.findInvalid(null, msgs, null, (err, indices) => {
+    t.error(err);
+    // the third message links to the original (untampered) second message
+    t.deepEqual(indices, [1, 2], "invalid indices");
+    validate.findInvalid(null, msgs.slice(3), msgs[2], (err, indices) => {
+      t.error(err);
+      t.deepEqual(indices, [], "valid batch with previous");
+      t.end();
+    });
+  });
+});
+
+test("a trusted prefix of the batch is skipped", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000]);
+  // tampering with the trusted prefix goes unnoticed
+  msgs[0] = Object.assign({}, msgs[0], { timestamp: 1001 });
+  validate.getMessageKeys(msgs, (err, msgKeys) => {
+    t.error(err);
+    const opts = { trustedPrefixLen: 2 };
+    validate.validateBatch(null, msgs, null, opts, (err, res) => {
+      t.error(err, "the rest of the batch is validated");
+      t.deepEqual(res, msgKeys.slice(2), "keys of the remaining messages");
+      msgs[3] = Object.assign({}, msgs[3], { timestamp: 4001 });
+      validate.validateBatch(null, msgs, null, opts, (err) => {
+        t.match(err.message, /Signature was invalid/, "tail is validated");
+        t.end();
+      });
+    });
+  });
+});
+
+test("timestamp delta statistics are reported", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 1500, 4500]);
+  const [first, ...rest] = msgs;
+  const opts = { timestampStats: true, checkTimestamps: true };
+  validate.validateBatch(null, rest, first, opts, (err, res, info) => {
+    t.error(err);
+    t.deepEqual(
+      info.timestampDeltas,
+      { min: -500, max: 3000, mean: 3500 / 3 },
+      "deltas include the previous message"
+    );
+    t.deepEqual(info.timestampRegressions, [1], "regression indices");
+    validate.validateBatch(null, [msgs[0]], null, opts, (err, res, info) => {
+      t.error(err);
+      t.equal(info.timestampDeltas, null, "no deltas for a single message");
+      t.end();
+    });
+  });
+});
+
+test("null and undefined messages are reported by index", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000]);
+  const withNull = [msgs[0], null, msgs[1]];
+  t.throws(
+    () => validate.validateBatch(null, withNull, null, () => {}),
+    /message at index 1 is not a string/,
+    "null message"
+  );
+  t.throws(
+    () => validate.verifySignatures(null, [msgs[0], undefined], () => {}),
+    /message at index 1 is not a string/,
+    "undefined message"
+  );
+  t.end();
+});
+
+test("the timestamp range of a batch is reported", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  const opts = { timestampRange: true };
+  validate.validateBatch(null, msgs, null, opts, (err, res, info) => {
+    t.error(err);
+    t.deepEqual(info.timestampRange, [1000, 3000], "first and last timestamp");
+    validate.validateBatch(null, [], null, opts, (err, res, info) => {
+      t.error(err);
+      t.equal(info.timestampRange, null, "no range for an empty batch");
+      t.end();
+    });
+  });
+});
+
+test("hmac keys can be loaded from the environment or a file", (t) => {
+  const registered = validate.registerHmacKey(hmacKey2);
+  process.env.SSB_VALIDATE_TEST_HMAC = hmacKey2;
+  const handle = validate.loadHmacKeyFromEnv("SSB_VALIDATE_TEST_HMAC");
+  delete process.env.SSB_VALIDATE_TEST_HMAC;
+  t.equal(handle, registered, "same handle as the registered key");
+  const keyPath = path.join(require("os").tmpdir(), "ssb-validate-test-hmac");
+  fs.writeFileSync(keyPath, hmacKey2 + "\n");
+  t.equal(validate.loadHmacKeyFromFile(keyPath), registered, "key from file");
+  fs.unlinkSync(keyPath);
+  const buf = Buffer.from(JSON.stringify(hmacMsg, null, 2));
+  validate.verifySignatureBuffer(handle, buf, (err) => {
+    t.error(err, "message verified with the loaded key");
+    t.throws(
+      () => validate.loadHmacKeyFromEnv("SSB_VALIDATE_TEST_HMAC"),
+      /environment variable `SSB_VALIDATE_TEST_HMAC` invalid/,
+      "unset variable"
+    );
+    process.env.SSB_VALIDATE_TEST_HMAC = " none\n";
+    t.throws(
+      () => validate.loadHmacKeyFromEnv("SSB_VALIDATE_TEST_HMAC"),
+      /`SSB_VALIDATE_TEST_HMAC` does not hold a key/,
+      "none is not accepted in place of a key"
+    );
+    process.env.SSB_VALIDATE_TEST_HMAC = "";
+    t.throws(
+      () => validate.loadHmacKeyFromEnv("SSB_VALIDATE_TEST_HMAC"),
+      /does not hold a key/,
+      "empty variable"
+    );
+    delete process.env.SSB_VALIDATE_TEST_HMAC;
+    validate.validateBatch(handle, [hmacMsg], null, (err, res) => {
+      t.error(err, "batch validated with the loaded key handle");
+      t.equal(res.length, 1);
+      validate.validateBatch(1e6, [hmacMsg], null, (err) => {
+        t.match(err.message, /hmac key handle invalid/, "unknown handle");
+        t.end();
+      });
+    });
+  });
+});
+
+test("the underlying chain error is reported for duplicated messages", (t) => {
+  const keys = ssbKeys.generate();
+  const [msg] = createFeed(keys, [1000]);
+  // each copy is valid on its own, but the pair is not a valid feed. each
+  // message is checked against its predecessor, so the second copy is found
+  validate.validateBatch(null, [msg, msg], null, (err) => {
+    t.match(
+      err.message,
+      /The sequence must increase by one: \{/,
+      "underlying error is reported with the offending message"
+    );
+    t.equal(err.index, 1, "second copy is at fault");
+    t.end();
+  });
+});
+
+test("a message claiming another author fails verification", (t) => {
+  const signer = ssbKeys.generate();
+  const claimed = ssbKeys.generate();
+  // signed by `signer` but claiming `claimed` as the author
+  const msg = ssbKeys.signObj(signer, null, {
+    previous: null,
+    author: claimed.id,
+    sequence: 1,
+    timestamp: 1000,
+    hash: "sha256",
+    content: { type: "test", text: "impostor" },
+  });
+  validate.verifySignatures(null, [msg], (err) => {
+    t.match(err.message, /Signature was invalid/, "verification");
+    validate.validateSingle(null, msg, null, (err) => {
+      t.match(err.message, /Signature was invalid/, "validation");
+      t.end();
+    });
+  });
+});
+
+test("keys can be returned as raw digests", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000]);
+  validate.validateBatch(null, msgs, null, { keyFormat: "raw" }, (err, res) => {
+    t.error(err);
+    validate.getMessageKeys(msgs, (err, legacyKeys) => {
+      t.error(err);
+      const digests = res.map((digest) => Buffer.from(digest));
+      t.equal(digests[0].length, 32, "32 byte digest");
+      t.deepEqual(
+        digests.map((digest) => "%" + digest.toString("base64") + ".sha256"),
+        legacyKeys,
+        "digests match the legacy keys"
+      );
+      validate.getMessageKeys(msgs, { keyFormat: "raw" }, (err, raw) => {
+        t.error(err);
+        t.deepEqual(raw.map((d) => Buffer.from(d)), digests, "getMessageKeys");
+        t.end();
+      });
+    });
+  });
+});
+
+test("messages with encrypted content are flagged", (t) => {
+  const keys = ssbKeys.generate();
+  const boxed = Buffer.alloc(64, 7).toString("base64") + ".box";
+  const msgs = createFeed(keys, [1000, 2000, 3000], (i) =>
+    i === 1 ? boxed : { type: "post", text: "plaintext" }
+  );
+  const opts = { detectEncrypted: true };
+  validate.validateBatch(null, msgs, null, opts, (err, res, info) => {
+    t.error(err);
+    t.deepEqual(info.encrypted, [false, true, false], "encrypted flags");
+    t.end();
+  });
+});
+
+test("content types outside allowedTypes are rejected or reported", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000], (i) =>
+    i === 2 ? { type: "vote" } : { type: "post", text: "hi" }
+  );
+  const opts = { allowedTypes: ["post"] };
+  validate.validateBatch(null, msgs, null, opts, (err) => {
+    t.match(err.message, /index 2: content type `vote` is not allowed/);
+    const warnOpts = { allowedTypes: ["post"], disallowedTypes: "warn" };
+    validate.validateBatch(null, msgs, null, warnOpts, (err, res, info) => {
+      t.error(err);
+      t.equal(res.length, 3, "all messages are valid");
+      t.deepEqual(info.disallowedTypes, [{ index: 2, type: "vote" }]);
+      t.end();
+    });
+  });
+});
+
+test("validateInPlace reports the broken link", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000]);
+  validate.validateInPlace(msgs[1], msgs[0], msgs[2], null, (err, key) => {
+    t.error(err);
+    t.equal(key, msgs[2].previous, "key of the message");
+    validate.validateInPlace(msgs[1], msgs[0], msgs[3], null, (err) => {
+      t.equal(err.link, "next", "successor does not follow the message");
+      validate.validateInPlace(msgs[2], msgs[0], null, null, (err) => {
+        t.equal(err.link, "previous", "predecessor is not the previous");
+        t.end();
+      });
+    });
+  });
+});
+
+test("batch results can be returned as a binary envelope", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  const opts = { envelope: true };
+  validate.validateBatch(null, msgs, null, (err, expected) => {
+    t.error(err);
+    validate.validateBatch(null, msgs, null, opts, (err, envelope) => {
+      t.error(err);
+      t.ok(envelope instanceof ArrayBuffer, "envelope is an ArrayBuffer");
+      const digests = validate.decodeEnvelope(envelope);
+      const decoded = digests.map((d) => `%${d.toString("base64")}.sha256`);
+      t.deepEqual(decoded, expected, "envelope holds the keys");
+      const invalid = [msgs[0], msgs[2]];
+      validate.validateOOOBatch(null, invalid, opts, (err, envelope) => {
+        t.error(err);
+        t.equal(validate.decodeEnvelope(envelope).length, 2);
+        validate.validateBatch(null, invalid, null, opts, (err, envelope) => {
+          t.error(err);
+          t.throws(() => validate.decodeEnvelope(envelope), /invalid message/);
+          t.end();
+        });
+      });
+    });
+  });
+});
+
+test("OOO validation failures are summarised by reason", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000]);
+  const badSignature = Object.assign({}, msgs[1], { timestamp: 2001 });
+  // a correctly signed message with an unsupported hash function
+  const unsigned = Object.assign({}, msgs[2], { hash: "sha512" });
+  delete unsigned.signature;
+  const badHash = ssbKeys.signObj(keys, null, unsigned);
+  const dump = [msgs[0], badSignature, badHash, msgs[3], badSignature];
+  validate.validateOOOBatchSummary(null, dump, (err, summary) => {
+    t.error(err);
+    t.equal(summary.valid, 2, "valid count");
+    t.equal(summary.invalid, 3, "invalid count");
+    const { reasons } = summary;
+    t.deepEqual(Object.keys(reasons), [
+      "INVALID_HASH_FUNCTION",
+      "INVALID_SIGNATURE",
+    ]);
+    t.equal(reasons.INVALID_SIGNATURE.count, 2);
+    t.equal(reasons.INVALID_SIGNATURE.examples.length, 2);
+    t.equal(reasons.INVALID_HASH_FUNCTION.count, 1);
+    t.end();
+  });
+});
+
+test("hardened validation rejects a fuzz corpus without crashing", (t) => {
+  const keys = ssbKeys.generate();
+  const [msg] = createFeed(keys, [1000]);
+  const json = JSON.stringify(msg, null, 2);
+  const nested = (n) => "[".repeat(n) + "]".repeat(n);
+  const corpus = [
+    "",
+    "null",
+    "[]",
+    "{",
+    '"\\ud800"',
+    json.slice(0, json.length / 2),
+    json.replace('"sequence": 1', '"sequence": 1e400'),
+    json.replace('"sequence": 1', '"sequence": -1'),
+    json.replace(/"author": "[^"]*"/, '"author": "@\\u0000.ed25519"'),
+    json.replace(/"signature": "[^"]*"/, '"signature": "ÿþ"'),
+    json.replace(/"content": \{[^}]*\}/, `"content": ${nested(100000)}`),
+  ];
+  // deterministic single-byte mutations of a valid message
+  for (let i = 0; i < json.length; i += 7) {
+    const code = (json.charCodeAt(i) * 31 + i) % 128;
+    const mutated = String.fromCharCode(code);
+    if (mutated === json[i]) continue;
+    corpus.push(json.slice(0, i) + mutated + json.slice(i + 1));
+  }
+  const opts = { hardened: true };
+  // messages are parsed up front to report gaps or sort by sequence, which must
+  // not happen before the depth limit is applied
+  const variants = [
+    opts,
+    { hardened: true, reportGaps: true },
+    { hardened: true, sortBySequence: true },
+  ];
+  variants.forEach((variant) => {
+    let rejected = 0;
+    corpus.forEach((input) => {
+      validate.validateBatch(null, [input], null, variant, (err) => {
+        if (err) rejected++;
+      });
+    });
+    const name = JSON.stringify(variant);
+    t.equal(rejected, corpus.length, `malformed input is rejected: ${name}`);
+  });
+  validate.validateBatch(null, [json], null, opts, (err, res) => {
+    t.error(err, "raw JSON strings are accepted");
+    t.equal(res.length, 1);
+    t.end();
+  });
+});
+
+test("messages by a revoked author are rejected", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  const opts = { revokedKeys: { [keys.id]: 3 } };
+  validate.validateBatch(null, msgs, null, opts, (err) => {
+    t.match(err.message, /index 2: author revoked at sequence 3/);
+    const before = msgs.slice(0, 2);
+    validate.validateOOOBatch(null, before, opts, (err, res) => {
+      t.error(err, "messages before the revocation are valid");
+      t.equal(res.length, 2);
+      validate.validateMultiAuthorBatch(null, msgs, opts, (err) => {
+        t.match(err.message, /author revoked/);
+        t.equal(err.author, keys.id, "author is reported");
+        t.end();
+      });
+    });
+  });
+});
+
+test("batches are classified as contiguous feed segments", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000]);
+  validate.classifyBatch(null, msgs.slice(1), (err, res) => {
+    t.error(err);
+    t.deepEqual(res, {
+      isContiguousFeed: true,
+      author: keys.id,
+      startSeq: 2,
+      endSeq: 4,
+    });
+    const gap = [msgs[0], msgs[2]];
+    validate.classifyBatch(null, gap, (err, res) => {
+      t.error(err);
+      t.equal(res.isContiguousFeed, false, "a gap is not contiguous");
+      const other = createFeed(ssbKeys.generate(), [1000]);
+      validate.classifyBatch(null, [msgs[0], other[0]], (err, res) => {
+        t.error(err);
+        t.equal(res.isContiguousFeed, false, "two authors");
+        t.equal(res.author, null);
+        const forged = Object.assign({}, msgs[1], { timestamp: 2001 });
+        const batch = [msgs[0], forged];
+        validate.classifyBatch(null, batch, { verify: true }, (err, res) => {
+          t.error(err);
+          t.equal(res.isContiguousFeed, false, "invalid signature");
+          // sequence numbers at the end of the range do not overflow
+          const withSequence = (msg, sequence) =>
+            Buffer.from(
+              JSON.stringify(msg, null, 2).replace(
+                /"sequence": \d+/,
+                `"sequence": ${sequence}`
+              )
+            );
+          const max = "18446744073709551615";
+          const wrapped = [
+            withSequence(msgs[0], max),
+            withSequence(msgs[1], "0"),
+          ];
+          validate.classifyBatch(null, wrapped, (err, res) => {
+            t.error(err);
+            t.equal(res.isContiguousFeed, false, "no successor of the maximum");
+            const opts = { reportGaps: true };
+            validate.validateBatch(null, wrapped, null, opts, (err) => {
+              t.ok(err, "rejected rather than reported as a gap");
+              t.end();
+            });
+          });
+        });
+      });
+    });
+  });
+});
+
+test("the native module passes its self-test", (t) => {
+  const result = validate.selfTest();
+  t.equal(result.passed, true, "self-test passed");
+  t.equal(result.reason, null);
+  t.deepEqual(result.versionInfo, validate.versionInfo());
+  t.equal(result.versionInfo.cryptoBackend, "u64", "crypto backend");
+  t.equal(result.versionInfo.simdEnabled, false, "simd enabled");
+  t.end();
+});
+
+test("blob ids referenced by each message are collected", (t) => {
+  const keys = ssbKeys.generate();
+  const blob = "&" + Buffer.alloc(32, 1).toString("base64") + ".sha256";
+  const other = "&" + Buffer.alloc(32, 2).toString("base64") + ".sha256";
+  const msgs = createFeed(keys, [1000, 2000], (i) =>
+    i === 0
+      ? { type: "post", text: "no blobs", mentions: ["&not-a-blob"] }
+      : { type: "post", mentions: [{ link: other }, { link: blob }], blob }
+  );
+  const opts = { collectBlobIds: true };
+  validate.validateBatch(null, msgs, null, opts, (err, res, info) => {
+    t.error(err);
+    t.deepEqual(info.blobIds, [[], [blob, other]], "blob ids per message");
+    t.end();
+  });
+});
+
+test("a Validator chains batches onto the cached tip", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000, 5000]);
+  const validator = new validate.Validator(null);
+  validator.validateBatch(msgs.slice(0, 2), (err) => {
+    t.error(err);
+    t.deepEqual(validator.tip(keys.id), msgs[1], "tip is cached");
+    validator.validateBatch(msgs.slice(2, 4), (err, res) => {
+      t.error(err, "chained onto the cached tip");
+      t.equal(res.length, 2);
+      validator.validateBatch([msgs[4]], msgs[2], (err) => {
+        // msgs[4] does not follow msgs[2]
+        t.match(err.message, /sequence/, "explicit previous is used");
+        validator.reset();
+        t.equal(validator.tip(keys.id), null, "cache is reset");
+        t.end();
+      });
+    });
+  });
+});
+
+test("a Validator caches the highest validated sequence as the tip", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000, 5000, 6000]);
+  const validator = new validate.Validator(null, { sortBySequence: true });
+  validator.validateBatch([msgs[1], msgs[0]], (err) => {
+    t.error(err);
+    t.deepEqual(validator.tip(keys.id), msgs[1], "tip of the sorted batch");
+    const gapped = new validate.Validator(null, { reportGaps: true });
+    gapped.pushBatch([msgs[0], msgs[1], msgs[3]], (err, res, info) => {
+      t.error(err);
+      t.deepEqual(info.gaps, [2]);
+      t.deepEqual(gapped.tip(keys.id), msgs[1], "tip precedes the gap");
+      gapped.validateBatch([msgs[2]], (err) => {
+        t.error(err, "chained onto the last validated message");
+        gapped.close();
+        t.end();
+      });
+    });
+  });
+});
+
+test("messages can be given as buffers", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  const json = msgs.map((msg) => JSON.stringify(msg, null, 2));
+  const buffers = [
+    Buffer.from(json[0]),
+    new Uint8Array(Buffer.from(json[1])),
+    Buffer.from(json[2]),
+  ];
+  validate.validateBatch(null, msgs, null, (err, expected) => {
+    t.error(err);
+    validate.validateBatch(null, buffers, null, (err, res) => {
+      t.error(err);
+      t.deepEqual(res, expected, "same keys as for objects");
+      const invalid = [Buffer.from([0xff, 0xfe])];
+      t.throws(
+        () => validate.validateBatch(null, invalid, null, () => {}),
+        /message at index 0 is not valid UTF-8/
+      );
+      t.end();
+    });
+  });
+});
+
+test("batch errors carry the index of the offending message", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000]);
+  const forged = msgs.slice();
+  forged[2] = Object.assign({}, msgs[2], { timestamp: 3001 });
+  validate.validateBatch(null, forged, null, (err) => {
+    t.match(err.message, /Signature was invalid/);
+    t.equal(err.index, 2, "index of the invalid signature");
+    const unlinked = [msgs[0], msgs[1], msgs[3]];
+    validate.validateBatch(null, unlinked, null, (err) => {
+      t.equal(err.index, 2, "index of the broken link");
+      t.end();
+    });
+  });
+});
+
+test("verbose batch validation reports every invalid message", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000, 5000]);
+  const corrupt = msgs.slice();
+  corrupt[1] = Object.assign({}, msgs[1], { timestamp: 2001 });
+  validate.validateBatchVerbose(null, corrupt, null, (err, res) => {
+    t.error(err);
+    // the successor of the corrupt message no longer links to it
+    t.deepEqual(
+      res.errors.map((e) => [e.index, e.code]),
+      [
+        [1, "INVALID_SIGNATURE"],
+        [2, "FORKED_FEED"],
+      ],
+      "only the corrupt message and the broken link are reported"
+    );
+    t.equal(res.keys.length, 3, "keys of the valid messages");
+    validate.validateBatchSettled(null, corrupt, null, (err, res) => {
+      t.error(err);
+      t.equal(res.errors.length, 4, "settled validation rejects the rest");
+      t.end();
+    });
+  });
+});
+
+test("errors carry a stable code and the offending message", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  const tampered = Object.assign({}, msgs[1], { timestamp: 2001 });
+  validate.validateSingle(null, tampered, msgs[0], (err) => {
+    t.equal(err.code, "INVALID_SIGNATURE", "single message code");
+    t.equal(err.offendingMessage, tampered, "single offending message");
+    const forked = [msgs[0], msgs[2]];
+    validate.validateBatch(null, forked, null, (err) => {
+      t.equal(err.code, "INVALID_SEQUENCE", "batch code");
+      t.equal(err.offendingMessage, msgs[2], "batch offending message");
+      validate.validateOOOBatch(null, [msgs[0], tampered], (err) => {
+        t.equal(err.code, "INVALID_SIGNATURE", "out-of-order code");
+        t.equal(err.index, 1, "out-of-order index");
+        t.equal(err.offendingMessage, tampered, "out-of-order message");
+        validate.validateBatch("bad key", msgs, null, (err) => {
+          t.equal(err.code, "INVALID_HMAC_KEY", "hmac key code");
+          t.equal(err.offendingMessage, undefined, "no offending message");
+          t.end();
+        });
+      });
+    });
+  });
+});
+
+test("async batch validation runs in the background", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000, 5000]);
+  validate.validateBatch(null, msgs, null, (err, expectedKeys) => {
+    t.error(err);
+    validate
+      .validateBatchAsync(null, msgs, null, { chunkSize: 2 })
+      .then((res) => {
+        t.deepEqual(res, expectedKeys, "keys match validateBatch");
+        const tampered = msgs.slice();
+        tampered[3] = Object.assign({}, msgs[3], { timestamp: 4001 });
+        return validate.validateBatchAsync(null, tampered, null, {
+          chunkSize: 2,
+        });
+      })
+      .then(
+        () => t.fail("tampered batch should be rejected"),
+        (err) => {
+          t.equal(err.code, "INVALID_SIGNATURE", "rejected with the code");
+          t.equal(err.index, 3, "index within the whole batch");
+          t.deepEqual(err.keys, expectedKeys.slice(0, 2), "keys so far");
+          // the rest of the feed, chained onto a link to its first message
+          const previous = { key: expectedKeys[0], sequence: 1 };
+          return validate.validateBatchAsync(null, msgs.slice(1), previous, {
+            chunkSize: 2,
+            keyFormat: "uri",
+          });
+        }
+      )
+      .then((res) => {
+        t.equal(res.length, 4, "chained onto the previous link");
+        t.ok(res[0].startsWith("ssb:"), "keys in the chosen format");
+        return validate.validateBatchAsync(null, msgs, null, {
+          sortBySequence: true,
+        });
+      })
+      .then(
+        () => t.fail("unchunkable options should be rejected"),
+        (err) => t.match(err.message, /sortBySequence cannot be combined/)
+      )
+      .then(() => t.end());
+  });
+});
+
+test("compute the key of a single message", (t) => {
+  const [msg] = createFeed(ssbKeys.generate(), [1000]);
+  validate.validateSingle(null, msg, null, (err, key) => {
+    t.error(err);
+    t.equal(validate.computeMessageKey(msg), key, "key of an object");
+    const json = JSON.stringify(msg, null, 2);
+    t.equal(validate.computeMessageKey(json), key, "key of a JSON string");
+    t.throws(
+      () => validate.computeMessageKey("hello"),
+      /message value invalid/,
+      "input which is not a message value is rejected"
+    );
+    t.end();
+  });
+});
+
+test("compute the keys of an array of messages", (t) => {
+  const msgs = createFeed(ssbKeys.generate(), [1000, 2000, 3000]);
+  validate.validateBatch(null, msgs, null, (err, keys) => {
+    t.error(err);
+    t.deepEqual(validate.computeMessageKeys(msgs), keys, "keys in order");
+    const buffers = msgs.map((msg) => Buffer.from(JSON.stringify(msg)));
+    t.equal(validate.computeMessageKeys(buffers).length, 3, "buffers");
+    t.throws(
+      () => validate.computeMessageKeys([msgs[0], null]),
+      /message at index 1/,
+      "the index of a non-message is reported"
+    );
+    t.throws(
+      () => validate.computeMessageKeys([msgs[0], "hello"]),
+      /message at index 1 invalid/,
+      "a string which is not a message value is rejected"
+    );
+    t.end();
+  });
+});
+
+test("single message validation returning a promise", (t) => {
+  const msgs = createFeed(ssbKeys.generate(), [1000, 2000]);
+  validate.validateSingle(null, msgs[1], msgs[0], (err, expectedKey) => {
+    t.error(err);
+    validate
+      .validateSingleAsync(null, msgs[1], msgs[0])
+      .then((key) => {
+        t.equal(key, expectedKey, "resolved with the key");
+        return validate.validateSingleAsync(null, msgs[1]);
+      })
+      .then(
+        () => t.fail("a message without its previous should be rejected"),
+        (err) => {
+          t.equal(err.code, "INVALID_FIRST_SEQUENCE", "rejected with the code");
+          t.equal(err.offendingMessage, msgs[1], "offending message");
+        }
+      )
+      .then(() => t.end());
+  });
+});
+
+test("single message validation with the message metadata", (t) => {
+  const msgs = createFeed(ssbKeys.generate(), [1000, 2000]);
+  validate.validateSingle(null, msgs[1], msgs[0], (err, key) => {
+    t.error(err);
+    validate.validateSingleWithMeta(null, msgs[1], msgs[0], (err, meta) => {
+      t.error(err);
+      t.deepEqual(
+        meta,
+        {
+          key,
+          author: msgs[1].author,
+          sequence: 2,
+          previous: msgs[1].previous,
+        },
+        "key, author, sequence and previous"
+      );
+      validate.validateSingleWithMeta(null, msgs[0], null, (err, meta) => {
+        t.error(err);
+        t.equal(meta.previous, null, "no previous for the first message");
+        t.end();
+      });
+    });
+  });
+});
+
+test("verify each signature of a batch independently", (t) => {
+  const msgs = createFeed(ssbKeys.generate(), [1000, 2000, 3000]);
+  msgs[1] = Object.assign({}, msgs[1], { timestamp: 2001 });
+  validate.verifySignaturesPartial(null, msgs, (err, results) => {
+    t.error(err);
+    t.deepEqual(
+      results.map((result) => result.valid),
+      [true, false, true],
+      "one result per message, in order"
+    );
+    t.equal(results[1].index, 1, "index of the invalid message");
+    t.equal(results[1].code, "INVALID_SIGNATURE", "code of the failure");
+    t.ok(results[1].error, "reason of the failure");
+    t.equal(results[0].code, undefined, "no code for a valid signature");
+    t.end();
+  });
+});
+
+test("single message validation returning a uri key", (t) => {
+  const msgs = createFeed(ssbKeys.generate(), [1000, 2000]);
+  const opts = { keyFormat: "uri" };
+  validate.validateBatch(null, msgs, null, opts, (err, keys) => {
+    t.error(err);
+    validate.validateSingle(null, msgs[1], msgs[0], opts, (err, key) => {
+      t.error(err);
+      t.equal(key, keys[1], "same uri key as validateBatch");
+      t.ok(key.startsWith("ssb:message/classic/"), "uri format");
+      validate
+        .validateSingleAsync(null, msgs[0], null, opts)
+        .then((key) => t.equal(key, keys[0], "async uri key"))
+        .then(() => t.end());
+    });
+  });
+});
+
+test("extract the author of a message", (t) => {
+  const keys = ssbKeys.generate();
+  const [msg] = createFeed(keys, [1000]);
+  t.equal(validate.extractAuthor(msg), keys.id, "author of an object");
+  t.equal(validate.extractAuthor(JSON.stringify(msg)), keys.id, "of JSON");
+  t.throws(
+    () => validate.extractAuthor({ sequence: 1 }),
+    /no author field/,
+    "missing author"
+  );
+  t.throws(
+    () => validate.extractAuthor(Object.assign({}, msg, { author: "@x" })),
+    /not a valid feed id/,
+    "malformed author"
+  );
+  t.end();
+});
+
+test("batch validation rejects messages by an unexpected author", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000]);
+  const opts = { expectedAuthor: keys.id };
+  validate.validateBatch(null, msgs, null, opts, (err, res) => {
+    t.error(err);
+    t.equal(res.length, 2, "messages by the expected author");
+    const spliced = createFeed(ssbKeys.generate(), [1000, 2000]);
+    validate.validateBatch(null, spliced, null, opts, (err) => {
+      t.match(err.message, /index 0: author @.* does not match/, "error");
+      t.equal(err.index, 0, "index of the mismatching message");
+      t.equal(err.code, "AUTHOR_MISMATCH", "code");
+      t.end();
+    });
+  });
+});
+
+test("an hmac key of the wrong length is reported with its length", (t) => {
+  const msgs = createFeed(ssbKeys.generate(), [1000]);
+  const shortKey = Buffer.alloc(16).toString("base64");
+  validate.validateBatch(shortKey, msgs, null, (err) => {
+    t.match(
+      err.message,
+      /expected 32 bytes, got 16 \(decoded from base64\)/,
+      "length of a base64 key"
+    );
+    validate.validateBatch(Buffer.alloc(16), msgs, null, (err) => {
+      t.match(err.message, /expected 32 bytes, got 16$/, "length of a buffer");
+      t.end();
+    });
+  });
+});
+
+test("an hmac key may be given as a hex string", (t) => {
+  const hexKey = Buffer.from(hmacKey2, "base64").toString("hex");
+  validate.validateSingle(hexKey, hmacMsg, null, (err, hexRes) => {
+    t.error(err, "hex key accepted");
+    validate.validateSingle(hmacKey2, hmacMsg, null, (err, res) => {
+      t.error(err);
+      t.equal(hexRes, res, "same key as with the base64 hmac key");
+      const notHex = hexKey.slice(0, 63) + "z";
+      validate.validateSingle(notHex, hmacMsg, null, (err) => {
+        t.match(err.message, /base64 or hex/, "invalid hex rejected");
+        t.end();
+      });
+    });
+  });
+});
+
+test("previous may be given as the key and sequence of a message", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000]);
+  const rest = msgs.slice(2);
+  validate.getMessageKeys(msgs, (err, msgKeys) => {
+    t.error(err);
+    const previous = { key: msgKeys[1], sequence: 2 };
+    validate.validateBatch(null, rest, previous, (err, res) => {
+      t.error(err, "the batch links to the given key and sequence");
+      t.deepEqual(res, msgKeys.slice(2), "keys of the batch");
+      const forked = { key: msgKeys[0], sequence: 2 };
+      validate.validateBatch(null, rest, forked, (err) => {
+        t.equal(err.code, "FORKED_FEED", "a different key is rejected");
+        t.equal(err.index, 0, "the first message is at fault");
+        const gap = { key: msgKeys[1], sequence: 1 };
+        validate.validateBatch(null, rest, gap, (err) => {
+          t.equal(err.code, "INVALID_SEQUENCE", "a gap is rejected");
+          t.deepEqual(err.missingRange, [2, 2], "missing sequence");
+          const keyOnly = { previousKey: msgKeys[1] };
+          validate.validateBatch(null, rest, null, keyOnly, (err) => {
+            t.equal(err.code, "INVALID_OPTIONS", "key without sequence");
+            const both = { previousKey: msgKeys[1], previousSequence: 2 };
+            validate.validateBatch(null, rest, msgs[1], both, (err) => {
+              t.equal(err.code, "INVALID_OPTIONS", "link and message");
+              t.end();
+            });
+          });
+        });
+      });
+    });
+  });
+});
+
+test("the key of the previous message is reported", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  const [first, ...rest] = msgs;
+  const opts = { returnPreviousKey: true };
+  validate.getMessageKeys(msgs, (err, msgKeys) => {
+    t.error(err);
+    validate.validateBatch(null, rest, first, opts, (err, res, info) => {
+      t.error(err);
+      t.equal(info.previousKey, msgKeys[0], "key of previous");
+      validate.validateBatch(null, msgs, null, opts, (err, res, info) => {
+        t.error(err);
+        t.equal(info.previousKey, null, "no previous message");
+        t.end();
+      });
+    });
+  });
+});
+
+test("batches larger than the maximum batch size are rejected", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  t.throws(() => validate.setMaxBatchSize(0), /must be a positive integer/);
+  validate.setMaxBatchSize(2);
+  validate.validateBatch(null, msgs, null, (err) => {
+    t.equal(err.message, "batch too large: 3 > 2", "validateBatch");
+    t.throws(
+      () => validate.verifySignatures(null, msgs, () => {}),
+      /batch too large: 3 > 2/,
+      "other batch functions"
+    );
+    validate.validateBatch(null, msgs.slice(0, 2), null, (err) => {
+      t.error(err, "batches within the limit are validated");
+      validate.setMaxBatchSize(Infinity);
+      validate.validateBatch(null, msgs, null, (err) => {
+        t.error(err, "the limit is removed");
+        t.end();
+      });
+    });
+  });
+});
+
+test("the signature of a single message is verified on its own", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000]);
+  validate.getMessageKeys(msgs, (err, msgKeys) => {
+    t.error(err);
+    // the second message is not valid without its previous message
+    validate.verifySingleSignature(null, msgs[1], (err, key) => {
+      t.error(err, "no hash-chain validation");
+      t.equal(key, msgKeys[1], "key of the message");
+      const tampered = Object.assign({}, msgs[1], { timestamp: 2001 });
+      validate.verifySingleSignature(null, tampered, (err) => {
+        t.equal(err.code, "INVALID_SIGNATURE", "signature is verified");
+        t.equal(err.offendingMessage, tampered, "offending message");
+        t.end();
+      });
+    });
+  });
+});
+
+test("signature verification may be skipped", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  // a tampered signature goes unnoticed, but the hash chain is still validated
+  const tampered = msgs.slice();
+  tampered[1] = Object.assign({}, msgs[1], {
+    signature: msgs[0].signature,
+  });
+  const opts = { skipVerify: true };
+  validate.validateBatch(null, msgs, null, (err, expected) => {
+    t.error(err);
+    validate.validateBatch(null, msgs, null, opts, (err, res) => {
+      t.error(err);
+      t.deepEqual(res, expected, "the same keys are returned");
+      validate.validateBatch(null, tampered, null, opts, (err) => {
+        t.equal(err.code, "FORKED_FEED", "the hash chain is validated");
+        t.equal(err.index, 2, "the successor of the tampered message");
+        t.end();
+      });
+    });
+  });
+});
+
+test("each author chain in a multi-author batch is validated", (t) => {
+  const alice = createFeed(ssbKeys.generate(), [1000, 2000, 3000]);
+  const bob = createFeed(ssbKeys.generate(), [1500, 2500]);
+  // interleaved and out of order
+  const msgs = [bob[1], alice[2], alice[0], bob[0], alice[1]];
+  const opts = { chainPerAuthor: true };
+  validate.validateMultiAuthorBatch(null, msgs, (err, expected) => {
+    t.error(err);
+    validate.validateMultiAuthorBatch(null, msgs, opts, (err, res) => {
+      t.error(err, "interleaved feeds are validated");
+      t.deepEqual(res, expected, "keys in input order");
+      // alice's second message is missing from the batch
+      const gap = [bob[1], alice[2], alice[0], bob[0]];
+      validate.validateMultiAuthorBatch(null, gap, opts, (err) => {
+        t.equal(err.code, "INVALID_SEQUENCE", "a gap is rejected");
+        t.equal(err.index, 1, "index of the offending message");
+        t.equal(err.author, alice[0].author, "author of the feed");
+        t.end();
+      });
+    });
+  });
+});
+
+test("the time spent in each phase is reported", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  const opts = { stats: true };
+  validate.validateBatch(null, msgs, null, opts, (err, res, info) => {
+    t.error(err);
+    t.equal(info.stats.count, 3, "number of messages");
+    t.ok(info.stats.verifyMs >= 0, "verification time");
+    t.ok(info.stats.validateMs >= 0, "validation time");
+    t.end();
+  });
+});
+
+test("out-of-order links to known messages are checked", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000, 5000]);
+  const batch = [msgs[3], msgs[1]];
+  validate.getMessageKeys(msgs, (err, msgKeys) => {
+    t.error(err);
+    const knownMessages = {
+      [msgKeys[0]]: { sequence: 1, previous: null },
+      [msgKeys[2]]: { sequence: 3, previous: msgKeys[1] },
+    };
+    const opts = { knownMessages };
+    validate.validateOOOBatch(null, batch, opts, (err, res, unlinked) => {
+      t.error(err);
+      t.deepEqual(res, [msgKeys[3], msgKeys[1]], "keys of the batch");
+      t.deepEqual(unlinked, [], "every message is linked");
+      const first = { [msgKeys[0]]: knownMessages[msgKeys[0]] };
+      const partial = { knownMessages: first };
+      validate.validateOOOBatch(null, batch, partial, (err, _, unlinked) => {
+        t.error(err);
+        t.deepEqual(unlinked, [0], "the previous of message 0 is unknown");
+        knownMessages[msgKeys[2]].sequence = 4;
+        validate.validateOOOBatch(null, batch, opts, (err) => {
+          t.equal(err.code, "INVALID_SEQUENCE", "a broken link is rejected");
+          t.equal(err.index, 0, "index of the offending message");
+          t.end();
+        });
+      });
+    });
+  });
+});
+
+test("errors carry a stable numeric code", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000]);
+  t.equal(validate.errorCodes.INVALID_SIGNATURE, 100, "signature errors");
+  t.equal(validate.errorCodes.FORKED_FEED, 211, "validation errors");
+  t.equal(validate.errorCodes.REVOKED_AUTHOR, 303, "additional checks");
+  msgs[1] = Object.assign({}, msgs[1], { timestamp: 2001 });
+  validate.validateBatch(null, msgs, null, (err) => {
+    t.equal(err.code, "INVALID_SIGNATURE");
+    t.equal(err.errorCode, 100, "number of the code");
+    t.end();
+  });
+});
+
+test("the link between two messages is validated", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000]);
+  validate.getMessageKeys(msgs, (err, msgKeys) => {
+    t.error(err);
+    validate.validateLink(msgs[0], msgs[1], (err, key) => {
+      t.error(err, "adjacent messages are linked");
+      t.equal(key, msgKeys[1], "key of the message");
+      validate.validateLink(msgs[0], msgs[2], (err) => {
+        t.equal(err.code, "INVALID_SEQUENCE", "a gap is rejected");
+        t.equal(err.offendingMessage, msgs[2], "offending message");
+        t.end();
+      });
+    });
+  });
+});
+
+test("null, undefined and none all mean no hmac key", (t) => {
+  const [msg] = createFeed(ssbKeys.generate(), [1000]);
+  const keys = [hmacKey2, undefined, null, "none"];
+  validate.verifySingleMultiKey(msg, keys, (err, index) => {
+    t.error(err);
+    t.equal(index, 1, "undefined is keyless verification");
+    validate.verifySingleMultiKey(msg, keys.slice(2), (err, index) => {
+      t.error(err);
+      t.equal(index, 0, "null is keyless verification");
+      validate.verifySingleMultiKey(msg, keys.slice(3), (err, index) => {
+        t.error(err);
+        t.equal(index, 0, "none is still accepted");
+        t.end();
+      });
+    });
+  });
+});
+
+test("partial verification returns the keys of verified messages", (t) => {
+  const msgs = createFeed(ssbKeys.generate(), [1000, 2000, 3000]);
+  validate.getMessageKeys(msgs, (err, msgKeys) => {
+    t.error(err);
+    msgs[1] = Object.assign({}, msgs[1], { timestamp: 2001 });
+    validate.verifySignaturesPartial(null, msgs, (err, results) => {
+      t.error(err);
+      t.deepEqual(
+        results.map((result) => result.key),
+        [msgKeys[0], undefined, msgKeys[2]],
+        "keys of the verified messages only"
+      );
+      t.end();
+    });
+  });
+});
+
+test("a Validator chains pushed batches in rustland", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000, 5000, 6000]);
+  const validator = new validate.Validator(null);
+  validator.pushBatch(msgs.slice(0, 2), (err) => {
+    t.error(err);
+    t.deepEqual(validator.tip(keys.id), msgs[1], "tip is cached");
+    validator.pushBatch(msgs.slice(2, 3), (err, res) => {
+      t.error(err, "chained onto the native tip");
+      t.equal(res.length, 1);
+      validator.validateBatch([msgs[3]], (err) => {
+        t.error(err, "validateBatch continues from the pushed tip");
+        validator.pushBatch([msgs[4]], (err) => {
+          t.error(err, "pushBatch continues from the validated tip");
+          validator.pushBatch([msgs[4]], (err) => {
+            t.equal(err.code, "INVALID_SEQUENCE", "a replayed batch fails");
+            validator.reset();
+            validator.pushBatch([msgs[5]], (err) => {
+              t.equal(err.code, "INVALID_FIRST_SEQUENCE", "tips are reset");
+              validator.close();
+              t.end();
+            });
+          });
+        });
+      });
+    });
+  });
+});
+
+test("a Validator keeps the highest pushed sequence as the tip", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000]);
+  const validator = new validate.Validator(null, { sortBySequence: true });
+  validator.pushBatch([msgs[2], msgs[0], msgs[1]], (err) => {
+    t.error(err, "a reordered batch is valid");
+    validator.pushBatch([msgs[3]], (err, res) => {
+      t.error(err, "chained onto the highest sequence of the reordered batch");
+      t.equal(res.length, 1);
+      validator.close();
+      t.end();
+    });
+  });
+});
+
+test("a Validator chains pushed batches onto a previous link", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000, 5000]);
+  const link = {
+    previousKey: validate.computeMessageKey(msgs[0]),
+    previousSequence: 1,
+  };
+  const validator = new validate.Validator(null);
+  validator.pushBatch(msgs.slice(1, 3), link, (err) => {
+    t.error(err, "the first batch is chained onto the link");
+    t.deepEqual(validator.tip(keys.id), msgs[2], "tip is cached");
+    validator.pushBatch([msgs[3]], (err) => {
+      t.error(err, "the next batch is chained onto the tip");
+      validator.reset();
+      validator.pushBatch([msgs[4]], { chain: false }, (err) => {
+        t.error(err, "a batch is validated without its previous message");
+        t.equal(validator.tip(keys.id), null, "no tip without the chain");
+        validator.pushBatch([msgs[4]], (err) => {
+          t.equal(err.code, "INVALID_FIRST_SEQUENCE", "no tip was recorded");
+          validator.close();
+          t.end();
+        });
+      });
+    });
+  });
+});
+
+test("the hash of the content of each message is reported", (t) => {
+  const keys = ssbKeys.generate();
+  const content = { type: "post", text: "hello" };
+  const msgs = createFeed(keys, [1000, 2000], () => content);
+  const opts = { contentHashes: true };
+  validate.validateBatch(null, msgs, null, opts, (err, res, info) => {
+    t.error(err);
+    const hash = ssbKeys.hash(JSON.stringify(content));
+    t.deepEqual(
+      info.contentHashes,
+      [hash, hash],
+      "hash of the compact content encoding"
+    );
+    t.end();
+  });
+});
+
+test("the feed format of a message is detected", (t) => {
+  const [msg] = createFeed(ssbKeys.generate(), [1000]);
+  const id = msg.author.slice(1, -".ed25519".length);
+  const withAuthor = (author) => Object.assign({}, msg, { author });
+  t.equal(validate.detectFeedFormat(msg), "classic", "classic feed");
+  t.equal(
+    validate.detectFeedFormat(withAuthor(`@${id}.bbfeed-v1`)),
+    "bendybutt-v1",
+    "bendy butt feed"
+  );
+  t.equal(
+    validate.detectFeedFormat(withAuthor(`ssb:feed/gabbygrove-v1/${id}`)),
+    "gabbygrove-v1",
+    "gabby grove feed as a uri"
+  );
+  t.throws(
+    () => validate.detectFeedFormat(withAuthor(`@${id}.unknown`)),
+    /unrecognised feed format/,
+    "unknown format"
+  );
+  t.end();
+});
+
+test("a broken hash chain names the claimed and expected previous keys", (t) => {
+  const keys = ssbKeys.generate();
+  const feed = createFeed(keys, [1000, 2000]);
+  const fork = createFeed(keys, [1001, 2001]);
+  const claimed = fork[1].previous;
+  const expected = "%" + ssbKeys.hash(JSON.stringify(feed[0], null, 2));
+  validate.validateBatch(null, [feed[0], fork[1]], null, (err) => {
+    t.equal(err.code, "FORKED_FEED", "forked feed code");
+    t.ok(err.message.includes(`claimed previous: ${claimed}`), "claimed key");
+    t.ok(err.message.includes(`expected previous: ${expected}`), "expected");
+    validate.validateSingle(null, fork[1], feed[0], (err) => {
+      t.ok(err.message.includes(`claimed previous: ${claimed}`), "single");
+      const msgs = [feed[0], fork[1]];
+      validate.validateBatchVerbose(null, msgs, null, (err, res) => {
+        t.error(err);
+        const [{ reason }] = res.errors;
+        t.ok(reason.includes(`claimed previous: ${claimed}`), "verbose");
+        const link = { key: expected, sequence: 1 };
+        validate.validateBatch(null, [fork[1]], link, (err) => {
+          t.ok(err.message.includes(`claimed previous: ${claimed}`), "link");
+          t.end();
+        });
+      });
+    });
+  });
+});
+
+test("sequence gaps are reported instead of failing the batch", (t) => {
+  const keys = ssbKeys.generate();
+  const msgs = createFeed(keys, [1000, 2000, 3000, 4000, 5000]);
+  const partial = [msgs[0], msgs[1], msgs[3], msgs[4]];
+  validate.validateBatch(null, partial, null, (err) => {
+    t.equal(err.code, "INVALID_SEQUENCE", "gaps fail without the option");
+    const opts = { reportGaps: true };
+    validate.validateBatch(null, partial, null, opts, (err, res, info) => {
+      t.error(err);
+      t.equal(res.length, 2, "keys of the contiguous prefix");
+      t.deepEqual(info.gaps, [2], "index following the gap");
+      validate.validateBatch(null, msgs, null, opts, (err, res, info) => {
+        t.error(err);
+        t.equal(res.length, 5, "all keys without a gap");
+        t.deepEqual(info.gaps, [], "no gaps");
+        const negative = Object.assign(
+          { previousKey: "%" + ssbKeys.hash("x"), previousSequence: -1 },
+          opts
+        );
+        validate.validateBatch(null, msgs, null, negative, (err) => {
+          t.equal(err.code, "INVALID_OPTIONS", "negative previous sequence");
+          t.end();
+        });
+      });
+    });
+  });
+});
+
+test("a message is canonicalized to its signing encoding", (t) => {
+  const [msg] = createFeed(ssbKeys.generate(), [1000]);
+  const encoded = JSON.stringify(msg, null, 2);
+  const { value, signing } = validate.canonicalizeMessage(JSON.stringify(msg));
+  t.equal(value, encoded, "canonical encoding of the value");
+  const unsigned = Object.assign({}, msg);
+  delete unsigned.signature;
+  t.equal(signing, JSON.stringify(unsigned, null, 2), "signing encoding");
+  const [unicode] = createFeed(ssbKeys.generate(), [1000], () => ({
+    type: "post",
+    text: "grüße ✨",
+  }));
+  const canonical = validate.canonicalizeMessage(unicode).value;
+  t.equal(
+    "%" + ssbKeys.hash(Buffer.from(canonical, "binary")),
+    validate.computeMessageKey(unicode),
+    "the key is the hash of the binary encoding"
+  );
+  t.notEqual(
+    "%" + ssbKeys.hash(Buffer.from(canonical, "utf8")),
+    validate.computeMessageKey(unicode),
+    "not of the utf8 encoding"
+  );
+  t.throws(() => validate.canonicalizeMessage("{"), /invalid/, "bad json");
+  t.end();
+});
+
+test("messages are validated with an hmac key per message", (t) => {
+  const [msg] = createFeed(ssbKeys.generate(), [1000]);
+  const entries = [
+    { msg, hmacKey: null },
+    { msg: hmacMsg, hmacKey: hmacKey2 },
+    { msg: hmacMsg, hmacKey: null },
+    { msg, hmacKey: "not base64" },
+  ];
+  validate.validateBatchMixedHmac(entries, (err, res) => {
+    t.error(err);
+    t.deepEqual(
+      res.map((result) => result.valid),
+      [true, true, false, false],
+      "each message is checked with its own key"
+    );
+    t.equal(
+      res[1].key,
+      "%8RL6pJ+3zdcX4v9wv3inbWzlnQH7ZV4Hi0Nvzdfibu0=.sha256",
+      "key of the hmac message"
+    );
+    t.equal(res[2].code, "INVALID_SIGNATURE", "wrong key");
+    t.equal(res[3].code, "INVALID_HMAC_KEY", "invalid key");
+    t.end();
+  });
+});
+
+test("message buffers are reused across batches", (t) => {
+  const keys = ssbKeys.generate();
+  const long = createFeed(keys, [1000, 2000], (i) => ({
+    type: "test",
+    text: "x".repeat(2000 + i),
+  }));
+  const short = createFeed(ssbKeys.generate(), [1000, 2000]);
+  validate.validateBatch(null, long, null, (err, res) => {
+    t.error(err);
+    t.equal(res.length, 2, "long messages");
+    // the buffers of the long messages now hold the short messages
+    validate.validateBatch(null, short, null, (err, res) => {
+      t.error(err);
+      t.equal(res[0], validate.computeMessageKey(short[0]), "short messages");
+      t.throws(() => validate.setBufferPoolSize(-1), /non-negative/);
+      validate.setBufferPoolSize(0);
+      validate.validateBatch(null, short, null, (err) => {
+        t.error(err, "without reuse");
+        validate.setBufferPoolSize(4096);
+        t.end();
+      });
+    });
+  });
+});
+
+test("detached signatures are verified", (t) => {
+  const keys = ssbKeys.generate();
+  const payload = "handshake artefact";
+  const signature = ssbKeys.signObj(keys, null, { payload }).signature;
+  const signed = JSON.stringify({ payload }, null, 2);
+  t.true(validate.verifyDetached(keys.id, signature, signed), "valid");
+  t.true(
+    validate.verifyDetached(keys.public, signature, Buffer.from(signed)),
+    "valid with a buffer payload"
+  );
+  t.false(validate.verifyDetached(keys.id, signature, payload), "wrong data");
+  t.throws(
+    () => validate.verifyDetached("not a key", signature, signed),
+    /public key invalid/,
+    "malformed public key"
+  );
+  t.end();
+});
+
+// the thread pool can only be created once, so this test comes last
+test("limit the number of threads used for validation", (t) => {
+  t.throws(() => validate.setMaxThreads(0), /positive integer/, "zero");
+  validate.setMaxThreads(2);
+  t.equal(validate.recommendedBatchSize(), 500, "sized for two threads");
+  const msgs = createFeed(ssbKeys.generate(), [1000, 2000, 3000]);
+  validate.validateBatch(null, msgs, null, (err, keys) => {
+    t.error(err);
+    t.equal(keys.length, 3, "validated with the limited pool");
+    t.throws(() => validate.setMaxThreads(4), /already/, "set only once");
+    t.end();
+  });
+});